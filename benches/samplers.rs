@@ -0,0 +1,74 @@
+//! Convergence of each sampler: RMSE against a high sample count reference
+//! of `scenes/three_spheres.toml`, which has depth of field, as the number
+//! of samples per pixel grows. Run with `cargo bench --bench samplers`.
+
+extern crate rust_ray_tracing;
+
+use rust_ray_tracing::scene::{load_scene, RenderOverrides};
+use rust_ray_tracing::{render, BvhNode, Image, SamplerKind};
+use std::time::Instant;
+
+const WIDTH: usize = 96;
+const HEIGHT: usize = 54;
+const REFERENCE_SAMPLES: usize = 4096;
+const SAMPLE_COUNTS: [usize; 5] = [1, 4, 16, 64, 256];
+const KINDS: [SamplerKind; 5] = [
+    SamplerKind::Independent,
+    SamplerKind::Stratified,
+    SamplerKind::Halton,
+    SamplerKind::Sobol,
+    SamplerKind::BlueNoise,
+];
+
+fn render_with(sampler: SamplerKind, samples_per_pixel: usize, seed: u64) -> Image {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/three_spheres.toml");
+    let overrides = RenderOverrides {
+        width: Some(WIDTH),
+        height: Some(HEIGHT),
+        samples_per_pixel: Some(samples_per_pixel),
+        pass_samples: Some(samples_per_pixel),
+        seed: Some(seed),
+        sampler: Some(sampler),
+        ..RenderOverrides::default()
+    };
+    let scene = load_scene(path, &overrides).expect("could not load the scene");
+    let world = BvhNode::new(scene.world);
+    render(&world, &scene.lights, &scene.camera, &scene.settings)
+}
+
+fn rmse(image: &Image, reference: &Image) -> f32 {
+    let squared: f32 = image
+        .pixels()
+        .iter()
+        .zip(reference.pixels())
+        .map(|(a, b)| (*a - *b).squared_length())
+        .sum();
+    (squared / (3 * image.pixels().len()) as f32).sqrt()
+}
+
+fn main() {
+    let start = Instant::now();
+    // a different seed from the renders measured, so that errors are independent
+    let reference = render_with(SamplerKind::Sobol, REFERENCE_SAMPLES, 1);
+    println!(
+        "reference: {} spp in {:.1}s",
+        REFERENCE_SAMPLES,
+        start.elapsed().as_secs_f32()
+    );
+
+    print!("{:>5}", "spp");
+    for kind in &KINDS {
+        print!(" {:>12}", kind.to_string());
+    }
+    println!();
+    for &samples in &SAMPLE_COUNTS {
+        print!("{:>5}", samples);
+        for &kind in &KINDS {
+            print!(
+                " {:>12.5}",
+                rmse(&render_with(kind, samples, 2), &reference)
+            );
+        }
+        println!();
+    }
+}
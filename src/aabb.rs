@@ -0,0 +1,97 @@
+use super::ray::Ray;
+use super::vec3::Vec3;
+
+/// Axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+  pub min: Vec3,
+  pub max: Vec3,
+}
+
+impl Aabb {
+  pub fn new(min: Vec3, max: Vec3) -> Self {
+    Aabb { min, max }
+  }
+
+  /// Smallest box containing both `a` and `b`.
+  pub fn surrounding(a: &Aabb, b: &Aabb) -> Aabb {
+    Aabb {
+      min: Vec3(
+        a.min.x().min(b.min.x()),
+        a.min.y().min(b.min.y()),
+        a.min.z().min(b.min.z()),
+      ),
+      max: Vec3(
+        a.max.x().max(b.max.x()),
+        a.max.y().max(b.max.y()),
+        a.max.z().max(b.max.z()),
+      ),
+    }
+  }
+
+  pub fn centroid(&self) -> Vec3 {
+    0.5 * (self.min + self.max)
+  }
+
+  pub fn surface_area(&self) -> f32 {
+    let d = self.max - self.min;
+    2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+  }
+
+  /// Slab test: does `r` pass through the box somewhere in (t_min, t_max)?
+  pub fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> bool {
+    let mut t_min = t_min;
+    let mut t_max = t_max;
+    for axis in 0..3 {
+      let inv_d = 1.0 / axis_of(r.direction, axis);
+      let mut t0 = (axis_of(self.min, axis) - axis_of(r.origin, axis)) * inv_d;
+      let mut t1 = (axis_of(self.max, axis) - axis_of(r.origin, axis)) * inv_d;
+      if inv_d < 0.0 {
+        std::mem::swap(&mut t0, &mut t1);
+      }
+      // written so that a NaN from 0 * inf keeps the old bound
+      t_min = if t0 > t_min { t0 } else { t_min };
+      t_max = if t1 < t_max { t1 } else { t_max };
+      if t_max < t_min {
+        return false;
+      }
+    }
+    true
+  }
+}
+
+/// Component `axis` (0 = x, 1 = y, 2 = z) of `v`.
+pub fn axis_of(v: Vec3, axis: usize) -> f32 {
+  match axis {
+    0 => v.x(),
+    1 => v.y(),
+    _ => v.z(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn surrounding_box() {
+    let a = Aabb::new(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 2.0, 1.0));
+    let b = Aabb::new(Vec3(-1.0, 1.0, 0.5), Vec3(0.5, 3.0, 0.5));
+    let c = Aabb::surrounding(&a, &b);
+    assert_eq!(Vec3(-1.0, 0.0, 0.0), c.min);
+    assert_eq!(Vec3(1.0, 3.0, 1.0), c.max);
+    assert_eq!(22.0, c.surface_area());
+  }
+
+  #[test]
+  fn box_hit() {
+    let aabb = Aabb::new(Vec3(1.0, -1.0, -1.0), Vec3(2.0, 1.0, 1.0));
+    let r = Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0));
+    assert!(aabb.hit(&r, 0.0001, f32::MAX));
+    assert!(!aabb.hit(&r, 0.0001, 0.5));
+    let r = Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(-1.0, 0.0, 0.0));
+    assert!(!aabb.hit(&r, 0.0001, f32::MAX));
+    let r = Ray::new(Vec3(0.0, 2.0, 0.0), Vec3(1.0, 0.0, 0.0));
+    assert!(!aabb.hit(&r, 0.0001, f32::MAX));
+  }
+}
@@ -0,0 +1,168 @@
+use super::hittable::HitRecord;
+use super::material::{Material, Scatter};
+use super::progress::luminance;
+use super::ray::Ray;
+use super::sampler::Sampler;
+use super::texture::Texture;
+use super::vec3::Vec3;
+use std::sync::Arc;
+
+/// Step in u and v over which bump maps are differentiated.
+const DELTA: f32 = 1e-3;
+
+/// Where the perturbed shading normals come from.
+pub enum BumpMap {
+  /// Tangent space normals stored as colors, each channel mapping [0, 1] to
+  /// [-1, 1]: red along u, green along v and blue out of the surface.
+  Normal(Arc<dyn Texture>),
+  /// Heights above the surface, the texture's luminance times `scale`.
+  Height {
+    texture: Arc<dyn Texture>,
+    scale: f32,
+  },
+}
+
+impl BumpMap {
+  /// Shading normal out of the surface at `rec`, as the map perturbs it.
+  pub fn normal(&self, rec: &HitRecord) -> Vec3 {
+    let n = rec.outward_normal();
+    match self {
+      BumpMap::Normal(texture) => {
+        let c = 2.0 * texture.value(rec.u, rec.v, rec.p) - Vec3(1.0, 1.0, 1.0);
+        // tangents made orthonormal to the normal, keeping their handedness
+        let t = (rec.dpdu - n.dot(rec.dpdu) * n).to_unit_vector();
+        let b = n.cross(t);
+        let b = if b.dot(rec.dpdv) < 0.0 { -b } else { b };
+        let perturbed = c.x() * t + c.y() * b + c.z() * n;
+        if perturbed.squared_length() > 0.0 {
+          perturbed.to_unit_vector()
+        } else {
+          n
+        }
+      }
+      BumpMap::Height { texture, scale } => {
+        let height = |u: f32, v: f32, p: Vec3| scale * luminance(texture.value(u, v, p));
+        let h = height(rec.u, rec.v, rec.p);
+        let dhdu = (height(rec.u + DELTA, rec.v, rec.p + DELTA * rec.dpdu) - h) / DELTA;
+        let dhdv = (height(rec.u, rec.v + DELTA, rec.p + DELTA * rec.dpdv) - h) / DELTA;
+        // the surface displaced by h along n, ignoring how n itself turns
+        let perturbed = (rec.dpdu + dhdu * n).cross(rec.dpdv + dhdv * n);
+        if perturbed.squared_length() == 0.0 {
+          n
+        } else if perturbed.dot(n) < 0.0 {
+          -perturbed.to_unit_vector()
+        } else {
+          perturbed.to_unit_vector()
+        }
+      }
+    }
+  }
+}
+
+/// `material` with its shading normals perturbed by `map` before it
+/// scatters light, so the surface looks rough without changing its shape.
+pub struct Bumped {
+  pub material: Box<dyn Material>,
+  pub map: BumpMap,
+}
+
+impl Bumped {
+  pub fn new(material: Box<dyn Material>, map: BumpMap) -> Self {
+    Bumped { material, map }
+  }
+
+  fn perturbed<'a>(&self, rec: &HitRecord<'a>) -> HitRecord<'a> {
+    rec.with_shading_normal(self.map.normal(rec))
+  }
+}
+
+impl Material for Bumped {
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+    self.material.scatter(r_in, &self.perturbed(rec), sampler)
+  }
+
+  fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Vec3 {
+    self.material.emitted(r_in, rec)
+  }
+
+  fn is_emissive(&self) -> bool {
+    self.material.is_emissive()
+  }
+
+  fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+    self.material.eval(r_in, &self.perturbed(rec), direction)
+  }
+
+  fn pdf(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> f32 {
+    self.material.pdf(r_in, &self.perturbed(rec), direction)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::material::Lambertian;
+  use super::super::texture::SolidColor;
+  use super::*;
+
+  /// Hit from above on the plane z = 0 at (u, v) = (x, y).
+  fn flat_hit(material: &dyn Material, x: f32, y: f32) -> HitRecord<'_> {
+    let r = Ray::new(Vec3(x, y, 1.0), Vec3(0.0, 0.0, -1.0));
+    HitRecord::new(&r, 1.0, Vec3(0.0, 0.0, 1.0), material).with_uv(
+      x,
+      y,
+      Vec3(1.0, 0.0, 0.0),
+      Vec3(0.0, 1.0, 0.0),
+    )
+  }
+
+  /// Height of x in texture space, so a slope of 1 along u.
+  struct Ramp;
+
+  impl Texture for Ramp {
+    fn value(&self, u: f32, _v: f32, _p: Vec3) -> Vec3 {
+      Vec3(u, u, u)
+    }
+  }
+
+  #[test]
+  fn normal_maps_turn_normals_in_tangent_space() {
+    let material = Lambertian::new(Vec3(0.5, 0.5, 0.5));
+    let rec = flat_hit(&material, 0.2, 0.3);
+    // the flat normal map color leaves the normal alone
+    let flat = BumpMap::Normal(Arc::new(SolidColor::new(Vec3(0.5, 0.5, 1.0))));
+    assert_eq!(Vec3(0.0, 0.0, 1.0), flat.normal(&rec));
+    let tilted = BumpMap::Normal(Arc::new(SolidColor::new(Vec3(1.0, 0.5, 1.0))));
+    let expected = Vec3(1.0, 0.0, 1.0).to_unit_vector();
+    assert!((expected - tilted.normal(&rec)).length() < 1e-5);
+    // and stays on its side when the surface is hit from behind
+    let r = Ray::new(Vec3(0.2, 0.3, -1.0), Vec3(0.0, 0.0, 1.0));
+    let below = HitRecord::new(&r, 1.0, Vec3(0.0, 0.0, 1.0), &material).with_uv(
+      0.2,
+      0.3,
+      Vec3(1.0, 0.0, 0.0),
+      Vec3(0.0, 1.0, 0.0),
+    );
+    let bumped = Bumped::new(Box::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))), tilted);
+    let perturbed = bumped.perturbed(&below);
+    assert!((-expected - perturbed.normal).length() < 1e-5);
+    assert_eq!(Vec3(0.0, 0.0, -1.0), perturbed.geometric_normal);
+  }
+
+  #[test]
+  fn height_maps_tilt_normals_down_the_slope() {
+    let material = Lambertian::new(Vec3(0.5, 0.5, 0.5));
+    let rec = flat_hit(&material, 0.2, 0.3);
+    let constant = BumpMap::Height {
+      texture: Arc::new(SolidColor::new(Vec3(0.7, 0.7, 0.7))),
+      scale: 1.0,
+    };
+    assert_eq!(Vec3(0.0, 0.0, 1.0), constant.normal(&rec));
+    // heights rising by `scale` per unit of u lean the normal back along -u
+    let ramp = BumpMap::Height {
+      texture: Arc::new(Ramp),
+      scale: 0.5,
+    };
+    let expected = Vec3(-0.5, 0.0, 1.0).to_unit_vector();
+    assert!((expected - ramp.normal(&rec)).length() < 1e-3);
+  }
+}
@@ -0,0 +1,294 @@
+use super::aabb::{axis_of, Aabb};
+use super::hittable::{HitRecord, Hittable};
+use super::ray::Ray;
+use super::sampler::Sampler;
+
+/// Number of centroid buckets evaluated per split by the surface area heuristic.
+const SAH_BUCKETS: usize = 12;
+/// Nodes with at most this many objects may become leaves.
+const MAX_LEAF_SIZE: usize = 4;
+/// Cost of visiting a node relative to one object intersection.
+const TRAVERSAL_COST: f32 = 0.125;
+
+/// Bounding volume hierarchy over a set of objects, built with surface area
+/// heuristic splits. A drop-in replacement for `Vec<Box<dyn Hittable>>`.
+pub struct BvhNode {
+  bbox: Option<Aabb>,
+  contents: BvhContents,
+}
+
+enum BvhContents {
+  Leaf(Vec<Box<dyn Hittable>>),
+  Split(Box<BvhNode>, Box<BvhNode>),
+}
+
+impl BvhNode {
+  pub fn new(objects: Vec<Box<dyn Hittable>>) -> Self {
+    // objects without a bounding box (e.g. infinite planes) sit in a leaf
+    // that is always tested, next to the tree of bounded ones
+    let (bounded, unbounded): (Vec<_>, Vec<_>) = objects
+      .into_iter()
+      .partition(|o| o.bounding_box().is_some());
+    let tree = BvhNode::build(
+      bounded
+        .into_iter()
+        .map(|o| (o.bounding_box().unwrap(), o))
+        .collect(),
+    );
+    if unbounded.is_empty() {
+      tree
+    } else {
+      BvhNode {
+        bbox: None,
+        contents: BvhContents::Split(
+          Box::new(tree),
+          Box::new(BvhNode {
+            bbox: None,
+            contents: BvhContents::Leaf(unbounded),
+          }),
+        ),
+      }
+    }
+  }
+
+  fn build(objects: Vec<(Aabb, Box<dyn Hittable>)>) -> Self {
+    let bbox = objects
+      .iter()
+      .map(|(b, _)| *b)
+      .reduce(|a, b| Aabb::surrounding(&a, &b));
+    let bbox = match bbox {
+      Some(bbox) => bbox,
+      None => return BvhNode::leaf(None, objects),
+    };
+    if objects.len() == 1 {
+      return BvhNode::leaf(Some(bbox), objects);
+    }
+
+    let centroids = objects
+      .iter()
+      .map(|(b, _)| b.centroid())
+      .fold(None, |acc: Option<Aabb>, c| {
+        let point = Aabb::new(c, c);
+        Some(acc.map_or(point, |acc| Aabb::surrounding(&acc, &point)))
+      })
+      .unwrap();
+    let extent = centroids.max - centroids.min;
+    let axis = if extent.x() >= extent.y() && extent.x() >= extent.z() {
+      0
+    } else if extent.y() >= extent.z() {
+      1
+    } else {
+      2
+    };
+    let lo = axis_of(centroids.min, axis);
+    let width = axis_of(extent, axis);
+    if width <= 0.0 {
+      // every centroid coincides, no split can separate them
+      if objects.len() <= MAX_LEAF_SIZE {
+        return BvhNode::leaf(Some(bbox), objects);
+      }
+      let mut left = objects;
+      let right = left.split_off(left.len() / 2);
+      return BvhNode::split(bbox, left, right);
+    }
+    let bucket_of = |b: &Aabb| {
+      let offset = (axis_of(b.centroid(), axis) - lo) / width;
+      ((offset * SAH_BUCKETS as f32) as usize).min(SAH_BUCKETS - 1)
+    };
+
+    let mut counts = [0usize; SAH_BUCKETS];
+    let mut bounds: [Option<Aabb>; SAH_BUCKETS] = [None; SAH_BUCKETS];
+    for (b, _) in &objects {
+      let i = bucket_of(b);
+      counts[i] += 1;
+      bounds[i] = Some(bounds[i].map_or(*b, |acc| Aabb::surrounding(&acc, b)));
+    }
+
+    // cost of splitting after each bucket, sweeping boxes in from both ends
+    let area = |b: Option<Aabb>| b.map_or(0.0, |b| b.surface_area());
+    let mut below = [(0usize, None); SAH_BUCKETS - 1];
+    let (mut count, mut acc) = (0, None);
+    for i in 0..SAH_BUCKETS - 1 {
+      count += counts[i];
+      acc = union(acc, bounds[i]);
+      below[i] = (count, acc);
+    }
+    let (mut count, mut acc) = (0, None);
+    let mut best = (f32::MAX, 0);
+    for i in (0..SAH_BUCKETS - 1).rev() {
+      count += counts[i + 1];
+      acc = union(acc, bounds[i + 1]);
+      let (below_count, below_box) = below[i];
+      let cost = TRAVERSAL_COST
+        + (area(below_box) * below_count as f32 + area(acc) * count as f32) / bbox.surface_area();
+      if below_count > 0 && count > 0 && cost < best.0 {
+        best = (cost, i);
+      }
+    }
+
+    let leaf_cost = objects.len() as f32;
+    if objects.len() <= MAX_LEAF_SIZE && best.0 >= leaf_cost {
+      return BvhNode::leaf(Some(bbox), objects);
+    }
+    let (left, right): (Vec<_>, Vec<_>) = objects
+      .into_iter()
+      .partition(|(b, _)| bucket_of(b) <= best.1);
+    BvhNode::split(bbox, left, right)
+  }
+
+  fn leaf(bbox: Option<Aabb>, objects: Vec<(Aabb, Box<dyn Hittable>)>) -> Self {
+    BvhNode {
+      bbox,
+      contents: BvhContents::Leaf(objects.into_iter().map(|(_, o)| o).collect()),
+    }
+  }
+
+  fn split(
+    bbox: Aabb,
+    left: Vec<(Aabb, Box<dyn Hittable>)>,
+    right: Vec<(Aabb, Box<dyn Hittable>)>,
+  ) -> Self {
+    BvhNode {
+      bbox: Some(bbox),
+      contents: BvhContents::Split(
+        Box::new(BvhNode::build(left)),
+        Box::new(BvhNode::build(right)),
+      ),
+    }
+  }
+}
+
+fn union(a: Option<Aabb>, b: Option<Aabb>) -> Option<Aabb> {
+  match (a, b) {
+    (Some(a), Some(b)) => Some(Aabb::surrounding(&a, &b)),
+    (a, None) => a,
+    (None, b) => b,
+  }
+}
+
+impl Hittable for BvhNode {
+  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+    if let Some(bbox) = self.bbox {
+      if !bbox.hit(r, t_min, t_max) {
+        return None;
+      }
+    }
+    match &self.contents {
+      BvhContents::Leaf(objects) => objects.hit(r, t_min, t_max),
+      BvhContents::Split(left, right) => {
+        let left_hit = left.hit(r, t_min, t_max);
+        let right_hit = right.hit(r, t_min, left_hit.map_or(t_max, |h| h.t));
+        right_hit.or(left_hit)
+      }
+    }
+  }
+
+  fn hit_sampled(
+    &self,
+    r: &Ray,
+    t_min: f32,
+    t_max: f32,
+    sampler: &mut dyn Sampler,
+  ) -> Option<HitRecord<'_>> {
+    if let Some(bbox) = self.bbox {
+      if !bbox.hit(r, t_min, t_max) {
+        return None;
+      }
+    }
+    match &self.contents {
+      BvhContents::Leaf(objects) => objects.hit_sampled(r, t_min, t_max, sampler),
+      BvhContents::Split(left, right) => {
+        let left_hit = left.hit_sampled(r, t_min, t_max, sampler);
+        let right_hit = right.hit_sampled(r, t_min, left_hit.map_or(t_max, |h| h.t), sampler);
+        right_hit.or(left_hit)
+      }
+    }
+  }
+
+  fn bounding_box(&self) -> Option<Aabb> {
+    self.bbox
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  extern crate rand;
+  use super::super::material::{Lambertian, Material};
+  use super::super::sphere::Sphere;
+  use super::super::vec3::Vec3;
+  use super::*;
+  use rand::rngs::StdRng;
+  use rand::{Rng, SeedableRng};
+
+  fn random_spheres(rng: &mut StdRng, n: usize) -> Vec<Box<dyn Hittable>> {
+    (0..n)
+      .map(|_| {
+        Box::new(Sphere::new(
+          Vec3(
+            rng.gen_range(-20.0, 20.0),
+            rng.gen_range(-20.0, 20.0),
+            rng.gen_range(-20.0, 20.0),
+          ),
+          rng.gen_range(0.05, 2.0),
+          Box::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+        )) as Box<dyn Hittable>
+      })
+      .collect()
+  }
+
+  fn material_ptr(m: &dyn Material) -> *const () {
+    m as *const dyn Material as *const ()
+  }
+
+  #[test]
+  fn same_nearest_hit_as_list() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let list = random_spheres(&mut rng, 500);
+    let rays: Vec<Ray> = (0..2000)
+      .map(|_| {
+        let origin = Vec3(
+          rng.gen_range(-30.0, 30.0),
+          rng.gen_range(-30.0, 30.0),
+          rng.gen_range(-30.0, 30.0),
+        );
+        let target = Vec3(
+          rng.gen_range(-20.0, 20.0),
+          rng.gen_range(-20.0, 20.0),
+          rng.gen_range(-20.0, 20.0),
+        );
+        Ray::new(origin, target - origin)
+      })
+      .collect();
+    let expected: Vec<_> = rays
+      .iter()
+      .map(|r| {
+        list
+          .hit(r, 0.001, f32::MAX)
+          .map(|h| (h.t, h.p, h.normal, material_ptr(h.material)))
+      })
+      .collect();
+    // the boxed materials do not move when the list is handed to the tree
+    let bvh = BvhNode::new(list);
+    for (r, expected) in rays.iter().zip(expected) {
+      let actual = bvh
+        .hit(r, 0.001, f32::MAX)
+        .map(|h| (h.t, h.p, h.normal, material_ptr(h.material)));
+      assert_eq!(expected, actual);
+    }
+  }
+
+  #[test]
+  fn bounding_box_of_tree() {
+    let list = random_spheres(&mut StdRng::seed_from_u64(3), 50);
+    let expected = list.bounding_box();
+    assert_eq!(expected, BvhNode::new(list).bounding_box());
+  }
+
+  #[test]
+  fn empty_tree_misses() {
+    let bvh = BvhNode::new(Vec::new());
+    let r = Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0));
+    assert!(bvh.hit(&r, 0.001, f32::MAX).is_none());
+    assert!(bvh.bounding_box().is_none());
+  }
+}
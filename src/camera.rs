@@ -1,4 +1,5 @@
 use super::ray::Ray;
+use super::sampler::Sampler;
 use super::vec3::{random_in_unit_disk, Vec3};
 use std::f32;
 
@@ -10,8 +11,10 @@ pub struct Camera {
   vertical: Vec3,
   u: Vec3,
   v: Vec3,
-  w: Vec3,
   lens_radius: f32,
+  /// Shutter interval, rays get random times within it.
+  time_open: f32,
+  time_close: f32,
 }
 
 impl Camera {
@@ -40,17 +43,36 @@ impl Camera {
       vertical: 2.0 * half_height * focus_dist * v,
       u,
       v,
-      w,
       lens_radius: aperture * 0.5,
+      time_open: 0.0,
+      time_close: 0.0,
     }
   }
 
-  pub fn get_ray(&self, s: f32, t: f32) -> Ray {
-    let rd = self.lens_radius * random_in_unit_disk();
+  /// Keeps the shutter open from `time_open` to `time_close`, so that objects
+  /// moving in between are blurred.
+  pub fn with_shutter(self, time_open: f32, time_close: f32) -> Self {
+    Camera {
+      time_open,
+      time_close,
+      ..self
+    }
+  }
+
+  /// Ray through the point (`s`, `t`) of the image plane, both in [0, 1] from
+  /// the bottom left corner.
+  pub fn get_ray(&self, s: f32, t: f32, sampler: &mut dyn Sampler) -> Ray {
+    let rd = self.lens_radius * random_in_unit_disk(sampler);
     let offset = self.u * rd.x() + self.v * rd.y();
-    Ray::new(
+    let time = if self.time_close > self.time_open {
+      self.time_open + sampler.next_1d() * (self.time_close - self.time_open)
+    } else {
+      self.time_open
+    };
+    Ray::new_at_time(
       self.origin + offset,
       self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
+      time,
     )
   }
 }
@@ -0,0 +1,266 @@
+//! Saving and restoring a progressive render between passes.
+//!
+//! The random numbers of a sample are derived from the seed, the pixel and
+//! the sample's index alone (see `render_progressive`), so the statistics of
+//! every pixel, its film and the number of passes made are all that is
+//! needed to carry on exactly as an uninterrupted render would.
+
+use super::film::FilmPixel;
+use super::progress::{PixelStats, Progress};
+use super::render::RenderSettings;
+use super::sampler::SamplerKind;
+use super::vec3::Vec3;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"RTCKPT02";
+
+/// Hash of what a checkpoint's samples depend on: the scene's description
+/// and the settings other than the sample budget, thread count and time
+/// limit, which may differ between runs, as may the tone mapping. Stratified
+/// samples depend on the budget too.
+pub fn fingerprint(settings: &RenderSettings, scene: &[u8]) -> u64 {
+  let relevant = format!(
+    "{} {} {} {} {:?} {:?} {:?} {} {}{}",
+    settings.width,
+    settings.height,
+    settings.pass_samples,
+    settings.max_depth,
+    settings.background,
+    settings.integrator,
+    settings.filter,
+    settings.seed,
+    settings.sampler,
+    match settings.sampler {
+      SamplerKind::Stratified => format!(" {}", settings.samples_per_pixel),
+      _ => String::new(),
+    },
+  );
+  // FNV-1a, stable across runs and platforms unlike `DefaultHasher`
+  scene
+    .iter()
+    .chain(relevant.as_bytes())
+    .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+      (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Writes `progress` to `path`, through a temporary file so that a crash
+/// while saving leaves the previous checkpoint intact.
+pub fn save<P: AsRef<Path>>(progress: &Progress, fingerprint: u64, path: P) -> io::Result<()> {
+  let path = path.as_ref();
+  let mut temporary = path.as_os_str().to_owned();
+  temporary.push(".tmp");
+  {
+    let mut w = BufWriter::new(File::create(&temporary)?);
+    write(progress, fingerprint, &mut w)?;
+    w.flush()?;
+    w.get_ref().sync_all()?;
+  }
+  fs::rename(&temporary, path)
+}
+
+/// Reads the checkpoint at `path`, which must have been saved with the same
+/// `fingerprint`.
+pub fn load<P: AsRef<Path>>(path: P, fingerprint: u64) -> io::Result<Progress> {
+  read(&mut BufReader::new(File::open(path)?), fingerprint)
+}
+
+pub fn write<W: Write>(progress: &Progress, fingerprint: u64, w: &mut W) -> io::Result<()> {
+  w.write_all(MAGIC)?;
+  for value in &[
+    fingerprint,
+    progress.width as u64,
+    progress.height as u64,
+    progress.passes as u64,
+  ] {
+    w.write_all(&value.to_le_bytes())?;
+  }
+  for y in 0..progress.height {
+    for x in 0..progress.width {
+      let p = progress.pixel(x, y);
+      w.write_all(&p.samples.to_le_bytes())?;
+      for value in &[p.sum.r(), p.sum.g(), p.sum.b(), p.mean_luminance, p.m2] {
+        w.write_all(&value.to_le_bytes())?;
+      }
+    }
+  }
+  for y in 0..progress.height {
+    for x in 0..progress.width {
+      let p = progress.film().pixel(x, y);
+      let sum = p.weighted_sum;
+      for value in &[sum.r(), sum.g(), sum.b(), p.weight] {
+        w.write_all(&value.to_le_bytes())?;
+      }
+    }
+  }
+  Ok(())
+}
+
+pub fn read<R: Read>(r: &mut R, fingerprint: u64) -> io::Result<Progress> {
+  let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+  let mut magic = [0; 8];
+  r.read_exact(&mut magic)?;
+  if &magic != MAGIC {
+    return Err(invalid("not a checkpoint file"));
+  }
+  let u64_le = |r: &mut R| -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+  };
+  if u64_le(r)? != fingerprint {
+    return Err(invalid(
+      "checkpoint was saved for another scene or other render settings",
+    ));
+  }
+  let width = u64_le(r)? as usize;
+  let height = u64_le(r)? as usize;
+  let passes = u64_le(r)? as usize;
+  let mut progress = Progress::new(width, height);
+  progress.passes = passes;
+  let f32_le = |r: &mut R| -> io::Result<f32> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+  };
+  for y in 0..height {
+    for x in 0..width {
+      let mut samples = [0; 4];
+      r.read_exact(&mut samples)?;
+      *progress.pixel_mut(x, y) = PixelStats {
+        samples: u32::from_le_bytes(samples),
+        sum: Vec3(f32_le(r)?, f32_le(r)?, f32_le(r)?),
+        mean_luminance: f32_le(r)?,
+        m2: f32_le(r)?,
+      };
+    }
+  }
+  for y in 0..height {
+    for x in 0..width {
+      *progress.film_mut().pixel_mut(x, y) = FilmPixel {
+        weighted_sum: Vec3(f32_le(r)?, f32_le(r)?, f32_le(r)?),
+        weight: f32_le(r)?,
+      };
+    }
+  }
+  Ok(progress)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::camera::Camera;
+  use super::super::filter::{Filter, FilterKind};
+  use super::super::hittable::Hittable;
+  use super::super::material::{Dielectric, Lambertian};
+  use super::super::render::render_progressive;
+  use super::super::sphere::Sphere;
+  use super::*;
+
+  #[test]
+  fn resumed_render_is_identical() {
+    let world: Vec<Box<dyn Hittable>> = vec![
+      Box::new(Sphere::new(
+        Vec3(0.0, -100.5, -1.0),
+        100.0,
+        Box::new(Lambertian::new(Vec3(0.8, 0.8, 0.0))),
+      )),
+      Box::new(Sphere::new(
+        Vec3(0.0, 0.0, -1.0),
+        0.5,
+        Box::new(Dielectric::new(1.5)),
+      )),
+    ];
+    let lights: Vec<Box<dyn Hittable>> = Vec::new();
+    let camera = Camera::new(
+      Vec3(0.0, 0.0, 1.0),
+      Vec3(0.0, 0.0, -1.0),
+      Vec3(0.0, 1.0, 0.0),
+      60.0,
+      1.5,
+      0.0,
+      2.0,
+    );
+    let settings = RenderSettings {
+      width: 24,
+      height: 16,
+      samples_per_pixel: 12,
+      pass_samples: 4,
+      threads: 2,
+      seed: 11,
+      ..RenderSettings::default()
+    };
+    let mut uninterrupted = Progress::new(24, 16);
+    render_progressive(
+      &world,
+      &lights,
+      &camera,
+      &settings,
+      &mut uninterrupted,
+      |_| {},
+    );
+
+    // stop after the first pass, save, and carry on from the saved copy
+    let first_pass = RenderSettings {
+      samples_per_pixel: 4,
+      ..settings.clone()
+    };
+    let mut interrupted = Progress::new(24, 16);
+    render_progressive(
+      &world,
+      &lights,
+      &camera,
+      &first_pass,
+      &mut interrupted,
+      |_| {},
+    );
+    let fingerprint = fingerprint(&settings, b"scene");
+    assert_eq!(fingerprint, super::fingerprint(&first_pass, b"scene"));
+    let mut saved = Vec::new();
+    write(&interrupted, fingerprint, &mut saved).unwrap();
+    let mut resumed = read(&mut &saved[..], fingerprint).unwrap();
+    assert_eq!(interrupted, resumed);
+    render_progressive(&world, &lights, &camera, &settings, &mut resumed, |_| {});
+
+    assert_eq!(3, resumed.passes);
+    assert_eq!(uninterrupted, resumed);
+    assert_eq!(uninterrupted.image(), resumed.image());
+  }
+
+  #[test]
+  fn rejects_other_settings() {
+    let settings = RenderSettings::default();
+    let progress = Progress::new(2, 2);
+    let mut saved = Vec::new();
+    write(&progress, fingerprint(&settings, b"a"), &mut saved).unwrap();
+    assert!(read(&mut &saved[..], fingerprint(&settings, b"b")).is_err());
+    let other_seed = RenderSettings {
+      seed: 1,
+      ..settings.clone()
+    };
+    assert_ne!(fingerprint(&settings, b"a"), fingerprint(&other_seed, b"a"));
+    assert!(read(&mut &b"RTCKPT00"[..], 0).is_err());
+    let mitchell = RenderSettings {
+      filter: Filter::new(FilterKind::Mitchell),
+      ..settings.clone()
+    };
+    assert_ne!(fingerprint(&settings, b"a"), fingerprint(&mitchell, b"a"));
+  }
+
+  #[test]
+  fn save_and_load_file() {
+    let path = std::env::temp_dir().join(format!(
+      "rust_ray_tracing_checkpoint_test_{}.ckpt",
+      std::process::id()
+    ));
+    let mut progress = Progress::new(3, 2);
+    progress.pixel_mut(2, 1).add(Vec3(0.5, 1.0, 2.0));
+    progress
+      .film_mut()
+      .add_sample(&Filter::default(), 2.5, 1.5, Vec3(0.5, 1.0, 2.0));
+    progress.passes = 1;
+    save(&progress, 42, &path).unwrap();
+    assert_eq!(progress, load(&path, 42).unwrap());
+  }
+}
@@ -0,0 +1,172 @@
+use super::aabb::Aabb;
+use super::cylinder::{around_y, around_y_tangent, cap_hit, nearest, record, Crossing};
+use super::hittable::{spans_between, HitRecord, Hittable, Span};
+use super::material::Material;
+use super::ray::Ray;
+use super::vec3::Vec3;
+
+/// Cone standing on the disk of `radius` around `base`, with its apex
+/// `height` above it along +y, closed by a flat base. On the side u goes
+/// around the y axis and v up it; on the base (u, v) is the point's
+/// position over the base's bounding square.
+pub struct Cone {
+  pub base: Vec3,
+  pub radius: f32,
+  pub height: f32,
+  pub material: Box<dyn Material>,
+}
+
+impl Cone {
+  pub fn new(base: Vec3, radius: f32, height: f32, material: Box<dyn Material>) -> Self {
+    Cone {
+      base,
+      radius,
+      height,
+      material,
+    }
+  }
+}
+
+impl Cone {
+  /// Every crossing of the line through `r` with the surface.
+  fn crossings(&self, r: &Ray) -> Vec<Crossing> {
+    let local = Ray::new_at_time(r.origin - self.base, r.direction, r.time);
+    let (o, d) = (local.origin, local.direction);
+    // x² + z² = (slope (height - y))² on the side
+    let slope = self.radius / self.height;
+    let s2 = slope * slope;
+    let below_apex = self.height - o.y();
+    let a = d.x() * d.x() + d.z() * d.z() - s2 * d.y() * d.y();
+    let b = o.x() * d.x() + o.z() * d.z() + s2 * below_apex * d.y();
+    let c = o.x() * o.x() + o.z() * o.z() - s2 * below_apex * below_apex;
+    let side = if a.abs() < 1e-12 {
+      // parallel to the side: only one root
+      if b != 0.0 {
+        vec![-c / (2.0 * b)]
+      } else {
+        Vec::new()
+      }
+    } else {
+      let discriminant = b * b - a * c;
+      if discriminant > 0.0 {
+        let root = discriminant.sqrt();
+        vec![(-b - root) / a, (-b + root) / a]
+      } else {
+        Vec::new()
+      }
+    };
+    let side = side.into_iter().filter_map(|t| {
+      let p = local.point_at_parameter(t);
+      if (0.0..=self.height).contains(&p.y()) {
+        let radial = Vec3(p.x(), 0.0, p.z());
+        // at the apex, any normal in the cone of normals will do
+        let (normal, outwards) = if radial.squared_length() > 0.0 {
+          let outwards = radial.to_unit_vector();
+          let normal = (outwards + Vec3(0.0, slope, 0.0)).to_unit_vector();
+          (normal, outwards)
+        } else {
+          (Vec3(0.0, 1.0, 0.0), Vec3::default())
+        };
+        Some(Crossing {
+          t,
+          normal,
+          u: around_y(p),
+          v: p.y() / self.height,
+          dpdu: around_y_tangent(p),
+          dpdv: Vec3(0.0, self.height, 0.0) - self.radius * outwards,
+        })
+      } else {
+        None
+      }
+    });
+    let base = cap_hit(&local, 0.0, self.radius, false);
+    side.chain(base).collect()
+  }
+}
+
+impl Hittable for Cone {
+  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+    let crossing = nearest(self.crossings(r).into_iter(), t_min, t_max)?;
+    Some(record(r, crossing, &*self.material))
+  }
+
+  fn spans(&self, r: &Ray) -> Option<Vec<Span<'_>>> {
+    let hits = self.crossings(r).into_iter();
+    Some(spans_between(
+      hits
+        .map(|crossing| record(r, crossing, &*self.material))
+        .collect(),
+    ))
+  }
+
+  fn bounding_box(&self) -> Option<Aabb> {
+    let r = self.radius;
+    Some(Aabb::new(
+      self.base - Vec3(r, 0.0, r),
+      self.base + Vec3(r, self.height, r),
+    ))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::material::Lambertian;
+  use super::*;
+
+  fn cone() -> Cone {
+    Cone::new(
+      Vec3(0.0, 0.0, 0.0),
+      1.0,
+      1.0,
+      Box::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+    )
+  }
+
+  #[test]
+  fn cone_hit() {
+    let cone = cone();
+    // the side is half way in at half the height
+    let rec = cone
+      .hit(
+        &Ray::new(Vec3(3.0, 0.5, 0.0), Vec3(-1.0, 0.0, 0.0)),
+        0.001,
+        f32::MAX,
+      )
+      .unwrap();
+    assert_eq!(2.5, rec.t);
+    let expected = Vec3(1.0, 1.0, 0.0).to_unit_vector();
+    assert!((rec.normal - expected).length() < 1e-6);
+    assert_eq!((0.0, 0.5), (rec.u, rec.v));
+    // up into the base
+    let rec = cone
+      .hit(
+        &Ray::new(Vec3(0.0, -1.0, 0.5), Vec3(0.0, 1.0, 0.0)),
+        0.001,
+        f32::MAX,
+      )
+      .unwrap();
+    assert_eq!(1.0, rec.t);
+    assert_eq!(Vec3(0.0, -1.0, 0.0), rec.normal);
+    // over the apex, and through the other nappe of the double cone
+    assert!(cone
+      .hit(
+        &Ray::new(Vec3(3.0, 1.5, 0.0), Vec3(-1.0, 0.0, 0.0)),
+        0.001,
+        f32::MAX
+      )
+      .is_none());
+    assert_eq!(
+      Aabb::new(Vec3(-1.0, 0.0, -1.0), Vec3(1.0, 1.0, 1.0)),
+      cone.bounding_box().unwrap()
+    );
+  }
+
+  #[test]
+  fn cone_tangent() {
+    // along the side's slope, through the apex
+    let r = Ray::new(Vec3(-2.0, -1.0, 0.0), Vec3(1.0, 1.0, 0.0));
+    let cone = cone();
+    let rec = cone.hit(&r, 0.001, f32::MAX);
+    assert!(rec.is_none_or(|rec| rec.p.y() <= 1.0 + 1e-5));
+  }
+}
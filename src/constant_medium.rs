@@ -0,0 +1,129 @@
+use super::aabb::Aabb;
+use super::hittable::HitRecord;
+use super::hittable::Hittable;
+use super::material::{Isotropic, Material};
+use super::ray::Ray;
+use super::sampler::Sampler;
+use super::vec3::Vec3;
+use std::f32;
+
+/// Homogeneous participating medium filling the inside of `boundary`, which
+/// must be closed and convex (e.g. a sphere or a box). Rays going through it
+/// scatter after an exponentially distributed distance, with `phase_function`
+/// choosing the new direction.
+pub struct ConstantMedium {
+  pub boundary: Box<dyn Hittable>,
+  neg_inv_density: f32,
+  pub phase_function: Box<dyn Material>,
+}
+
+impl ConstantMedium {
+  /// Isotropic medium, e.g. fog or smoke.
+  pub fn new(boundary: Box<dyn Hittable>, density: f32, albedo: Vec3) -> Self {
+    ConstantMedium::with_phase(boundary, density, Box::new(Isotropic::new(albedo)))
+  }
+
+  pub fn with_phase(
+    boundary: Box<dyn Hittable>,
+    density: f32,
+    phase_function: Box<dyn Material>,
+  ) -> Self {
+    ConstantMedium {
+      boundary,
+      neg_inv_density: -1.0 / density,
+      phase_function,
+    }
+  }
+}
+
+impl ConstantMedium {
+  /// Where `r` scatters in the medium within (`t_min`, `t_max`), with the
+  /// free flight distance drawn from the uniform number `xi`.
+  fn scatter_point(&self, r: &Ray, t_min: f32, t_max: f32, xi: f32) -> Option<HitRecord<'_>> {
+    // entry and exit points of the whole line, so that rays starting
+    // inside the medium work too
+    let entry = self.boundary.hit(r, -f32::INFINITY, f32::INFINITY)?;
+    let exit = self.boundary.hit(r, entry.t + 0.0001, f32::INFINITY)?;
+    let t_enter = entry.t.max(t_min).max(0.0);
+    let t_exit = exit.t.min(t_max);
+    if t_enter >= t_exit {
+      return None;
+    }
+    let ray_length = r.direction.length();
+    let distance_inside = (t_exit - t_enter) * ray_length;
+    // 1 - xi is in (0, 1], keeping the logarithm finite
+    let hit_distance = self.neg_inv_density * (1.0 - xi).ln();
+    if hit_distance > distance_inside {
+      return None;
+    }
+    let t = t_enter + hit_distance / ray_length;
+    // the normal is meaningless inside a volume
+    Some(HitRecord::new(
+      r,
+      t,
+      Vec3(1.0, 0.0, 0.0),
+      &*self.phase_function,
+    ))
+  }
+}
+
+impl Hittable for ConstantMedium {
+  /// Without a sampler, rays scatter at the median free flight distance.
+  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+    self.scatter_point(r, t_min, t_max, 0.5)
+  }
+
+  fn hit_sampled(
+    &self,
+    r: &Ray,
+    t_min: f32,
+    t_max: f32,
+    sampler: &mut dyn Sampler,
+  ) -> Option<HitRecord<'_>> {
+    self.scatter_point(r, t_min, t_max, sampler.next_1d())
+  }
+
+  fn bounding_box(&self) -> Option<Aabb> {
+    self.boundary.bounding_box()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::material::Lambertian;
+  use super::super::sampler::IndependentSampler;
+  use super::super::sphere::Sphere;
+  use super::*;
+
+  #[test]
+  fn transmittance_follows_beer_lambert() {
+    let density = 0.5;
+    let medium = ConstantMedium::new(
+      Box::new(Sphere::new(
+        Vec3(0.0, 0.0, 0.0),
+        1.0,
+        Box::new(Lambertian::new(Vec3(0.0, 0.0, 0.0))),
+      )),
+      density,
+      Vec3(1.0, 1.0, 1.0),
+    );
+    let mut sampler = IndependentSampler::new(3);
+    let n = 20000;
+    // through the center, from outside and from inside
+    let outside = Ray::new(Vec3(0.0, 0.0, 5.0), Vec3(0.0, 0.0, -2.0));
+    let inside = Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, -1.0));
+    for (r, length) in [(outside, 2.0f32), (inside, 1.0)] {
+      let passed = (0..n)
+        .filter(|_| {
+          medium
+            .hit_sampled(&r, 0.001, f32::MAX, &mut sampler)
+            .is_none()
+        })
+        .count();
+      let expected = (-density * length).exp();
+      assert!((expected - passed as f32 / n as f32).abs() < 0.01);
+    }
+    let r = Ray::new(Vec3(0.0, 2.0, 5.0), Vec3(0.0, 0.0, -1.0));
+    assert!(medium.hit(&r, 0.001, f32::MAX).is_none());
+  }
+}
@@ -0,0 +1,261 @@
+extern crate serde;
+
+use self::serde::Deserialize;
+use super::aabb::Aabb;
+use super::hittable::{HitRecord, Hittable, Span};
+use super::ray::Ray;
+use super::vec3::Vec3;
+use std::fmt;
+use std::str::FromStr;
+
+/// How `Csg` combines its two solids.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CsgOp {
+  /// Inside either solid.
+  Union,
+  /// Inside both solids.
+  Intersection,
+  /// Inside the first solid but not the second.
+  Difference,
+}
+
+impl CsgOp {
+  fn inside(self, a: bool, b: bool) -> bool {
+    match self {
+      CsgOp::Union => a || b,
+      CsgOp::Intersection => a && b,
+      CsgOp::Difference => a && !b,
+    }
+  }
+}
+
+impl FromStr for CsgOp {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "union" => Ok(CsgOp::Union),
+      "intersection" => Ok(CsgOp::Intersection),
+      "difference" => Ok(CsgOp::Difference),
+      _ => Err(format!(
+        "unknown operation `{}`, expected union, intersection or difference",
+        s
+      )),
+    }
+  }
+}
+
+impl fmt::Display for CsgOp {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      CsgOp::Union => "union",
+      CsgOp::Intersection => "intersection",
+      CsgOp::Difference => "difference",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+/// Solid made by combining the solids `a` and `b`, which must answer
+/// `spans`. Each face keeps the material of the solid it comes from, so the
+/// faces `b` carves out of `a` in a difference show `b`'s material, with
+/// their outside being the outside of the result.
+pub struct Csg {
+  pub op: CsgOp,
+  pub a: Box<dyn Hittable>,
+  pub b: Box<dyn Hittable>,
+}
+
+impl Csg {
+  pub fn new(op: CsgOp, a: Box<dyn Hittable>, b: Box<dyn Hittable>) -> Self {
+    Csg { op, a, b }
+  }
+}
+
+impl Hittable for Csg {
+  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+    self
+      .spans(r)?
+      .into_iter()
+      .flat_map(|span| vec![span.enter, span.exit])
+      .find(|rec| t_min < rec.t && rec.t < t_max)
+  }
+
+  fn spans(&self, r: &Ray) -> Option<Vec<Span<'_>>> {
+    // every boundary of either solid in order, as (hit, from b, entering)
+    let mut events = Vec::new();
+    for (spans, from_b) in [(self.a.spans(r)?, false), (self.b.spans(r)?, true)] {
+      for span in spans {
+        events.push((span.enter, from_b, true));
+        events.push((span.exit, from_b, false));
+      }
+    }
+    events.sort_by(|x, y| x.0.t.total_cmp(&y.0.t));
+
+    let (mut in_a, mut in_b) = (false, false);
+    let mut enter = None;
+    let mut spans = Vec::new();
+    for (mut rec, from_b, entering) in events {
+      let was_inside = self.op.inside(in_a, in_b);
+      if from_b {
+        in_b = entering;
+      } else {
+        in_a = entering;
+      }
+      let inside = self.op.inside(in_a, in_b);
+      if inside == was_inside {
+        continue;
+      }
+      // the inside of b is the outside of a difference
+      if from_b && self.op == CsgOp::Difference {
+        rec.front_face = !rec.front_face;
+      }
+      match enter.take() {
+        None => enter = Some(rec),
+        Some(enter) => spans.push(Span { enter, exit: rec }),
+      }
+    }
+    Some(spans)
+  }
+
+  fn bounding_box(&self) -> Option<Aabb> {
+    match self.op {
+      CsgOp::Union => Some(Aabb::surrounding(
+        &self.a.bounding_box()?,
+        &self.b.bounding_box()?,
+      )),
+      CsgOp::Intersection => match (self.a.bounding_box(), self.b.bounding_box()) {
+        (Some(a), Some(b)) => {
+          let per_axis = |f: fn(f32, f32) -> f32, a: Vec3, b: Vec3| {
+            Vec3(f(a.x(), b.x()), f(a.y(), b.y()), f(a.z(), b.z()))
+          };
+          // an empty overlap shrinks to a point rather than turning inside out
+          let min = per_axis(f32::max, a.min, b.min);
+          let max = per_axis(f32::min, a.max, b.max);
+          Some(Aabb::new(min, per_axis(f32::max, min, max)))
+        }
+        (a, b) => a.or(b),
+      },
+      CsgOp::Difference => self.a.bounding_box(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::cuboid::Cuboid;
+  use super::super::material::DiffuseLight;
+  use super::super::sphere::Sphere;
+  use super::*;
+  use std::f32;
+
+  /// Sphere whose material glows `tag`, to tell which solid a face is from.
+  fn sphere(z: f32, radius: f32, tag: f32) -> Box<dyn Hittable> {
+    Box::new(Sphere::new(
+      Vec3(0.0, 0.0, z),
+      radius,
+      Box::new(DiffuseLight::new(Vec3(tag, tag, tag))),
+    ))
+  }
+
+  fn cube(tag: f32) -> Box<dyn Hittable> {
+    Box::new(Cuboid::new(
+      Vec3(-1.0, -1.0, -1.0),
+      Vec3(1.0, 1.0, 1.0),
+      Box::new(DiffuseLight::new(Vec3(tag, tag, tag))),
+    ))
+  }
+
+  /// The line along -z from z = 5, as (z, outward normal's z, tag) at each
+  /// boundary.
+  fn boundaries(csg: &Csg) -> Vec<(f32, f32, f32)> {
+    let r = Ray::new(Vec3(0.0, 0.0, 5.0), Vec3(0.0, 0.0, -1.0));
+    let tag = |rec: &HitRecord| rec.material.emitted(&r, rec).x();
+    csg
+      .spans(&r)
+      .unwrap()
+      .iter()
+      .flat_map(|span| vec![span.enter, span.exit])
+      .map(|rec| (rec.p.z(), rec.outward_normal().z(), tag(&rec)))
+      .collect()
+  }
+
+  #[test]
+  fn operations_parse_their_names() {
+    for op in &[CsgOp::Union, CsgOp::Intersection, CsgOp::Difference] {
+      assert_eq!(Ok(*op), op.to_string().parse());
+    }
+    assert!("xor".parse::<CsgOp>().is_err());
+  }
+
+  #[test]
+  fn union_drops_inner_faces() {
+    let csg = Csg::new(CsgOp::Union, sphere(0.5, 1.0, 1.0), sphere(-0.5, 1.0, 2.0));
+    assert_eq!(vec![(1.5, 1.0, 1.0), (-1.5, -1.0, 2.0)], boundaries(&csg));
+    let bbox = csg.bounding_box().unwrap();
+    assert_eq!(
+      (Vec3(-1.0, -1.0, -1.5), Vec3(1.0, 1.0, 1.5)),
+      (bbox.min, bbox.max)
+    );
+  }
+
+  #[test]
+  fn intersection_makes_a_lens() {
+    let csg = Csg::new(
+      CsgOp::Intersection,
+      sphere(0.5, 1.0, 1.0),
+      sphere(-0.5, 1.0, 2.0),
+    );
+    assert_eq!(vec![(0.5, 1.0, 2.0), (-0.5, -1.0, 1.0)], boundaries(&csg));
+    let bbox = csg.bounding_box().unwrap();
+    assert_eq!(
+      (Vec3(-1.0, -1.0, -0.5), Vec3(1.0, 1.0, 0.5)),
+      (bbox.min, bbox.max)
+    );
+  }
+
+  #[test]
+  fn difference_turns_carved_faces_outwards() {
+    let csg = Csg::new(CsgOp::Difference, cube(1.0), sphere(0.0, 0.5, 2.0));
+    assert_eq!(
+      vec![
+        (1.0, 1.0, 1.0),
+        (0.5, -1.0, 2.0),
+        (-0.5, 1.0, 2.0),
+        (-1.0, -1.0, 1.0)
+      ],
+      boundaries(&csg)
+    );
+    // from inside the cavity, the nearest hit is its wall
+    let r = Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0));
+    let rec = csg.hit(&r, 0.001, f32::MAX).unwrap();
+    assert_eq!((0.5, -1.0, true), (rec.t, rec.normal.x(), rec.front_face));
+    assert!(csg.hit(&r, 0.6, 1.0).is_none());
+    assert_eq!(1.0, csg.hit(&r, 0.6, f32::MAX).unwrap().t);
+
+    // nothing is left of a cube carved by a sphere around it
+    let csg = Csg::new(CsgOp::Difference, cube(1.0), sphere(0.0, 2.0, 2.0));
+    assert!(boundaries(&csg).is_empty());
+  }
+
+  #[test]
+  fn nested_operations() {
+    // the lens with the hole of a smaller sphere through its middle
+    let lens = Csg::new(
+      CsgOp::Intersection,
+      sphere(0.5, 1.0, 1.0),
+      sphere(-0.5, 1.0, 1.0),
+    );
+    let csg = Csg::new(CsgOp::Difference, Box::new(lens), sphere(0.0, 0.25, 2.0));
+    assert_eq!(
+      vec![
+        (0.5, 1.0, 1.0),
+        (0.25, -1.0, 2.0),
+        (-0.25, 1.0, 2.0),
+        (-0.5, -1.0, 1.0)
+      ],
+      boundaries(&csg)
+    );
+  }
+}
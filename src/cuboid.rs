@@ -0,0 +1,160 @@
+use super::aabb::{axis_of, Aabb};
+use super::hittable::{HitRecord, Hittable, Span};
+use super::material::Material;
+use super::ray::Ray;
+use super::vec3::Vec3;
+
+/// Axis-aligned box from `min` to `max`. u and v go from 0 to 1 across each
+/// face, along the two axes it spans in x, y, z order.
+pub struct Cuboid {
+  pub min: Vec3,
+  pub max: Vec3,
+  pub material: Box<dyn Material>,
+}
+
+impl Cuboid {
+  pub fn new(a: Vec3, b: Vec3, material: Box<dyn Material>) -> Self {
+    Cuboid {
+      min: Vec3(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z())),
+      max: Vec3(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z())),
+      material,
+    }
+  }
+}
+
+/// Where a ray crosses a face: its t, the axis the face is across and which
+/// side of the box it is on.
+type Crossing = (f32, usize, f32);
+
+impl Cuboid {
+  /// Where the line through `r` enters and leaves the box, if it does.
+  fn slabs(&self, r: &Ray) -> Option<(Crossing, Crossing)> {
+    // remember the axis and side each bound of the overlap comes from
+    let (mut near, mut far) = ((f32::NEG_INFINITY, 0, 0.0), (f32::INFINITY, 0, 0.0));
+    for axis in 0..3 {
+      let d = axis_of(r.direction, axis);
+      let o = axis_of(r.origin, axis);
+      let (lo, hi) = (axis_of(self.min, axis), axis_of(self.max, axis));
+      if d == 0.0 {
+        if o < lo || o > hi {
+          return None;
+        }
+        continue;
+      }
+      let (t0, t1) = ((lo - o) / d, (hi - o) / d);
+      let (entry, exit) = if d > 0.0 {
+        ((t0, axis, -1.0), (t1, axis, 1.0))
+      } else {
+        ((t1, axis, 1.0), (t0, axis, -1.0))
+      };
+      if entry.0 > near.0 {
+        near = entry;
+      }
+      if exit.0 < far.0 {
+        far = exit;
+      }
+    }
+    if near.0 > far.0 {
+      return None;
+    }
+    Some((near, far))
+  }
+
+  fn record(&self, r: &Ray, (t, axis, side): Crossing) -> HitRecord<'_> {
+    let p = r.point_at_parameter(t);
+    let unit = |i: usize| {
+      let mut v = [0.0; 3];
+      v[i] = 1.0;
+      Vec3(v[0], v[1], v[2])
+    };
+    let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+    let (a, b) = (a.min(b), a.max(b));
+    let size = |i: usize| axis_of(self.max, i) - axis_of(self.min, i);
+    let across = |i: usize| (axis_of(p, i) - axis_of(self.min, i)) / size(i);
+    HitRecord::new(r, t, side * unit(axis), &*self.material).with_uv(
+      across(a),
+      across(b),
+      size(a) * unit(a),
+      size(b) * unit(b),
+    )
+  }
+}
+
+impl Hittable for Cuboid {
+  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+    let (near, far) = self.slabs(r)?;
+    let crossing = if t_min < near.0 && near.0 < t_max {
+      near
+    } else if t_min < far.0 && far.0 < t_max {
+      far
+    } else {
+      return None;
+    };
+    Some(self.record(r, crossing))
+  }
+
+  fn spans(&self, r: &Ray) -> Option<Vec<Span<'_>>> {
+    Some(
+      self
+        .slabs(r)
+        .map(|(near, far)| Span {
+          enter: self.record(r, near),
+          exit: self.record(r, far),
+        })
+        .into_iter()
+        .collect(),
+    )
+  }
+
+  fn bounding_box(&self) -> Option<Aabb> {
+    Some(Aabb::new(self.min, self.max))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::material::Lambertian;
+  use super::*;
+
+  fn cuboid() -> Cuboid {
+    Cuboid::new(
+      Vec3(1.0, 1.0, 1.0),
+      Vec3(-1.0, 0.0, -2.0),
+      Box::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+    )
+  }
+
+  #[test]
+  fn cuboid_hit() {
+    let cuboid = cuboid();
+    let r = Ray::new(Vec3(0.5, 0.25, 5.0), Vec3(0.0, 0.0, -2.0));
+    let rec = cuboid.hit(&r, 0.001, f32::MAX).unwrap();
+    assert_eq!(2.0, rec.t);
+    assert_eq!(Vec3(0.0, 0.0, 1.0), rec.normal);
+    assert_eq!((0.75, 0.25), (rec.u, rec.v));
+    // from inside, the far face
+    let inside = Ray::new(Vec3(0.0, 0.5, 0.0), Vec3(0.0, -1.0, 0.0));
+    let rec = cuboid.hit(&inside, 0.001, f32::MAX).unwrap();
+    assert_eq!(0.5, rec.t);
+    assert_eq!(Vec3(0.0, 1.0, 0.0), rec.normal);
+    assert_eq!(Vec3(0.0, -1.0, 0.0), rec.outward_normal());
+    assert!(!rec.front_face);
+    let beside = Ray::new(Vec3(2.0, 0.5, 5.0), Vec3(0.0, 0.0, -1.0));
+    assert!(cuboid.hit(&beside, 0.001, f32::MAX).is_none());
+    assert_eq!(
+      Aabb::new(Vec3(-1.0, 0.0, -2.0), Vec3(1.0, 1.0, 1.0)),
+      cuboid.bounding_box().unwrap()
+    );
+  }
+
+  #[test]
+  fn cuboid_tangent() {
+    // grazing the top face
+    let r = Ray::new(Vec3(-3.0, 1.0, 0.0), Vec3(1.0, 0.0, 0.0));
+    let cuboid = cuboid();
+    let rec = cuboid.hit(&r, 0.001, f32::MAX);
+    assert!(rec.is_none_or(|rec| rec.p.y() == 1.0));
+    let above = Ray::new(Vec3(-3.0, 1.001, 0.0), Vec3(1.0, 0.0, 0.0));
+    assert!(cuboid.hit(&above, 0.001, f32::MAX).is_none());
+  }
+}
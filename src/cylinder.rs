@@ -0,0 +1,222 @@
+use super::aabb::Aabb;
+use super::hittable::{spans_between, HitRecord, Hittable, Span};
+use super::material::Material;
+use super::ray::Ray;
+use super::vec3::Vec3;
+use std::f32;
+
+/// Cylinder standing on the disk of `radius` around `base`, `height` tall
+/// along +y and closed by flat caps. On the side u goes around the y axis
+/// and v up it; on the caps (u, v) is the point's position over the
+/// cap's bounding square.
+pub struct Cylinder {
+  pub base: Vec3,
+  pub radius: f32,
+  pub height: f32,
+  pub material: Box<dyn Material>,
+}
+
+impl Cylinder {
+  pub fn new(base: Vec3, radius: f32, height: f32, material: Box<dyn Material>) -> Self {
+    Cylinder {
+      base,
+      radius,
+      height,
+      material,
+    }
+  }
+}
+
+/// Where a ray crosses the surface of a shape standing along +y, in
+/// coordinates relative to its base.
+#[derive(Clone, Copy)]
+pub(crate) struct Crossing {
+  pub t: f32,
+  /// Unit normal out of the shape.
+  pub normal: Vec3,
+  pub u: f32,
+  pub v: f32,
+  pub dpdu: Vec3,
+  pub dpdv: Vec3,
+}
+
+/// Nearest of the `hits` within (t_min, t_max).
+pub(crate) fn nearest(
+  hits: impl Iterator<Item = Crossing>,
+  t_min: f32,
+  t_max: f32,
+) -> Option<Crossing> {
+  hits
+    .filter(|hit| t_min < hit.t && hit.t < t_max)
+    .min_by(|a, b| a.t.total_cmp(&b.t))
+}
+
+/// Hit of `r`, relative to the shape's base, with the flat cap of `radius`
+/// at height `y`, facing up or down.
+pub(crate) fn cap_hit(r: &Ray, y: f32, radius: f32, up: bool) -> Option<Crossing> {
+  let t = (y - r.origin.y()) / r.direction.y();
+  let p = r.point_at_parameter(t);
+  if !t.is_finite() || p.x() * p.x() + p.z() * p.z() > radius * radius {
+    return None;
+  }
+  Some(Crossing {
+    t,
+    normal: Vec3(0.0, if up { 1.0 } else { -1.0 }, 0.0),
+    u: 0.5 + 0.5 * p.x() / radius,
+    v: 0.5 + 0.5 * p.z() / radius,
+    dpdu: Vec3(2.0 * radius, 0.0, 0.0),
+    dpdv: Vec3(0.0, 0.0, 2.0 * radius),
+  })
+}
+
+/// Angle of `p` around the y axis, from 0 to 1.
+pub(crate) fn around_y(p: Vec3) -> f32 {
+  (p.z().atan2(p.x()) / (2.0 * f32::consts::PI)).rem_euclid(1.0)
+}
+
+/// How `p` moves as its angle around the y axis goes from 0 to 1.
+pub(crate) fn around_y_tangent(p: Vec3) -> Vec3 {
+  2.0 * f32::consts::PI * Vec3(-p.z(), 0.0, p.x())
+}
+
+/// The record of `r` making the `crossing`.
+pub(crate) fn record<'m>(r: &Ray, crossing: Crossing, material: &'m dyn Material) -> HitRecord<'m> {
+  HitRecord::new(r, crossing.t, crossing.normal, material).with_uv(
+    crossing.u,
+    crossing.v,
+    crossing.dpdu,
+    crossing.dpdv,
+  )
+}
+
+impl Cylinder {
+  /// Every crossing of the line through `r` with the surface.
+  fn crossings(&self, r: &Ray) -> Vec<Crossing> {
+    let local = Ray::new_at_time(r.origin - self.base, r.direction, r.time);
+    let (o, d) = (local.origin, local.direction);
+    // x² + z² = radius² on the side
+    let a = d.x() * d.x() + d.z() * d.z();
+    let b = o.x() * d.x() + o.z() * d.z();
+    let c = o.x() * o.x() + o.z() * o.z() - self.radius * self.radius;
+    let discriminant = b * b - a * c;
+    let side = if a > 0.0 && discriminant > 0.0 {
+      let root = discriminant.sqrt();
+      vec![(-b - root) / a, (-b + root) / a]
+    } else {
+      Vec::new()
+    };
+    let side = side.into_iter().filter_map(|t| {
+      let p = local.point_at_parameter(t);
+      if (0.0..=self.height).contains(&p.y()) {
+        Some(Crossing {
+          t,
+          normal: Vec3(p.x(), 0.0, p.z()) / self.radius,
+          u: around_y(p),
+          v: p.y() / self.height,
+          dpdu: around_y_tangent(p),
+          dpdv: Vec3(0.0, self.height, 0.0),
+        })
+      } else {
+        None
+      }
+    });
+    let caps = [
+      cap_hit(&local, 0.0, self.radius, false),
+      cap_hit(&local, self.height, self.radius, true),
+    ];
+    side.chain(caps.iter().flatten().copied()).collect()
+  }
+}
+
+impl Hittable for Cylinder {
+  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+    let crossing = nearest(self.crossings(r).into_iter(), t_min, t_max)?;
+    Some(record(r, crossing, &*self.material))
+  }
+
+  fn spans(&self, r: &Ray) -> Option<Vec<Span<'_>>> {
+    let hits = self.crossings(r).into_iter();
+    Some(spans_between(
+      hits
+        .map(|crossing| record(r, crossing, &*self.material))
+        .collect(),
+    ))
+  }
+
+  fn bounding_box(&self) -> Option<Aabb> {
+    let r = self.radius;
+    Some(Aabb::new(
+      self.base - Vec3(r, 0.0, r),
+      self.base + Vec3(r, self.height, r),
+    ))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::material::Lambertian;
+  use super::*;
+
+  fn cylinder() -> Cylinder {
+    Cylinder::new(
+      Vec3(0.0, -1.0, -3.0),
+      1.0,
+      2.0,
+      Box::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+    )
+  }
+
+  #[test]
+  fn cylinder_hit() {
+    let cylinder = cylinder();
+    let rec = cylinder
+      .hit(
+        &Ray::new(Vec3(0.0, 0.5, 0.0), Vec3(0.0, 0.0, -1.0)),
+        0.001,
+        f32::MAX,
+      )
+      .unwrap();
+    assert_eq!(2.0, rec.t);
+    assert_eq!(Vec3(0.0, 0.0, 1.0), rec.normal);
+    assert_eq!((0.25, 0.75), (rec.u, rec.v));
+    // down onto the top cap
+    let rec = cylinder
+      .hit(
+        &Ray::new(Vec3(0.5, 3.0, -3.0), Vec3(0.0, -1.0, 0.0)),
+        0.001,
+        f32::MAX,
+      )
+      .unwrap();
+    assert_eq!(2.0, rec.t);
+    assert_eq!(Vec3(0.0, 1.0, 0.0), rec.normal);
+    assert_eq!((0.75, 0.5), (rec.u, rec.v));
+    // from inside, out through the bottom
+    let rec = cylinder
+      .hit(
+        &Ray::new(Vec3(0.0, 0.0, -3.0), Vec3(0.0, -1.0, 0.0)),
+        0.001,
+        f32::MAX,
+      )
+      .unwrap();
+    assert_eq!(Vec3(0.0, -1.0, 0.0), rec.outward_normal());
+    assert!(!rec.front_face);
+    // above the top
+    assert!(cylinder
+      .hit(
+        &Ray::new(Vec3(0.0, 1.5, 0.0), Vec3(0.0, 0.0, -1.0)),
+        0.001,
+        f32::MAX
+      )
+      .is_none());
+    assert_eq!(
+      Aabb::new(Vec3(-1.0, -1.0, -4.0), Vec3(1.0, 1.0, -2.0)),
+      cylinder.bounding_box().unwrap()
+    );
+  }
+
+  #[test]
+  fn cylinder_tangent() {
+    let r = Ray::new(Vec3(1.0, 0.0, 0.0), Vec3(0.0, 0.0, -1.0));
+    assert!(cylinder().hit(&r, 0.001, f32::MAX).is_none());
+  }
+}
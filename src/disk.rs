@@ -0,0 +1,155 @@
+use super::aabb::Aabb;
+use super::hittable::{HitRecord, Hittable};
+use super::material::Material;
+use super::onb::Onb;
+use super::ray::Ray;
+use super::sampler::Sampler;
+use super::vec3::Vec3;
+use std::f32;
+
+/// Flat disk of `radius` around `center`, facing `normal`. u goes around
+/// the center and v from it to the rim.
+pub struct Disk {
+  pub center: Vec3,
+  pub normal: Vec3,
+  pub radius: f32,
+  pub material: Box<dyn Material>,
+  frame: Onb,
+}
+
+impl Disk {
+  pub fn new(center: Vec3, normal: Vec3, radius: f32, material: Box<dyn Material>) -> Self {
+    let frame = Onb::from_w(normal);
+    Disk {
+      center,
+      normal: frame.w,
+      radius,
+      material,
+      frame,
+    }
+  }
+
+  fn area(&self) -> f32 {
+    f32::consts::PI * self.radius * self.radius
+  }
+}
+
+impl Hittable for Disk {
+  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+    let denominator = r.direction.dot(self.normal);
+    if denominator == 0.0 {
+      return None;
+    }
+    let t = (self.center - r.origin).dot(self.normal) / denominator;
+    if !(t_min < t && t < t_max) {
+      return None;
+    }
+    let p = r.point_at_parameter(t);
+    let local = self.frame.coordinates(p - self.center);
+    let distance = (local.x() * local.x() + local.y() * local.y()).sqrt();
+    if distance > self.radius {
+      return None;
+    }
+    let angle = local.y().atan2(local.x());
+    // u turns the point around the center, v moves it out from there
+    let dpdu = 2.0 * f32::consts::PI * (local.x() * self.frame.v - local.y() * self.frame.u);
+    let dpdv = if distance > 0.0 {
+      self.radius / distance * (local.x() * self.frame.u + local.y() * self.frame.v)
+    } else {
+      Vec3::default()
+    };
+    Some(HitRecord::new(r, t, self.normal, &*self.material).with_uv(
+      (angle / (2.0 * f32::consts::PI)).rem_euclid(1.0),
+      distance / self.radius,
+      dpdu,
+      dpdv,
+    ))
+  }
+
+  fn bounding_box(&self) -> Option<Aabb> {
+    // the disk reaches radius · sin of the angle between each axis and the normal
+    let n = self.normal;
+    let extent = |c: f32| self.radius * (1.0 - c * c).max(0.0).sqrt() + 1e-4;
+    let half = Vec3(extent(n.x()), extent(n.y()), extent(n.z()));
+    Some(Aabb::new(self.center - half, self.center + half))
+  }
+
+  fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+    match self.hit(&Ray::new_at_time(origin, direction, time), 0.001, f32::MAX) {
+      Some(rec) => {
+        let distance_squared = rec.t * rec.t * direction.squared_length();
+        let cosine = (direction.dot(self.normal) / direction.length()).abs();
+        distance_squared / (cosine * self.area())
+      }
+      None => 0.0,
+    }
+  }
+
+  /// Towards a point picked uniformly on the disk's area.
+  fn random(&self, origin: Vec3, _time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+    let (u1, u2) = sampler.next_2d();
+    let r = self.radius * u1.sqrt();
+    let phi = 2.0 * f32::consts::PI * u2;
+    self.center + self.frame.local(Vec3(r * phi.cos(), r * phi.sin(), 0.0)) - origin
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::material::Lambertian;
+  use super::super::sampler::IndependentSampler;
+  use super::*;
+
+  fn disk() -> Disk {
+    Disk::new(
+      Vec3(0.0, 2.0, 0.0),
+      Vec3(0.0, -1.0, 0.0),
+      1.0,
+      Box::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+    )
+  }
+
+  #[test]
+  fn disk_hit() {
+    let disk = disk();
+    let rec = disk
+      .hit(
+        &Ray::new(Vec3(0.5, 0.0, 0.0), Vec3(0.0, 1.0, 0.0)),
+        0.001,
+        f32::MAX,
+      )
+      .unwrap();
+    assert_eq!(2.0, rec.t);
+    assert_eq!(Vec3(0.0, -1.0, 0.0), rec.normal);
+    assert!((rec.v - 0.5).abs() < 1e-6);
+    assert!(disk
+      .hit(
+        &Ray::new(Vec3(1.1, 0.0, 0.0), Vec3(0.0, 1.0, 0.0)),
+        0.001,
+        f32::MAX
+      )
+      .is_none());
+    let bbox = disk.bounding_box().unwrap();
+    assert!((bbox.max.x() - 1.0).abs() < 1e-3 && (bbox.max.y() - 2.0).abs() < 1e-3);
+  }
+
+  #[test]
+  fn disk_sampling() {
+    let disk = disk();
+    let origin = Vec3(0.0, 0.0, 0.0);
+    let mut sampler = IndependentSampler::new(1);
+    // the density integrates to one over the directions towards the disk
+    let n = 20000;
+    let mut solid_angle = 0.0;
+    for _ in 0..n {
+      let direction = disk.random(origin, 0.0, &mut sampler);
+      assert!(disk
+        .hit(&Ray::new(origin, direction), 0.001, f32::MAX)
+        .is_some());
+      solid_angle += 1.0 / disk.pdf_value(origin, direction, 0.0) / n as f32;
+    }
+    // cone of half angle atan(1/2)
+    let expected = 2.0 * f32::consts::PI * (1.0 - 2.0 / 5f32.sqrt());
+    assert!((solid_angle - expected).abs() < 1e-2 * expected);
+  }
+}
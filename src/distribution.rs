@@ -0,0 +1,152 @@
+/// Piecewise constant density over [0, 1), proportional to `func`.
+#[derive(Clone, Debug)]
+pub struct Distribution1D {
+  func: Vec<f32>,
+  /// cdf[i] is the probability of landing before piece i, cdf[n] = 1.
+  cdf: Vec<f32>,
+  /// Integral of `func` over [0, 1).
+  pub integral: f32,
+}
+
+impl Distribution1D {
+  /// `func` must not be negative. All zeros give the uniform density.
+  pub fn new(func: Vec<f32>) -> Self {
+    let n = func.len();
+    assert!(n > 0, "distribution without pieces");
+    let mut cdf = Vec::with_capacity(n + 1);
+    cdf.push(0.0);
+    // in f64, as environment maps have many tiny pieces
+    let mut sum = 0.0f64;
+    for f in &func {
+      sum += f64::from(*f) / n as f64;
+      cdf.push(sum as f32);
+    }
+    let integral = sum as f32;
+    if sum > 0.0 {
+      let mut running = 0.0f64;
+      for (i, f) in func.iter().enumerate() {
+        running += f64::from(*f) / n as f64;
+        cdf[i + 1] = (running / sum) as f32;
+      }
+    } else {
+      for (i, c) in cdf.iter_mut().enumerate() {
+        *c = i as f32 / n as f32;
+      }
+    }
+    cdf[n] = 1.0;
+    Distribution1D {
+      func,
+      cdf,
+      integral,
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.func.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.func.is_empty()
+  }
+
+  /// Maps uniform `u` to a point of the density, its density and the piece
+  /// it falls in.
+  pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+    // last piece whose cdf is at most u, skipping pieces without probability
+    let piece = self.cdf.partition_point(|&c| c <= u).clamp(1, self.len()) - 1;
+    let width = self.cdf[piece + 1] - self.cdf[piece];
+    let offset = if width > 0.0 {
+      (u - self.cdf[piece]) / width
+    } else {
+      0.0
+    };
+    let x = ((piece as f32 + offset) / self.len() as f32).min(1.0 - f32::EPSILON / 2.0);
+    (x, self.pdf(piece), piece)
+  }
+
+  /// Density of the points of `piece`.
+  pub fn pdf(&self, piece: usize) -> f32 {
+    if self.integral > 0.0 {
+      self.func[piece] / self.integral
+    } else {
+      1.0
+    }
+  }
+}
+
+/// Piecewise constant density over [0, 1)², proportional to a grid of values
+/// given row by row: a row is picked from the marginal density of the rows,
+/// then a column from that row's density.
+#[derive(Clone, Debug)]
+pub struct Distribution2D {
+  rows: Vec<Distribution1D>,
+  marginal: Distribution1D,
+}
+
+impl Distribution2D {
+  pub fn new(func: &[f32], width: usize, height: usize) -> Self {
+    assert_eq!(width * height, func.len());
+    let rows: Vec<Distribution1D> = func
+      .chunks(width)
+      .map(|row| Distribution1D::new(row.to_vec()))
+      .collect();
+    let marginal = Distribution1D::new(rows.iter().map(|r| r.integral).collect());
+    Distribution2D { rows, marginal }
+  }
+
+  /// Point (u, v) for uniform `u`, with v indexing rows, and its density.
+  pub fn sample(&self, u: (f32, f32)) -> ((f32, f32), f32) {
+    let (v, pdf_v, row) = self.marginal.sample(u.1);
+    let (u, pdf_u, _) = self.rows[row].sample(u.0);
+    ((u, v), pdf_u * pdf_v)
+  }
+
+  pub fn pdf(&self, u: f32, v: f32) -> f32 {
+    let row = ((v * self.marginal.len() as f32) as usize).min(self.marginal.len() - 1);
+    let distribution = &self.rows[row];
+    let column = ((u * distribution.len() as f32) as usize).min(distribution.len() - 1);
+    self.marginal.pdf(row) * distribution.pdf(column)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn samples_follow_the_function() {
+    let d = Distribution1D::new(vec![1.0, 0.0, 3.0, 0.0]);
+    assert_eq!(1.0, d.integral);
+    // the first quarter of u lands in piece 0, the rest in piece 2
+    let (x, pdf, piece) = d.sample(0.125);
+    assert_eq!((0.125, 1.0, 0), (x, pdf, piece));
+    let (x, pdf, piece) = d.sample(0.625);
+    assert_eq!((0.625, 3.0, 2), (x, pdf, piece));
+    assert_eq!(2, d.sample(0.25).2);
+    assert_eq!(2, d.sample(0.999_999).2);
+    assert_eq!(0.0, d.pdf(1));
+  }
+
+  #[test]
+  fn zero_function_is_uniform() {
+    let d = Distribution1D::new(vec![0.0; 3]);
+    let (x, pdf, piece) = d.sample(0.5);
+    assert!((x - 0.5).abs() < 1e-6);
+    assert_eq!((1.0, 1), (pdf, piece));
+  }
+
+  #[test]
+  fn two_dimensional_density_integrates_to_one() {
+    let func = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+    let d = Distribution2D::new(&func, 3, 2);
+    // cell (1, 1) holds 4 of the total 15, over a sixth of the square
+    assert!((d.pdf(0.5, 0.75) - 4.0 / 15.0 * 6.0).abs() < 1e-5);
+    assert_eq!(0.0, d.pdf(0.1, 0.1));
+    let ((u, v), pdf) = d.sample((0.3, 0.9));
+    assert!((pdf - d.pdf(u, v)).abs() < 1e-5);
+    let total: f32 = (0..6)
+      .map(|i| d.pdf((i % 3) as f32 / 3.0 + 0.1, (i / 3) as f32 / 2.0 + 0.1) / 6.0)
+      .sum();
+    assert!((total - 1.0).abs() < 1e-5);
+  }
+}
@@ -0,0 +1,244 @@
+use super::distribution::Distribution2D;
+use super::progress::luminance;
+use super::sampler::Sampler;
+use super::texture::load_linear;
+use super::vec3::Vec3;
+use image::ImageError;
+use std::f32;
+use std::fmt;
+use std::path::Path;
+
+/// Light arriving from infinitely far away, seen by rays that leave the scene.
+pub trait Environment: fmt::Debug + Send + Sync {
+  /// Radiance arriving from `direction`, which need not be of unit length.
+  fn radiance(&self, direction: Vec3) -> Vec3;
+
+  /// A unit direction picked roughly in proportion to the radiance, and its
+  /// density over solid angle, for environments that can be sampled as lights.
+  fn sample(&self, _sampler: &mut dyn Sampler) -> Option<(Vec3, f32)> {
+    None
+  }
+
+  /// Density of `sample` picking `direction`, 0 if the environment is not
+  /// sampled.
+  fn pdf(&self, _direction: Vec3) -> f32 {
+    0.0
+  }
+}
+
+/// Vertical blend between two colors on the ray direction's height.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gradient {
+  pub bottom: Vec3,
+  pub top: Vec3,
+}
+
+impl Default for Gradient {
+  /// The white to blue sky of the book.
+  fn default() -> Self {
+    Gradient {
+      bottom: Vec3(1.0, 1.0, 1.0),
+      top: Vec3(0.5, 0.7, 1.0),
+    }
+  }
+}
+
+impl Environment for Gradient {
+  fn radiance(&self, direction: Vec3) -> Vec3 {
+    let unit_direction = direction.to_unit_vector();
+    let t = 0.5 * (unit_direction.y() + 1.0);
+    (1.0 - t) * self.bottom + t * self.top
+  }
+}
+
+/// Same color in every direction, black for scenes lit only by their lights.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Uniform(pub Vec3);
+
+impl Environment for Uniform {
+  fn radiance(&self, _direction: Vec3) -> Vec3 {
+    self.0
+  }
+}
+
+/// Equirectangular (latitude-longitude) image of the surroundings: +y is at
+/// the top row, and -z in the middle column. Directions are sampled in
+/// proportion to the luminance of the pixels they fall in.
+pub struct EnvironmentMap {
+  width: usize,
+  height: usize,
+  pixels: Vec<Vec3>,
+  /// Degrees about the vertical axis.
+  rotation: f32,
+  intensity: f32,
+  distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+  /// A map of linear `pixels`, top row first, scaled by `intensity` and
+  /// turned by `rotation` degrees about the vertical axis.
+  pub fn new(
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>,
+    rotation: f32,
+    intensity: f32,
+  ) -> Self {
+    assert_eq!(width * height, pixels.len());
+    // rows near the poles cover less solid angle
+    let weights: Vec<f32> = pixels
+      .iter()
+      .enumerate()
+      .map(|(i, p)| {
+        let theta = ((i / width) as f32 + 0.5) / height as f32 * f32::consts::PI;
+        luminance(*p).max(0.0) * theta.sin()
+      })
+      .collect();
+    EnvironmentMap {
+      width,
+      height,
+      distribution: Distribution2D::new(&weights, width, height),
+      pixels,
+      rotation,
+      intensity,
+    }
+  }
+
+  /// Loads a Radiance HDR or OpenEXR file, or an sRGB PNG or JPEG one.
+  pub fn load<P: AsRef<Path>>(path: P, rotation: f32, intensity: f32) -> Result<Self, ImageError> {
+    let (width, height, pixels) = load_linear(path)?;
+    Ok(EnvironmentMap::new(
+      width, height, pixels, rotation, intensity,
+    ))
+  }
+
+  /// Image coordinates in [0, 1)² of a unit direction.
+  fn uv(&self, d: Vec3) -> (f32, f32) {
+    let phi = d.x().atan2(-d.z()) + self.rotation.to_radians();
+    let u = (0.5 + phi / (2.0 * f32::consts::PI)).rem_euclid(1.0);
+    let v = d.y().clamp(-1.0, 1.0).acos() / f32::consts::PI;
+    (
+      u.min(1.0 - f32::EPSILON / 2.0),
+      v.min(1.0 - f32::EPSILON / 2.0),
+    )
+  }
+
+  fn direction(&self, u: f32, v: f32) -> Vec3 {
+    let phi = (u - 0.5) * 2.0 * f32::consts::PI - self.rotation.to_radians();
+    let (sin_theta, cos_theta) = (v * f32::consts::PI).sin_cos();
+    Vec3(sin_theta * phi.sin(), cos_theta, -sin_theta * phi.cos())
+  }
+}
+
+impl fmt::Debug for EnvironmentMap {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("EnvironmentMap")
+      .field("width", &self.width)
+      .field("height", &self.height)
+      .field("rotation", &self.rotation)
+      .field("intensity", &self.intensity)
+      .finish()
+  }
+}
+
+impl Environment for EnvironmentMap {
+  fn radiance(&self, direction: Vec3) -> Vec3 {
+    // nearest pixel, so that the radiance is constant where the density is
+    let (u, v) = self.uv(direction.to_unit_vector());
+    let x = (u * self.width as f32) as usize;
+    let y = (v * self.height as f32) as usize;
+    self.intensity * self.pixels[y * self.width + x]
+  }
+
+  fn sample(&self, sampler: &mut dyn Sampler) -> Option<(Vec3, f32)> {
+    let ((u, v), pdf) = self.distribution.sample(sampler.next_2d());
+    let sin_theta = (v * f32::consts::PI).sin();
+    if pdf == 0.0 || sin_theta <= 0.0 {
+      return None;
+    }
+    // the image's area element is 2π² sin θ times the solid angle's
+    let pdf = pdf / (2.0 * f32::consts::PI * f32::consts::PI * sin_theta);
+    Some((self.direction(u, v), pdf))
+  }
+
+  fn pdf(&self, direction: Vec3) -> f32 {
+    let (u, v) = self.uv(direction.to_unit_vector());
+    let sin_theta = (v * f32::consts::PI).sin();
+    if sin_theta <= 0.0 {
+      return 0.0;
+    }
+    self.distribution.pdf(u, v) / (2.0 * f32::consts::PI * f32::consts::PI * sin_theta)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::sampler::IndependentSampler;
+  use super::*;
+
+  /// Dim sky with a bright spot, 32×16.
+  fn sky(rotation: f32) -> EnvironmentMap {
+    let pixels = (0..32 * 16)
+      .map(|i| {
+        if i == 5 * 32 + 20 {
+          Vec3(500.0, 400.0, 300.0)
+        } else {
+          Vec3(0.2, 0.3, (i % 7) as f32 * 0.1)
+        }
+      })
+      .collect();
+    EnvironmentMap::new(32, 16, pixels, rotation, 2.0)
+  }
+
+  #[test]
+  fn directions_and_pixels_correspond() {
+    for rotation in &[0.0, 75.0] {
+      let map = sky(*rotation);
+      for &(u, v) in &[(0.1, 0.2), (0.5, 0.5), (0.9, 0.97), (0.02, 0.6)] {
+        let (u2, v2) = map.uv(map.direction(u, v));
+        assert!((u - u2).abs() < 1e-4 && (v - v2).abs() < 1e-4);
+      }
+    }
+    let map = sky(0.0);
+    assert_eq!(2.0 * Vec3(0.2, 0.3, 0.0), map.radiance(Vec3(0.0, 1.0, 0.0)));
+    // straight ahead is the middle of the image
+    let ahead = map.uv(Vec3(0.0, 0.0, -1.0));
+    assert!((ahead.0 - 0.5).abs() < 1e-6 && (ahead.1 - 0.5).abs() < 1e-6);
+    // turning the map a quarter turn to the left brings what was ahead to the left
+    let turned = sky(90.0);
+    let side = Vec3(-1.0, 0.0, 0.0);
+    assert_eq!(map.radiance(Vec3(0.0, 0.0, -1.0)), turned.radiance(side));
+  }
+
+  #[test]
+  fn sampling_matches_density_and_integrates_radiance() {
+    let map = sky(30.0);
+    let mut sampler = IndependentSampler::new(2);
+    // the irradiance-like integral of the radiance over the sphere, by
+    // importance sampling and by summing pixels times their solid angle
+    let n = 20000;
+    let mut estimate = 0.0;
+    for i in 0..n {
+      sampler.start_sample(0, 0, i);
+      let (direction, pdf) = map.sample(&mut sampler).unwrap();
+      assert!((direction.length() - 1.0).abs() < 1e-4);
+      let relative = (pdf - map.pdf(direction)).abs() / pdf;
+      assert!(relative < 1e-2, "{} {}", pdf, map.pdf(direction));
+      estimate += luminance(map.radiance(direction)) / pdf / n as f32;
+    }
+    let mut exact = 0.0;
+    for y in 0..16 {
+      let theta = (y as f32 + 0.5) / 16.0 * f32::consts::PI;
+      let solid_angle = 2.0 * f32::consts::PI * f32::consts::PI / (32.0 * 16.0) * theta.sin();
+      for x in 0..32 {
+        exact += luminance(2.0 * map.pixels[y * 32 + x]) * solid_angle;
+      }
+    }
+    assert!(
+      (estimate - exact).abs() < 1e-2 * exact,
+      "{} {}",
+      estimate,
+      exact
+    );
+  }
+}
@@ -0,0 +1,168 @@
+use super::filter::Filter;
+use super::image::Image;
+use super::vec3::Vec3;
+
+/// Filter weighted sum of the samples reaching a pixel, and the sum of their
+/// weights.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FilmPixel {
+  pub weighted_sum: Vec3,
+  pub weight: f32,
+}
+
+/// Pixels of a rectangular window of the image that samples are splatted
+/// into: every sample counts towards each pixel within the filter's radius,
+/// weighted by the filter at its offset from the pixel's center.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Film {
+  pub x0: usize,
+  pub y0: usize,
+  pub width: usize,
+  pub height: usize,
+  pixels: Vec<FilmPixel>,
+}
+
+impl Film {
+  pub fn new(width: usize, height: usize) -> Self {
+    Film::window(0, 0, width, height)
+  }
+
+  /// Film for the pixels from (`x0`, `y0`) to (`x0 + width`, `y0 + height`)
+  /// excluded, as rendering a tile needs.
+  pub fn window(x0: usize, y0: usize, width: usize, height: usize) -> Self {
+    Film {
+      x0,
+      y0,
+      width,
+      height,
+      pixels: vec![FilmPixel::default(); width * height],
+    }
+  }
+
+  /// Pixel (`x`, `y`) of the image, which must be in the window.
+  pub fn pixel(&self, x: usize, y: usize) -> &FilmPixel {
+    &self.pixels[(y - self.y0) * self.width + x - self.x0]
+  }
+
+  pub fn pixel_mut(&mut self, x: usize, y: usize) -> &mut FilmPixel {
+    &mut self.pixels[(y - self.y0) * self.width + x - self.x0]
+  }
+
+  /// Splats a sample taken at (`x`, `y`) in image coordinates, where pixel
+  /// (i, j) covers [i, i + 1) × [j, j + 1). Pixels outside the window are
+  /// left out.
+  pub fn add_sample(&mut self, filter: &Filter, x: f32, y: f32, color: Vec3) {
+    let range = |center: f32, start: usize, size: usize| {
+      let first = (center - 0.5 - filter.radius).ceil().max(start as f32) as usize;
+      let last = (center - 0.5 + filter.radius).floor().max(0.0) as usize;
+      first..(last + 1).min(start + size)
+    };
+    for py in range(y, self.y0, self.height) {
+      let wy = filter.evaluate_1d(y - (py as f32 + 0.5));
+      if wy == 0.0 {
+        continue;
+      }
+      for px in range(x, self.x0, self.width) {
+        let weight = wy * filter.evaluate_1d(x - (px as f32 + 0.5));
+        if weight != 0.0 {
+          let pixel = self.pixel_mut(px, py);
+          pixel.weighted_sum += weight * color;
+          pixel.weight += weight;
+        }
+      }
+    }
+  }
+
+  /// Adds the samples splatted into `other`, whose window must lie within
+  /// this one's.
+  pub fn merge(&mut self, other: &Film) {
+    for y in other.y0..other.y0 + other.height {
+      for x in other.x0..other.x0 + other.width {
+        let from = *other.pixel(x, y);
+        let to = self.pixel_mut(x, y);
+        to.weighted_sum += from.weighted_sum;
+        to.weight += from.weight;
+      }
+    }
+  }
+
+  /// The filtered image of the window. Pixels without weight, or with a
+  /// negative one from the lobes of a sharpening filter, are black.
+  pub fn image(&self) -> Image {
+    let mut image = Image::new(self.width, self.height);
+    for y in 0..self.height {
+      for x in 0..self.width {
+        let p = self.pixels[y * self.width + x];
+        if p.weight > 0.0 {
+          image.set(x, y, p.weighted_sum / p.weight);
+        }
+      }
+    }
+    image
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::filter::FilterKind;
+  use super::*;
+
+  #[test]
+  fn box_filter_averages_the_pixels_samples() {
+    let filter = Filter::default();
+    let mut film = Film::new(3, 2);
+    film.add_sample(&filter, 1.0, 0.2, Vec3(1.0, 0.0, 0.0));
+    film.add_sample(&filter, 1.99, 0.9, Vec3(0.0, 1.0, 0.0));
+    film.add_sample(&filter, 2.5, 1.5, Vec3(0.0, 0.0, 1.0));
+    let image = film.image();
+    assert_eq!(Vec3(0.5, 0.5, 0.0), image.get(1, 0));
+    assert_eq!(Vec3(0.0, 0.0, 1.0), image.get(2, 1));
+    assert_eq!(Vec3(0.0, 0.0, 0.0), image.get(0, 0));
+    assert_eq!(0.0, film.pixel(0, 1).weight);
+  }
+
+  #[test]
+  fn wide_filters_reach_neighbours() {
+    let filter = Filter::new(FilterKind::Tent);
+    let mut film = Film::new(3, 3);
+    film.add_sample(&filter, 1.5, 1.5, Vec3(1.0, 1.0, 1.0));
+    film.add_sample(&filter, 1.25, 1.5, Vec3(0.0, 0.0, 0.0));
+    assert_eq!(0.0, film.pixel(0, 0).weight);
+    assert!((film.pixel(1, 1).weight - 1.75).abs() < 1e-6);
+    // only the second sample is within a pixel of (0, 1)'s center
+    assert!((film.pixel(0, 1).weight - 0.25).abs() < 1e-6);
+    assert_eq!(Vec3(0.0, 0.0, 0.0), film.image().get(0, 1));
+    assert!((film.image().get(1, 1).x() - 1.0 / 1.75).abs() < 1e-6);
+  }
+
+  #[test]
+  fn windows_merge_into_the_whole() {
+    let filter = Filter::new(FilterKind::Gaussian);
+    let samples = [(0.3, 0.7), (2.2, 1.1), (3.9, 2.5), (1.5, 2.9)];
+    let mut whole = Film::new(4, 3);
+    let mut merged = Film::new(4, 3);
+    for (i, &(x, y)) in samples.iter().enumerate() {
+      let color = Vec3(i as f32, 1.0, 0.5);
+      whole.add_sample(&filter, x, y, color);
+      // windows around the sample, clipped as for a tile on the border
+      let mut window = Film::window(1, 1, 3, 2);
+      window.add_sample(&filter, x, y, color);
+      let mut rest = Film::new(4, 3);
+      rest.add_sample(&filter, x, y, color);
+      for y in 1..3 {
+        for x in 1..4 {
+          *rest.pixel_mut(x, y) = FilmPixel::default();
+        }
+      }
+      merged.merge(&window);
+      merged.merge(&rest);
+    }
+    for y in 0..3 {
+      for x in 0..4 {
+        let (a, b) = (whole.pixel(x, y), merged.pixel(x, y));
+        assert!((a.weight - b.weight).abs() < 1e-5);
+        assert!((a.weighted_sum - b.weighted_sum).length() < 1e-5);
+      }
+    }
+  }
+}
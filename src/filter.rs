@@ -0,0 +1,201 @@
+extern crate serde;
+
+use serde::Deserialize;
+use std::f32;
+use std::fmt;
+use std::str::FromStr;
+
+/// Shape of a pixel reconstruction filter.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterKind {
+  /// Plain average of the samples within the radius.
+  #[default]
+  Box,
+  Tent,
+  /// Gaussian of standard deviation a third of the radius.
+  Gaussian,
+  /// Mitchell-Netravali cubic with B = C = 1/3.
+  Mitchell,
+  /// Windowed sinc with as many lobes as the radius.
+  Lanczos,
+}
+
+impl FilterKind {
+  /// Radius the filter is usually used with, in pixels.
+  pub fn default_radius(self) -> f32 {
+    match self {
+      FilterKind::Box => 0.5,
+      FilterKind::Tent => 1.0,
+      FilterKind::Gaussian => 1.5,
+      FilterKind::Mitchell => 2.0,
+      FilterKind::Lanczos => 2.0,
+    }
+  }
+}
+
+impl FromStr for FilterKind {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "box" => Ok(FilterKind::Box),
+      "tent" => Ok(FilterKind::Tent),
+      "gaussian" => Ok(FilterKind::Gaussian),
+      "mitchell" => Ok(FilterKind::Mitchell),
+      "lanczos" => Ok(FilterKind::Lanczos),
+      _ => Err(format!(
+        "unknown filter `{}`, expected box, tent, gaussian, mitchell or lanczos",
+        s
+      )),
+    }
+  }
+}
+
+impl fmt::Display for FilterKind {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      FilterKind::Box => "box",
+      FilterKind::Tent => "tent",
+      FilterKind::Gaussian => "gaussian",
+      FilterKind::Mitchell => "mitchell",
+      FilterKind::Lanczos => "lanczos",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+/// Separable reconstruction filter: how much a sample counts towards a
+/// pixel, given its offset from the pixel's center. Weights are normalized
+/// by the film, so filters need not integrate to one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Filter {
+  pub kind: FilterKind,
+  /// Half the width of the filter's support, in pixels.
+  pub radius: f32,
+}
+
+impl Filter {
+  pub fn new(kind: FilterKind) -> Self {
+    Filter {
+      kind,
+      radius: kind.default_radius(),
+    }
+  }
+
+  pub fn with_radius(kind: FilterKind, radius: f32) -> Self {
+    Filter { kind, radius }
+  }
+
+  pub fn evaluate(&self, dx: f32, dy: f32) -> f32 {
+    self.evaluate_1d(dx) * self.evaluate_1d(dy)
+  }
+
+  pub fn evaluate_1d(&self, x: f32) -> f32 {
+    let r = self.radius;
+    // half open, so that a sample on the edge between two pixels only
+    // counts for one with the box filter
+    if !(-r..r).contains(&x) {
+      return 0.0;
+    }
+    match self.kind {
+      FilterKind::Box => 1.0,
+      FilterKind::Tent => r - x.abs(),
+      FilterKind::Gaussian => {
+        let sigma = r / 3.0;
+        let gaussian = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+        (gaussian(x) - gaussian(r)).max(0.0)
+      }
+      FilterKind::Mitchell => mitchell(2.0 * x / r, 1.0 / 3.0, 1.0 / 3.0),
+      FilterKind::Lanczos => sinc(x) * sinc(x / r),
+    }
+  }
+}
+
+impl Default for Filter {
+  fn default() -> Self {
+    Filter::new(FilterKind::Box)
+  }
+}
+
+/// Mitchell-Netravali cubic over [-2, 2].
+fn mitchell(x: f32, b: f32, c: f32) -> f32 {
+  let x = x.abs();
+  if x < 1.0 {
+    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+      + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+      + (6.0 - 2.0 * b))
+      / 6.0
+  } else if x < 2.0 {
+    ((-b - 6.0 * c) * x * x * x
+      + (6.0 * b + 30.0 * c) * x * x
+      + (-12.0 * b - 48.0 * c) * x
+      + (8.0 * b + 24.0 * c))
+      / 6.0
+  } else {
+    0.0
+  }
+}
+
+fn sinc(x: f32) -> f32 {
+  if x.abs() < 1e-5 {
+    1.0
+  } else {
+    let x = f32::consts::PI * x;
+    x.sin() / x
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const KINDS: [FilterKind; 5] = [
+    FilterKind::Box,
+    FilterKind::Tent,
+    FilterKind::Gaussian,
+    FilterKind::Mitchell,
+    FilterKind::Lanczos,
+  ];
+
+  #[test]
+  fn kinds_parse_their_names() {
+    for kind in &KINDS {
+      assert_eq!(Ok(*kind), kind.to_string().parse());
+    }
+    assert!("sinc".parse::<FilterKind>().is_err());
+  }
+
+  #[test]
+  fn filters_peak_at_the_center_and_vanish_at_the_radius() {
+    for kind in &KINDS {
+      let filter = Filter::with_radius(*kind, 1.5);
+      let peak = filter.evaluate(0.0, 0.0);
+      assert!(peak > 0.0, "{}", kind);
+      for i in 1..30 {
+        let x = i as f32 * 0.05;
+        assert!(filter.evaluate(x, 0.0) <= peak, "{}", kind);
+        assert_eq!(
+          filter.evaluate(x, 0.3),
+          filter.evaluate(-x, -0.3),
+          "{}",
+          kind
+        );
+      }
+      assert_eq!(0.0, filter.evaluate(1.5, 0.0), "{}", kind);
+      assert_eq!(0.0, filter.evaluate(0.0, -2.0), "{}", kind);
+    }
+  }
+
+  #[test]
+  fn sharpening_filters_have_negative_lobes() {
+    let mitchell = Filter::new(FilterKind::Mitchell);
+    assert!(mitchell.evaluate_1d(1.5) < 0.0);
+    // the cubic is continuous where its pieces meet
+    assert!((mitchell.evaluate_1d(0.9999) - mitchell.evaluate_1d(1.0001)).abs() < 1e-3);
+    let lanczos = Filter::new(FilterKind::Lanczos);
+    assert!(lanczos.evaluate_1d(1.5) < 0.0);
+    assert!(lanczos.evaluate_1d(1.0).abs() < 1e-6);
+    assert!(Filter::new(FilterKind::Gaussian).evaluate_1d(1.4) > 0.0);
+  }
+}
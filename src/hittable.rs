@@ -1,15 +1,316 @@
+use super::aabb::Aabb;
 use super::material::Material;
+use super::onb::Onb;
 use super::ray::Ray;
+use super::sampler::Sampler;
 use super::vec3::Vec3;
 
 #[derive(Clone, Copy)]
 pub struct HitRecord<'obj> {
   pub t: f32,
   pub p: Vec3,
+  /// Unit shading normal, on the side of the surface the ray came from. It
+  /// differs from `geometric_normal` where normals are interpolated or mapped.
   pub normal: Vec3,
+  /// Unit normal of the surface itself, on the same side as `normal`.
+  pub geometric_normal: Vec3,
+  /// Whether the ray hit the outside of the surface.
+  pub front_face: bool,
+  /// Surface coordinates of `p`, for texture lookups.
+  pub u: f32,
+  pub v: f32,
+  /// How `p` moves along the surface with u and v.
+  pub dpdu: Vec3,
+  pub dpdv: Vec3,
+  /// Index of the primitive hit within its object, e.g. of a mesh's triangle.
+  pub primitive_id: u32,
+  /// Index of the scene object hit, for objects that are `Tagged` with one.
+  pub object_id: Option<u32>,
   pub material: &'obj dyn Material,
 }
 
-pub trait Hittable {
-  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+impl<'obj> HitRecord<'obj> {
+  /// Hit of `r` at `t` on a surface whose unit normal out of it is
+  /// `outward_normal`, turned to face where `r` comes from. Tangents are 0
+  /// until `with_uv` gives them.
+  pub fn new(r: &Ray, t: f32, outward_normal: Vec3, material: &'obj dyn Material) -> Self {
+    let front_face = r.direction.dot(outward_normal) < 0.0;
+    let normal = if front_face {
+      outward_normal
+    } else {
+      -outward_normal
+    };
+    HitRecord {
+      t,
+      p: r.point_at_parameter(t),
+      normal,
+      geometric_normal: normal,
+      front_face,
+      u: 0.0,
+      v: 0.0,
+      dpdu: Vec3::default(),
+      dpdv: Vec3::default(),
+      primitive_id: 0,
+      object_id: None,
+      material,
+    }
+  }
+
+  /// At surface coordinates (u, v), along which `p` moves by `dpdu` and
+  /// `dpdv`. Tangents that do not span a plane, as at the poles of a
+  /// sphere, are replaced by made up ones around the normal.
+  pub fn with_uv(self, u: f32, v: f32, dpdu: Vec3, dpdv: Vec3) -> Self {
+    let (dpdu, dpdv) = if dpdu.cross(dpdv).squared_length() > 0.0 {
+      (dpdu, dpdv)
+    } else {
+      let frame = Onb::from_w(self.geometric_normal);
+      (frame.u, frame.v)
+    };
+    HitRecord {
+      u,
+      v,
+      dpdu,
+      dpdv,
+      ..self
+    }
+  }
+
+  /// With the unit shading normal `outward`, e.g. interpolated from vertex
+  /// normals, turned to the side of the geometric normal.
+  pub fn with_shading_normal(self, outward: Vec3) -> Self {
+    let normal = if self.front_face { outward } else { -outward };
+    HitRecord { normal, ..self }
+  }
+
+  pub fn with_primitive_id(self, primitive_id: u32) -> Self {
+    HitRecord {
+      primitive_id,
+      ..self
+    }
+  }
+
+  /// Shading normal pointing out of the surface, whichever side was hit.
+  pub fn outward_normal(&self) -> Vec3 {
+    if self.front_face {
+      self.normal
+    } else {
+      -self.normal
+    }
+  }
+}
+
+/// Stretch of a ray inside a solid, from the hit entering it to the hit
+/// leaving it.
+#[derive(Clone, Copy)]
+pub struct Span<'obj> {
+  pub enter: HitRecord<'obj>,
+  pub exit: HitRecord<'obj>,
+}
+
+/// Spans between successive `hits` of a closed surface, which are sorted
+/// by t first. A last hit without a partner, from a ray grazing the
+/// surface, is dropped.
+pub fn spans_between<'obj>(mut hits: Vec<HitRecord<'obj>>) -> Vec<Span<'obj>> {
+  hits.sort_by(|a, b| a.t.total_cmp(&b.t));
+  hits
+    .chunks_exact(2)
+    .map(|pair| Span {
+      enter: pair[0],
+      exit: pair[1],
+    })
+    .collect()
+}
+
+pub trait Hittable: Send + Sync {
+  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
+
+  /// `hit`, drawing its random decisions, such as where a ray scatters in a
+  /// medium, from `sampler`. Integrators trace their rays with this.
+  fn hit_sampled(
+    &self,
+    r: &Ray,
+    t_min: f32,
+    t_max: f32,
+    _sampler: &mut dyn Sampler,
+  ) -> Option<HitRecord<'_>> {
+    self.hit(r, t_min, t_max)
+  }
+
+  /// Box enclosing the object, `None` for unbounded objects.
+  fn bounding_box(&self) -> Option<Aabb>;
+
+  /// Solid angle density with which `random` picks `direction` from `origin`
+  /// at `time`, 0 for objects that cannot be sampled as lights.
+  fn pdf_value(&self, _origin: Vec3, _direction: Vec3, _time: f32) -> f32 {
+    0.0
+  }
+
+  /// Every stretch of the line through `r`, at any t including negative
+  /// ones, that lies inside the object, in order along it. `None` for
+  /// objects that do not enclose a volume, which cannot be combined by `Csg`.
+  fn spans(&self, _r: &Ray) -> Option<Vec<Span<'_>>> {
+    None
+  }
+
+  /// Random direction from `origin` towards a point of the object as it is at `time`.
+  fn random(&self, _origin: Vec3, _time: f32, _sampler: &mut dyn Sampler) -> Vec3 {
+    Vec3(1.0, 0.0, 0.0)
+  }
+}
+
+/// `object` with `id`, its index in the scene, stamped on its hits.
+pub struct Tagged {
+  pub object: Box<dyn Hittable>,
+  pub id: u32,
+}
+
+impl Tagged {
+  pub fn new(object: Box<dyn Hittable>, id: u32) -> Self {
+    Tagged { object, id }
+  }
+
+  fn tag<'a>(&self, rec: HitRecord<'a>) -> HitRecord<'a> {
+    HitRecord {
+      object_id: Some(self.id),
+      ..rec
+    }
+  }
+}
+
+impl Hittable for Tagged {
+  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+    self.object.hit(r, t_min, t_max).map(|rec| self.tag(rec))
+  }
+
+  fn hit_sampled(
+    &self,
+    r: &Ray,
+    t_min: f32,
+    t_max: f32,
+    sampler: &mut dyn Sampler,
+  ) -> Option<HitRecord<'_>> {
+    self
+      .object
+      .hit_sampled(r, t_min, t_max, sampler)
+      .map(|rec| self.tag(rec))
+  }
+
+  fn spans(&self, r: &Ray) -> Option<Vec<Span<'_>>> {
+    let spans = self.object.spans(r)?;
+    Some(
+      spans
+        .into_iter()
+        .map(|span| Span {
+          enter: self.tag(span.enter),
+          exit: self.tag(span.exit),
+        })
+        .collect(),
+    )
+  }
+
+  fn bounding_box(&self) -> Option<Aabb> {
+    self.object.bounding_box()
+  }
+
+  fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+    self.object.pdf_value(origin, direction, time)
+  }
+
+  fn random(&self, origin: Vec3, time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+    self.object.random(origin, time, sampler)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::cone::Cone;
+  use super::super::cuboid::Cuboid;
+  use super::super::cylinder::Cylinder;
+  use super::super::disk::Disk;
+  use super::super::material::Lambertian;
+  use super::super::plane::Plane;
+  use super::super::rect::AxisRect;
+  use super::super::sphere::Sphere;
+  use super::super::torus::Torus;
+  use super::*;
+  use std::f32;
+
+  fn gray() -> Box<dyn Material> {
+    Box::new(Lambertian::new(Vec3(0.5, 0.5, 0.5)))
+  }
+
+  #[test]
+  fn normals_face_the_ray() {
+    let material = Lambertian::new(Vec3(0.5, 0.5, 0.5));
+    let up = Vec3(0.0, 1.0, 0.0);
+    let rec = HitRecord::new(&Ray::new(up, -up), 1.0, up, &material);
+    assert!(rec.front_face);
+    assert_eq!(
+      (up, up, up),
+      (rec.normal, rec.geometric_normal, rec.outward_normal())
+    );
+    let rec = HitRecord::new(&Ray::new(-up, up), 1.0, up, &material);
+    assert!(!rec.front_face);
+    assert_eq!((-up, up), (rec.normal, rec.outward_normal()));
+    assert_eq!(Vec3(0.0, 0.0, 0.0), rec.p);
+    // a shading normal goes to the side the geometric one is on
+    let tilted = Vec3(1.0, 1.0, 0.0).to_unit_vector();
+    let rec = rec.with_shading_normal(tilted);
+    assert_eq!((-tilted, -up), (rec.normal, rec.geometric_normal));
+  }
+
+  #[test]
+  fn every_shape_has_tangents() {
+    let shapes: Vec<Box<dyn Hittable>> = vec![
+      Box::new(Sphere::new(Vec3(0.0, 0.0, 0.0), 1.0, gray())),
+      Box::new(Plane::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 1.0, 1.0), gray())),
+      Box::new(Disk::new(
+        Vec3(0.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 1.0),
+        1.0,
+        gray(),
+      )),
+      Box::new(AxisRect::xz(-1.0, 1.0, -1.0, 1.0, 0.0, gray())),
+      Box::new(Cuboid::new(
+        Vec3(-1.0, -1.0, -1.0),
+        Vec3(1.0, 1.0, 1.0),
+        gray(),
+      )),
+      Box::new(Cylinder::new(Vec3(0.0, -1.0, 0.0), 1.0, 2.0, gray())),
+      Box::new(Cone::new(Vec3(0.0, -1.0, 0.0), 1.0, 2.0, gray())),
+      Box::new(Torus::new(Vec3(0.0, 0.0, 0.0), 1.0, 0.5, gray())),
+    ];
+    let rays = [
+      Ray::new(Vec3(0.3, 5.0, 0.4), Vec3(0.0, -1.0, 0.0)),
+      Ray::new(Vec3(5.0, 0.2, 0.1), Vec3(-1.0, 0.0, -0.2)),
+      Ray::new(Vec3(0.1, 0.0, 0.2), Vec3(0.3, 0.2, 1.0)),
+    ];
+    for (i, shape) in shapes.iter().enumerate() {
+      for r in &rays {
+        if let Some(rec) = shape.hit(r, 0.001, f32::MAX) {
+          let n = rec.geometric_normal;
+          assert!((n.length() - 1.0).abs() < 1e-5);
+          assert!(n.dot(r.direction) < 0.0, "shape {}", i);
+          for tangent in &[rec.dpdu, rec.dpdv] {
+            assert!(tangent.length() > 1e-3, "shape {}", i);
+            assert!(tangent.to_unit_vector().dot(n).abs() < 1e-4, "shape {}", i);
+          }
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn tags_stamp_object_ids() {
+    let sphere = Box::new(Sphere::new(Vec3(0.0, 0.0, -2.0), 1.0, gray()));
+    let r = Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, -1.0));
+    assert_eq!(None, sphere.hit(&r, 0.001, f32::MAX).unwrap().object_id);
+    let tagged = Tagged::new(sphere, 7);
+    assert_eq!(Some(7), tagged.hit(&r, 0.001, f32::MAX).unwrap().object_id);
+    assert!(tagged
+      .spans(&r)
+      .unwrap()
+      .iter()
+      .all(|span| span.exit.object_id == Some(7)));
+  }
 }
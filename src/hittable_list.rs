@@ -1,9 +1,12 @@
+use super::aabb::Aabb;
 use super::hittable::HitRecord;
 use super::hittable::Hittable;
 use super::ray::Ray;
+use super::sampler::Sampler;
+use super::vec3::Vec3;
 
 impl Hittable for Vec<Box<dyn Hittable>> {
-  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
     let mut best = None;
     for child in self {
       if let Some(hit) = child.hit(r, t_min, t_max) {
@@ -19,13 +22,53 @@ impl Hittable for Vec<Box<dyn Hittable>> {
     }
     best
   }
+
+  fn hit_sampled(
+    &self,
+    r: &Ray,
+    t_min: f32,
+    t_max: f32,
+    sampler: &mut dyn Sampler,
+  ) -> Option<HitRecord<'_>> {
+    self
+      .iter()
+      .filter_map(|child| child.hit_sampled(r, t_min, t_max, sampler))
+      .min_by(|a, b| a.t.total_cmp(&b.t))
+  }
+
+  /// Lights sampled as one: the average of the children's densities.
+  fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+    if self.is_empty() {
+      return 0.0;
+    }
+    let sum: f32 = self
+      .iter()
+      .map(|c| c.pdf_value(origin, direction, time))
+      .sum();
+    sum / self.len() as f32
+  }
+
+  fn random(&self, origin: Vec3, time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+    if self.is_empty() {
+      return Vec3(1.0, 0.0, 0.0);
+    }
+    let index = ((sampler.next_1d() * self.len() as f32) as usize).min(self.len() - 1);
+    self[index].random(origin, time, sampler)
+  }
+
+  fn bounding_box(&self) -> Option<Aabb> {
+    let mut children = self.iter();
+    let first = children.next()?.bounding_box()?;
+    children.try_fold(first, |acc, child| {
+      child.bounding_box().map(|b| Aabb::surrounding(&acc, &b))
+    })
+  }
 }
 
 #[cfg(test)]
 mod tests {
   use super::super::material::Lambertian;
   use super::super::sphere::Sphere;
-  use super::super::vec3::Vec3;
   use super::*;
 
   #[test]
@@ -47,10 +90,15 @@ mod tests {
       .into_iter()
       .map(|s| Box::new(s) as Box<dyn Hittable>)
       .collect();
-    let rec = list.hit(&r, 0.0001, std::f32::MAX);
+    let rec = list.hit(&r, 0.0001, f32::MAX);
     assert!(rec.is_some());
     assert_eq!(4.236068, rec.unwrap().t);
     assert_eq!(Vec3(4.236068, 0.0, 0.0), rec.unwrap().p);
-    assert_eq!(Vec3(0.74535596, -0.6666667, 0.0), rec.unwrap().normal);
+    // from inside, with the normal turned back towards the ray
+    assert_eq!(
+      Vec3(0.74535596, -0.6666667, 0.0),
+      rec.unwrap().outward_normal()
+    );
+    assert!(!rec.unwrap().front_face);
   }
 }
@@ -0,0 +1,45 @@
+use super::vec3::Vec3;
+
+/// Linear color framebuffer, stored row by row starting from the top row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+  pub width: usize,
+  pub height: usize,
+  pixels: Vec<Vec3>,
+}
+
+impl Image {
+  pub fn new(width: usize, height: usize) -> Self {
+    Image {
+      width,
+      height,
+      pixels: vec![Vec3(0.0, 0.0, 0.0); width * height],
+    }
+  }
+
+  pub fn get(&self, x: usize, y: usize) -> Vec3 {
+    self.pixels[y * self.width + x]
+  }
+
+  pub fn set(&mut self, x: usize, y: usize, color: Vec3) {
+    self.pixels[y * self.width + x] = color;
+  }
+
+  pub fn pixels(&self) -> &[Vec3] {
+    &self.pixels
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn set_pixel() {
+    let mut image = Image::new(3, 2);
+    image.set(2, 1, Vec3(1.0, 0.5, 0.25));
+    assert_eq!(Vec3(1.0, 0.5, 0.25), image.get(2, 1));
+    assert_eq!(Vec3(1.0, 0.5, 0.25), image.pixels()[5]);
+    assert_eq!(Vec3(0.0, 0.0, 0.0), image.get(1, 1));
+  }
+}
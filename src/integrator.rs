@@ -0,0 +1,258 @@
+extern crate serde;
+
+use super::hittable::Hittable;
+use super::ray::Ray;
+use super::render::RenderSettings;
+use super::sampler::Sampler;
+use super::vec3::Vec3;
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// How the radiance arriving along a camera ray is estimated.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Integrator {
+  /// Follows scattered rays only and finds lights by chance, as in the book.
+  Path,
+  /// Next-event estimation: samples a light as well as the BSDF at every
+  /// bounce, weighting both with the balance heuristic.
+  MisBalance,
+  /// Next-event estimation weighted with the power heuristic (exponent 2).
+  #[default]
+  MisPower,
+}
+
+impl FromStr for Integrator {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "path" => Ok(Integrator::Path),
+      "mis_balance" => Ok(Integrator::MisBalance),
+      "mis_power" => Ok(Integrator::MisPower),
+      _ => Err(format!(
+        "unknown integrator `{}`, expected path, mis_balance or mis_power",
+        s
+      )),
+    }
+  }
+}
+
+impl fmt::Display for Integrator {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      Integrator::Path => "path",
+      Integrator::MisBalance => "mis_balance",
+      Integrator::MisPower => "mis_power",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+/// Radiance arriving at the origin of `r`. `lights` are the objects sampled
+/// directly by the MIS integrators.
+pub fn radiance(
+  r: &Ray,
+  world: &dyn Hittable,
+  lights: &dyn Hittable,
+  settings: &RenderSettings,
+  sampler: &mut dyn Sampler,
+) -> Vec3 {
+  match settings.integrator {
+    Integrator::Path => path_radiance(r, world, settings, sampler, 0),
+    Integrator::MisBalance => mis_radiance(r, world, lights, settings, sampler, balance_heuristic),
+    Integrator::MisPower => mis_radiance(r, world, lights, settings, sampler, power_heuristic),
+  }
+}
+
+fn path_radiance(
+  r: &Ray,
+  world: &dyn Hittable,
+  settings: &RenderSettings,
+  sampler: &mut dyn Sampler,
+  depth: i32,
+) -> Vec3 {
+  match world.hit_sampled(r, 0.001, f32::MAX, sampler) {
+    Some(rec) => {
+      let emitted = rec.material.emitted(r, &rec);
+      if depth < settings.max_depth {
+        if let Some(s) = rec.material.scatter(r, &rec, sampler) {
+          return emitted
+            + s.attenuation * path_radiance(&s.scattered, world, settings, sampler, depth + 1);
+        }
+      }
+      emitted
+    }
+    None => settings.background.radiance(r.direction),
+  }
+}
+
+/// Weight of a sample drawn with density `pdf` among one drawn with `other_pdf`.
+fn balance_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+  pdf / (pdf + other_pdf)
+}
+
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+  let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+  if a.is_infinite() {
+    1.0
+  } else {
+    a / (a + b)
+  }
+}
+
+fn mis_radiance(
+  r: &Ray,
+  world: &dyn Hittable,
+  lights: &dyn Hittable,
+  settings: &RenderSettings,
+  sampler: &mut dyn Sampler,
+  weight: fn(f32, f32) -> f32,
+) -> Vec3 {
+  let mut radiance = Vec3(0.0, 0.0, 0.0);
+  let mut throughput = Vec3(1.0, 1.0, 1.0);
+  let mut ray = *r;
+  // density the BSDF picked `ray` with, `None` for camera and specular rays
+  // whose hits on lights were not light sampled and count in full
+  let mut bsdf_pdf: Option<f32> = None;
+  for depth in 0..=settings.max_depth {
+    let rec = match world.hit_sampled(&ray, 0.001, f32::MAX, sampler) {
+      Some(rec) => rec,
+      None => {
+        let w = match bsdf_pdf {
+          Some(pdf) => weight(pdf, settings.background.pdf(ray.direction)),
+          None => 1.0,
+        };
+        radiance += w * throughput * settings.background.radiance(ray.direction);
+        break;
+      }
+    };
+
+    let emitted = rec.material.emitted(&ray, &rec);
+    if emitted != Vec3(0.0, 0.0, 0.0) {
+      let w = match bsdf_pdf {
+        Some(pdf) => weight(pdf, lights.pdf_value(ray.origin, ray.direction, ray.time)),
+        None => 1.0,
+      };
+      radiance += w * throughput * emitted;
+    }
+    if depth == settings.max_depth {
+      break;
+    }
+    let scatter = match rec.material.scatter(&ray, &rec, sampler) {
+      Some(scatter) => scatter,
+      None => break,
+    };
+
+    if scatter.pdf.is_some() {
+      let direction = lights.random(rec.p, ray.time, sampler);
+      let light_pdf = lights.pdf_value(rec.p, direction, ray.time);
+      if light_pdf > 0.0 {
+        let f = rec.material.eval(&ray, &rec, direction);
+        if f != Vec3(0.0, 0.0, 0.0) {
+          let shadow = Ray::new_at_time(rec.p, direction, ray.time);
+          if let Some(light_rec) = world.hit_sampled(&shadow, 0.001, f32::MAX, sampler) {
+            let emitted = light_rec.material.emitted(&shadow, &light_rec);
+            let w = weight(light_pdf, rec.material.pdf(&ray, &rec, direction));
+            radiance += (w / light_pdf) * throughput * f * emitted;
+          }
+        }
+      }
+      // and the environment, if it is seen past everything else
+      if let Some((direction, environment_pdf)) = settings.background.sample(sampler) {
+        let f = rec.material.eval(&ray, &rec, direction);
+        let shadow = Ray::new_at_time(rec.p, direction, ray.time);
+        if f != Vec3(0.0, 0.0, 0.0)
+          && world
+            .hit_sampled(&shadow, 0.001, f32::MAX, sampler)
+            .is_none()
+        {
+          let w = weight(environment_pdf, rec.material.pdf(&ray, &rec, direction));
+          radiance +=
+            (w / environment_pdf) * throughput * f * settings.background.radiance(direction);
+        }
+      }
+    }
+
+    throughput *= scatter.attenuation;
+    bsdf_pdf = scatter.pdf;
+    ray = scatter.scattered;
+  }
+  radiance
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::environment::EnvironmentMap;
+  use super::super::material::Lambertian;
+  use super::super::sampler::IndependentSampler;
+  use super::super::sphere::Sphere;
+  use super::*;
+  use std::sync::Arc;
+
+  #[test]
+  fn heuristics() {
+    assert_eq!(0.75, balance_heuristic(3.0, 1.0));
+    assert_eq!(0.9, power_heuristic(3.0, 1.0));
+    assert_eq!(1.0, power_heuristic(2.0, 0.0));
+    assert_eq!(1.0, power_heuristic(f32::INFINITY, 2.0));
+  }
+
+  #[test]
+  fn parse_names() {
+    for integrator in &[
+      Integrator::Path,
+      Integrator::MisBalance,
+      Integrator::MisPower,
+    ] {
+      assert_eq!(Ok(*integrator), integrator.to_string().parse());
+    }
+    assert!("bdpt".parse::<Integrator>().is_err());
+  }
+
+  #[test]
+  fn sampled_environment_agrees_with_path_tracing() {
+    // gray ball under a sky of bright top half and dim bottom half, where
+    // paths leave after one bounce
+    let pixels = (0..16 * 8)
+      .map(|i| {
+        if i < 16 * 4 {
+          Vec3(2.0, 2.0, 2.0)
+        } else {
+          Vec3(0.1, 0.1, 0.1)
+        }
+      })
+      .collect();
+    let world: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(
+      Vec3(0.0, 0.0, 0.0),
+      1.0,
+      Box::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+    ))];
+    let lights: Vec<Box<dyn Hittable>> = Vec::new();
+    let r = Ray::new(Vec3(0.0, 0.0, 3.0), Vec3(0.0, 0.0, -1.0));
+    let mut settings = RenderSettings {
+      background: Arc::new(EnvironmentMap::new(16, 8, pixels, 0.0, 1.0)),
+      ..RenderSettings::default()
+    };
+    let mut mean = |integrator| {
+      settings.integrator = integrator;
+      let mut sampler = IndependentSampler::new(5);
+      let n = 20000;
+      (0..n)
+        .map(|i| {
+          sampler.start_sample(0, 0, i);
+          radiance(&r, &world, &lights, &settings, &mut sampler).x()
+        })
+        .sum::<f32>()
+        / n as f32
+    };
+    // half the light of the sky's upper half reaches a side facing point
+    let path = mean(Integrator::Path);
+    assert!((path - 0.525).abs() < 0.02, "{}", path);
+    for integrator in &[Integrator::MisBalance, Integrator::MisPower] {
+      let mis = mean(*integrator);
+      assert!((mis - 0.525).abs() < 0.01, "{} {}", integrator, mis);
+    }
+  }
+}
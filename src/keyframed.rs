@@ -0,0 +1,272 @@
+use super::aabb::Aabb;
+use super::hittable::{HitRecord, Hittable, Span};
+use super::mat4::Mat4;
+use super::ray::Ray;
+use super::sampler::Sampler;
+use super::transformed::{transformed_box, Placement};
+use super::vec3::Vec3;
+use std::f32;
+
+/// Steps between two keys at which the bounding box encloses the object.
+const BOX_STEPS: usize = 8;
+
+/// Scaling, then rotation about x, y and z in degrees, then translation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pose {
+  pub scale: Vec3,
+  pub rotate: Vec3,
+  pub translate: Vec3,
+}
+
+impl Default for Pose {
+  fn default() -> Self {
+    Pose {
+      scale: Vec3(1.0, 1.0, 1.0),
+      rotate: Vec3::default(),
+      translate: Vec3::default(),
+    }
+  }
+}
+
+impl Pose {
+  pub fn translation(offset: Vec3) -> Self {
+    Pose {
+      translate: offset,
+      ..Pose::default()
+    }
+  }
+
+  pub fn matrix(&self) -> Mat4 {
+    Mat4::translation(self.translate)
+      * Mat4::rotation(Vec3(0.0, 0.0, 1.0), self.rotate.z())
+      * Mat4::rotation(Vec3(0.0, 1.0, 0.0), self.rotate.y())
+      * Mat4::rotation(Vec3(1.0, 0.0, 0.0), self.rotate.x())
+      * Mat4::scaling(self.scale)
+  }
+
+  /// `s` of the way from `self` to `other`, component by component.
+  fn lerp(&self, other: &Pose, s: f32) -> Pose {
+    let mix = |a: Vec3, b: Vec3| a + s * (b - a);
+    Pose {
+      scale: mix(self.scale, other.scale),
+      rotate: mix(self.rotate, other.rotate),
+      translate: mix(self.translate, other.translate),
+    }
+  }
+}
+
+/// Moves, turns and scales `object` through `(time, pose)` keyframes: poses
+/// are interpolated linearly between keys and held before the first one and
+/// after the last one, so any shutter interval sees the object somewhere on
+/// that path.
+pub struct Keyframed {
+  pub object: Box<dyn Hittable>,
+  keys: Vec<(f32, Pose)>,
+}
+
+impl Keyframed {
+  /// `keys` get sorted by time, there must be at least one.
+  pub fn new(object: Box<dyn Hittable>, keys: Vec<(f32, Pose)>) -> Self {
+    assert!(!keys.is_empty(), "keyframed object without keys");
+    let mut keys = keys;
+    keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Keyframed { object, keys }
+  }
+
+  pub fn pose(&self, time: f32) -> Pose {
+    let next = self.keys.partition_point(|key| key.0 <= time);
+    if next == 0 {
+      return self.keys[0].1;
+    }
+    if next == self.keys.len() {
+      return self.keys[next - 1].1;
+    }
+    let (t0, pose0) = self.keys[next - 1];
+    let (t1, pose1) = self.keys[next];
+    pose0.lerp(&pose1, (time - t0) / (t1 - t0))
+  }
+
+  /// None while the pose scales by 0 on some axis.
+  fn placement(&self, time: f32) -> Option<Placement> {
+    Placement::new(self.pose(time).matrix())
+  }
+}
+
+impl Hittable for Keyframed {
+  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+    self
+      .placement(r.time)?
+      .hit_with(r, |local| self.object.hit(local, t_min, t_max))
+  }
+
+  fn hit_sampled(
+    &self,
+    r: &Ray,
+    t_min: f32,
+    t_max: f32,
+    sampler: &mut dyn Sampler,
+  ) -> Option<HitRecord<'_>> {
+    self.placement(r.time)?.hit_with(r, |local| {
+      self.object.hit_sampled(local, t_min, t_max, sampler)
+    })
+  }
+
+  fn spans(&self, r: &Ray) -> Option<Vec<Span<'_>>> {
+    // flattened to nothing, there is no inside
+    self
+      .placement(r.time)
+      .map_or(Some(Vec::new()), |placement| {
+        placement.spans(&*self.object, r)
+      })
+  }
+
+  /// The object's boxes at steps along the path between every two keys,
+  /// grown while it turns by half of how far any of its points can travel to
+  /// the next step, so that they enclose it in between.
+  fn bounding_box(&self) -> Option<Aabb> {
+    let bbox = self.object.bounding_box()?;
+    // distance of the box's farthest corner from the object's origin
+    let far = |a: f32, b: f32| a.abs().max(b.abs());
+    let reach = Vec3(
+      far(bbox.min.x(), bbox.max.x()),
+      far(bbox.min.y(), bbox.max.y()),
+      far(bbox.min.z(), bbox.max.z()),
+    )
+    .length();
+    let largest = |v: Vec3| v.x().abs().max(v.y().abs()).max(v.z().abs());
+    let padded = |pose: &Pose, pad: f32| {
+      let b = transformed_box(&pose.matrix(), &bbox);
+      let pad = Vec3(pad, pad, pad);
+      Aabb::new(b.min - pad, b.max + pad)
+    };
+    let mut result = padded(&self.keys[0].1, 0.0);
+    for pair in self.keys.windows(2) {
+      let (from, to) = (pair[0].1, pair[1].1);
+      for step in 0..BOX_STEPS {
+        let a = from.lerp(&to, step as f32 / BOX_STEPS as f32);
+        let b = from.lerp(&to, (step + 1) as f32 / BOX_STEPS as f32);
+        let turn = (b.rotate - a.rotate).x().abs()
+          + (b.rotate - a.rotate).y().abs()
+          + (b.rotate - a.rotate).z().abs();
+        // without turning every point moves in a straight line, otherwise
+        // this bounds the length of its path, and every point of a path is
+        // within half its length of one end
+        let travel = if turn == 0.0 {
+          0.0
+        } else {
+          let scale = largest(a.scale).max(largest(b.scale));
+          (b.translate - a.translate).length()
+            + reach * (largest(b.scale - a.scale) + scale * turn.to_radians())
+        };
+        result = Aabb::surrounding(&result, &padded(&a, 0.5 * travel));
+        result = Aabb::surrounding(&result, &padded(&b, 0.5 * travel));
+      }
+    }
+    Some(result)
+  }
+
+  fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+    self.placement(time).map_or(0.0, |placement| {
+      placement.pdf_value(&*self.object, origin, direction, time)
+    })
+  }
+
+  fn random(&self, origin: Vec3, time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+    match self.placement(time) {
+      Some(placement) => placement.random(&*self.object, origin, time, sampler),
+      None => self.object.random(origin, time, sampler),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::cuboid::Cuboid;
+  use super::super::material::Lambertian;
+  use super::super::sphere::Sphere;
+  use super::*;
+
+  fn grey() -> Box<Lambertian> {
+    Box::new(Lambertian::new(Vec3(0.5, 0.5, 0.5)))
+  }
+
+  #[test]
+  fn follows_keys() {
+    let sphere = Sphere::new(Vec3(0.0, 0.0, 0.0), 1.0, grey());
+    let keyframed = Keyframed::new(
+      Box::new(sphere),
+      vec![
+        (1.0, Pose::translation(Vec3(0.0, 4.0, 0.0))),
+        (0.0, Pose::translation(Vec3(0.0, 0.0, 0.0))),
+      ],
+    );
+    assert_eq!(Vec3(0.0, 0.0, 0.0), keyframed.pose(-1.0).translate);
+    assert_eq!(Vec3(0.0, 1.0, 0.0), keyframed.pose(0.25).translate);
+    assert_eq!(Vec3(0.0, 4.0, 0.0), keyframed.pose(2.0).translate);
+
+    let r = Ray::new_at_time(Vec3(0.0, 2.0, 5.0), Vec3(0.0, 0.0, -1.0), 0.5);
+    let rec = keyframed.hit(&r, 0.001, f32::MAX).unwrap();
+    assert_eq!(4.0, rec.t);
+    assert_eq!(Vec3(0.0, 2.0, 1.0), rec.p);
+    assert!(keyframed
+      .hit(&Ray::new(r.origin, r.direction), 0.001, f32::MAX)
+      .is_none());
+
+    let bbox = keyframed.bounding_box().unwrap();
+    assert_eq!(Vec3(-1.0, -1.0, -1.0), bbox.min);
+    assert_eq!(Vec3(1.0, 5.0, 1.0), bbox.max);
+  }
+
+  #[test]
+  fn turns_and_scales() {
+    // a plank along x, turning a quarter about y while doubling in length
+    let plank = Cuboid::new(Vec3(0.0, -0.1, -0.1), Vec3(1.0, 0.1, 0.1), grey());
+    let keyframed = Keyframed::new(
+      Box::new(plank),
+      vec![
+        (0.0, Pose::default()),
+        (
+          1.0,
+          Pose {
+            scale: Vec3(2.0, 1.0, 1.0),
+            rotate: Vec3(0.0, 90.0, 0.0),
+            translate: Vec3::default(),
+          },
+        ),
+      ],
+    );
+    let down = Vec3(0.0, -1.0, 0.0);
+    let above = |x: f32, z: f32| Vec3(x, 5.0, z);
+    assert!(keyframed
+      .hit(
+        &Ray::new_at_time(above(0.9, 0.0), down, 0.0),
+        0.001,
+        f32::MAX
+      )
+      .is_some());
+    // halfway it points along (1, 0, -1) and is 1.5 long
+    let r = Ray::new_at_time(above(1.0, -1.0), down, 0.5);
+    let rec = keyframed.hit(&r, 0.001, f32::MAX).unwrap();
+    assert!((4.9 - rec.t).abs() < 1e-5);
+    assert!((Vec3(0.0, 1.0, 0.0) - rec.normal).length() < 1e-5);
+    assert!(keyframed
+      .hit(
+        &Ray::new_at_time(above(0.9, 0.0), down, 0.5),
+        0.001,
+        f32::MAX
+      )
+      .is_none());
+    let r = Ray::new_at_time(above(0.0, -1.9), down, 1.0);
+    assert!(keyframed.hit(&r, 0.001, f32::MAX).is_some());
+
+    // the box holds the plank whenever it is sampled
+    let bbox = keyframed.bounding_box().unwrap();
+    for i in 0..=100 {
+      let pose = keyframed.pose(i as f32 / 100.0);
+      let b = transformed_box(&pose.matrix(), &keyframed.object.bounding_box().unwrap());
+      for (inner, outer) in &[(b.min, bbox.min), (bbox.max, b.max)] {
+        assert!(inner.x() >= outer.x() && inner.y() >= outer.y() && inner.z() >= outer.z());
+      }
+    }
+  }
+}
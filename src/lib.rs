@@ -0,0 +1,89 @@
+//! Ray tracer from the _Ray Tracing in One Weekend_ series.
+//!
+//! The binary in `main.rs` is a thin wrapper over this library: build a world
+//! out of `Hittable`s, point a `Camera` at it and call `render`.
+
+pub mod aabb;
+pub mod bump;
+pub mod bvh;
+pub mod camera;
+pub mod checkpoint;
+pub mod cone;
+pub mod constant_medium;
+pub mod csg;
+pub mod cuboid;
+pub mod cylinder;
+pub mod disk;
+pub mod distribution;
+pub mod environment;
+pub mod film;
+pub mod filter;
+pub mod hittable;
+pub mod hittable_list;
+pub mod image;
+pub mod integrator;
+pub mod keyframed;
+pub mod mat4;
+pub mod material;
+pub mod microfacet;
+pub mod moving_sphere;
+pub mod obj;
+pub mod onb;
+pub mod output;
+pub mod perlin;
+pub mod plane;
+pub mod progress;
+pub mod random;
+pub mod ray;
+pub mod rect;
+pub mod render;
+pub mod sampler;
+pub mod scene;
+pub mod sphere;
+pub mod texture;
+pub mod tonemap;
+pub mod torus;
+pub mod transformed;
+pub mod triangle;
+pub mod vec3;
+
+pub use aabb::Aabb;
+pub use bump::{BumpMap, Bumped};
+pub use bvh::BvhNode;
+pub use camera::Camera;
+pub use cone::Cone;
+pub use constant_medium::ConstantMedium;
+pub use csg::{Csg, CsgOp};
+pub use cuboid::Cuboid;
+pub use cylinder::Cylinder;
+pub use disk::Disk;
+pub use environment::{Environment, EnvironmentMap, Gradient, Uniform};
+pub use film::{Film, FilmPixel};
+pub use filter::{Filter, FilterKind};
+pub use hittable::{HitRecord, Hittable, Span};
+pub use image::Image;
+pub use integrator::Integrator;
+pub use keyframed::{Keyframed, Pose};
+pub use mat4::Mat4;
+pub use material::{
+  Dielectric, DiffuseLight, HenyeyGreenstein, Isotropic, Lambertian, Material, Metal,
+  RoughConductor, RoughDielectric, Scatter,
+};
+pub use microfacet::{Conductor, Ggx};
+pub use moving_sphere::MovingSphere;
+pub use plane::Plane;
+pub use progress::{PixelStats, Progress};
+pub use ray::Ray;
+pub use rect::AxisRect;
+pub use render::{render, render_progressive, RenderSettings};
+pub use sampler::{
+  BlueNoiseSampler, HaltonSampler, IndependentSampler, Sampler, SamplerKind, SobolSampler,
+  StratifiedSampler,
+};
+pub use sphere::Sphere;
+pub use texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture};
+pub use tonemap::{ToneMap, ToneMapping};
+pub use torus::Torus;
+pub use transformed::Transformed;
+pub use triangle::{Triangle, TriangleMesh};
+pub use vec3::Vec3;
@@ -1,27 +1,28 @@
+extern crate clap;
 extern crate rand;
-use rand::Rng;
+use clap::Parser;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rust_ray_tracing::checkpoint;
 
-mod camera;
-mod hittable;
-mod hittable_list;
-mod material;
-mod ray;
-mod sphere;
-mod vec3;
+use rust_ray_tracing::output::{self, ImageFormat};
+use rust_ray_tracing::scene::{load_scene, RenderOverrides, Scene};
+use rust_ray_tracing::{
+    render_progressive, BvhNode, Camera, Dielectric, FilterKind, Hittable, Integrator, Lambertian,
+    Metal, Plane, Progress, RenderSettings, SamplerKind, Sphere, ToneMap, Vec3,
+};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, Instant};
 
-use camera::Camera;
-use hittable::Hittable;
-use material::{Dielectric, Lambertian, Metal};
-use ray::Ray;
-use sphere::Sphere;
-use vec3::Vec3;
-
-fn random_scene() -> Box<dyn Hittable> {
-    let mut rng = rand::thread_rng();
+fn random_scene(seed: u64) -> Vec<Box<dyn Hittable>> {
+    let mut rng = StdRng::seed_from_u64(seed);
     let mut world: Vec<Box<dyn Hittable>> = Vec::new();
-    world.push(Box::new(Sphere::new(
-        Vec3(0.0, -1000.0, 0.0),
-        1000.0,
+    world.push(Box::new(Plane::new(
+        Vec3(0.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
         Box::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
     )));
     for a in -11..11 {
@@ -86,63 +87,249 @@ fn random_scene() -> Box<dyn Hittable> {
         Box::new(Metal::new(Vec3(0.7, 0.6, 0.5), 0.0)),
     )));
 
-    Box::new(world)
-}
-
-fn color(r: &Ray, world: &Box<dyn Hittable>, depth: i32) -> Vec3 {
-    match world.hit(r, 0.001, std::f32::MAX) {
-        Some(rec) => {
-            if depth < 50 {
-                if let Some(s) = rec.material.scatter(&r, &rec) {
-                    return s.attenuation * color(&s.scattered, world, depth + 1);
-                }
-            }
-            Vec3(0.0, 0.0, 0.0)
-        }
-        None => {
-            let unit_direction = r.direction.to_unit_vector();
-            let t = 0.5 * (unit_direction.y() + 1.0);
-            (1.0 - t) * Vec3(1.0, 1.0, 1.0) + t * Vec3(0.5, 0.7, 1.0)
-        }
-    }
+    world
 }
 
-fn main() {
-    let nx = 1200;
-    let ny = 800;
-    let ns = 100;
-    println!("P3\n{} {}\n255", nx, ny);
-
+fn random_scene_with_camera(settings: RenderSettings) -> Scene {
     let look_from = Vec3(13.0, 2.0, 3.0);
     let look_at = Vec3(0.0, 0.0, 0.0);
     let focus_dist = 10.0;
     let aperture = 0.1;
-    let cam = Camera::new(
+    let camera = Camera::new(
         look_from,
         look_at,
         Vec3(0.0, 1.0, 0.0),
         20.0,
-        nx as f32 / ny as f32,
+        settings.width as f32 / settings.height as f32,
         aperture,
         focus_dist,
     );
-    let mut rng = rand::thread_rng();
-    let world = random_scene();
-    for j in (0..ny).rev() {
-        for i in 0..nx {
-            let mut col = Vec3(0.0, 0.0, 0.0);
-            for _s in 0..ns {
-                let u = (i as f32 + rng.gen::<f32>()) / nx as f32;
-                let v = (j as f32 + rng.gen::<f32>()) / ny as f32;
-                let r = cam.get_ray(u, v);
-                col += color(&r, &world, 0);
+    Scene {
+        world: random_scene(settings.seed),
+        lights: Vec::new(),
+        camera,
+        settings,
+    }
+}
+
+/// Ray tracer from the Ray Tracing in One Weekend series.
+///
+/// Renders the book's final random scene, or a TOML scene file given with
+/// --scene. Options given here override the scene file's [render] table.
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Cli {
+    /// Image width in pixels
+    #[arg(long, value_parser = positive)]
+    width: Option<usize>,
+    /// Image height in pixels
+    #[arg(long, value_parser = positive)]
+    height: Option<usize>,
+    /// Samples per pixel, at most with --noise-threshold or --time-limit
+    #[arg(long, value_parser = positive)]
+    spp: Option<usize>,
+    /// Samples per pixel in each progressive pass
+    #[arg(long, value_parser = positive)]
+    pass_samples: Option<usize>,
+    /// Stop sampling pixels whose estimated error is below this, e.g. 0.01
+    #[arg(long, value_parser = non_negative)]
+    noise_threshold: Option<f32>,
+    /// Start no new pass after this many seconds
+    #[arg(long, value_parser = non_negative)]
+    time_limit: Option<f32>,
+    /// Maximum number of bounces of a path
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    max_depth: Option<i32>,
+    /// Seed of the random number generators
+    #[arg(long)]
+    seed: Option<u64>,
+    /// How to estimate light: path, mis_balance or mis_power
+    #[arg(long)]
+    integrator: Option<Integrator>,
+    /// Where sample positions come from: independent, stratified, halton, sobol or blue_noise
+    #[arg(long)]
+    sampler: Option<SamplerKind>,
+    /// Pixel reconstruction filter: box, tent, gaussian, mitchell or lanczos
+    #[arg(long)]
+    filter: Option<FilterKind>,
+    /// Radius of the filter in pixels
+    #[arg(long, value_parser = positive_radius)]
+    filter_radius: Option<f32>,
+    /// Tone mapping of 8 and 16-bit output: clamp, reinhard, aces or agx
+    #[arg(long)]
+    tone_map: Option<ToneMap>,
+    /// Exposure adjustment of 8 and 16-bit output, in stops
+    #[arg(long, allow_hyphen_values = true, value_parser = finite)]
+    exposure: Option<f32>,
+    /// Dither 8-bit output
+    #[arg(long)]
+    dither: bool,
+    /// Render threads, 0 uses every core
+    #[arg(long)]
+    threads: Option<usize>,
+    /// Output image (.png, .ppm, .pfm or .exr), binary PPM on stdout if omitted
+    #[arg(short, long, value_parser = output_path)]
+    output: Option<PathBuf>,
+    /// Write 16-bit PNG or 32-bit float EXR instead of 8-bit PNG or half EXR
+    #[arg(long)]
+    high_precision: bool,
+    /// TOML scene file to render instead of the random scene
+    #[arg(short, long)]
+    scene: Option<PathBuf>,
+    /// Save the render's progress to this file now and then
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+    /// Seconds between checkpoints
+    #[arg(long, default_value_t = 60.0, value_parser = non_negative)]
+    checkpoint_interval: f32,
+    /// Carry on from the --checkpoint file of an interrupted render
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+}
+
+impl Cli {
+    fn overrides(&self) -> RenderOverrides {
+        RenderOverrides {
+            width: self.width,
+            height: self.height,
+            samples_per_pixel: self.spp,
+            pass_samples: self.pass_samples,
+            noise_threshold: self.noise_threshold,
+            time_limit: self.time_limit,
+            max_depth: self.max_depth,
+            threads: self.threads,
+            seed: self.seed,
+            integrator: self.integrator,
+            sampler: self.sampler,
+            filter: self.filter,
+            filter_radius: self.filter_radius,
+            tone_map: self.tone_map,
+            exposure: self.exposure,
+            dither: if self.dither { Some(true) } else { None },
+        }
+    }
+}
+
+fn positive(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn non_negative(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(x) if x >= 0.0 && x.is_finite() => Ok(x),
+        Ok(_) => Err("must be a non-negative number".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn positive_radius(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(x) if x > 0.0 && x.is_finite() => Ok(x),
+        Ok(_) => Err("must be a positive number".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn finite(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(x) if x.is_finite() => Ok(x),
+        Ok(_) => Err("must be a finite number".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn output_path(s: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(s);
+    match ImageFormat::from_path(&path) {
+        Some(_) => Ok(path),
+        None => Err("unsupported extension, expected png, ppm, pfm or exr".to_string()),
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let overrides = cli.overrides();
+    let scene = match &cli.scene {
+        Some(path) => match load_scene(path, &overrides) {
+            Ok(scene) => scene,
+            Err(e) => {
+                eprintln!("error: {}: {}", path.display(), e);
+                process::exit(1);
+            }
+        },
+        None => {
+            let mut settings = RenderSettings::default();
+            overrides.apply(&mut settings);
+            random_scene_with_camera(settings)
+        }
+    };
+
+    // the random scene is generated from the seed, which is hashed anyway
+    let scene_source = match &cli.scene {
+        Some(path) => fs::read(path).unwrap_or_default(),
+        None => b"random scene".to_vec(),
+    };
+    let fingerprint = checkpoint::fingerprint(&scene.settings, &scene_source);
+    let mut progress = match &cli.checkpoint {
+        Some(path) if cli.resume => match checkpoint::load(path, fingerprint) {
+            Ok(progress) => progress,
+            Err(e) => {
+                eprintln!("error: could not resume from {}: {}", path.display(), e);
+                process::exit(1);
             }
-            col /= ns as f32;
-            col = Vec3(col.x().sqrt(), col.y().sqrt(), col.z().sqrt());
-            let ir = (255.99 * col.r()) as i32;
-            let ig = (255.99 * col.g()) as i32;
-            let ib = (255.99 * col.b()) as i32;
-            println!("{} {} {}", ir, ig, ib);
+        },
+        _ => Progress::new(scene.settings.width, scene.settings.height),
+    };
+    let save_checkpoint = |progress: &Progress| {
+        if let Some(path) = &cli.checkpoint {
+            if let Err(e) = checkpoint::save(progress, fingerprint, path) {
+                eprintln!("error: could not save {}: {}", path.display(), e);
+                process::exit(1);
+            }
+        }
+    };
+
+    let world = BvhNode::new(scene.world);
+    let interval = Duration::from_secs_f32(cli.checkpoint_interval);
+    let mut last_checkpoint = Instant::now();
+    render_progressive(
+        &world,
+        &scene.lights,
+        &scene.camera,
+        &scene.settings,
+        &mut progress,
+        |progress| {
+            if last_checkpoint.elapsed() >= interval {
+                save_checkpoint(progress);
+                last_checkpoint = Instant::now();
+            }
+        },
+    );
+    save_checkpoint(&progress);
+    let image = progress.image();
+    let result = match &cli.output {
+        Some(path) => {
+            let format = ImageFormat::from_path(path).unwrap();
+            let format = if cli.high_precision {
+                format.high_precision()
+            } else {
+                format
+            };
+            output::save_as(&image, path, format, &scene.settings.tone_mapping)
         }
+        None => output::write(
+            &image,
+            &mut io::stdout().lock(),
+            ImageFormat::Ppm,
+            &scene.settings.tone_mapping,
+        ),
+    };
+    if let Err(e) = result {
+        eprintln!("error: could not write image: {}", e);
+        process::exit(1);
     }
 }
@@ -0,0 +1,186 @@
+use super::vec3::Vec3;
+use std::f32;
+use std::ops::Mul;
+
+/// Affine transform as a row-major 4x4 matrix acting on column vectors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4 {
+  pub m: [[f32; 4]; 4],
+}
+
+impl Mat4 {
+  pub fn identity() -> Self {
+    Mat4::scaling(Vec3(1.0, 1.0, 1.0))
+  }
+
+  pub fn translation(offset: Vec3) -> Self {
+    Mat4 {
+      m: [
+        [1.0, 0.0, 0.0, offset.x()],
+        [0.0, 1.0, 0.0, offset.y()],
+        [0.0, 0.0, 1.0, offset.z()],
+        [0.0, 0.0, 0.0, 1.0],
+      ],
+    }
+  }
+
+  pub fn scaling(factors: Vec3) -> Self {
+    Mat4 {
+      m: [
+        [factors.x(), 0.0, 0.0, 0.0],
+        [0.0, factors.y(), 0.0, 0.0],
+        [0.0, 0.0, factors.z(), 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+      ],
+    }
+  }
+
+  /// Counterclockwise rotation by `degrees` around `axis`, looking down the
+  /// axis towards the origin.
+  pub fn rotation(axis: Vec3, degrees: f32) -> Self {
+    let a = axis.to_unit_vector();
+    let (sin, cos) = (degrees * f32::consts::PI / 180.0).sin_cos();
+    let (x, y, z) = (a.x(), a.y(), a.z());
+    let t = 1.0 - cos;
+    Mat4 {
+      m: [
+        [
+          t * x * x + cos,
+          t * x * y - sin * z,
+          t * x * z + sin * y,
+          0.0,
+        ],
+        [
+          t * x * y + sin * z,
+          t * y * y + cos,
+          t * y * z - sin * x,
+          0.0,
+        ],
+        [
+          t * x * z - sin * y,
+          t * y * z + sin * x,
+          t * z * z + cos,
+          0.0,
+        ],
+        [0.0, 0.0, 0.0, 1.0],
+      ],
+    }
+  }
+
+  pub fn transform_point(&self, p: Vec3) -> Vec3 {
+    self.transform_vector(p) + Vec3(self.m[0][3], self.m[1][3], self.m[2][3])
+  }
+
+  /// Applies the linear part only, for directions.
+  pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+    let row = |r: &[f32; 4]| r[0] * v.x() + r[1] * v.y() + r[2] * v.z();
+    Vec3(row(&self.m[0]), row(&self.m[1]), row(&self.m[2]))
+  }
+
+  pub fn transpose(&self) -> Self {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+      for (j, value) in row.iter_mut().enumerate() {
+        *value = self.m[j][i];
+      }
+    }
+    Mat4 { m }
+  }
+
+  /// Determinant of the linear part, how much volumes get scaled.
+  pub fn determinant3(&self) -> f32 {
+    let m = &self.m;
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+      - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+      + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+  }
+
+  /// Gauss-Jordan elimination with partial pivoting, `None` for singular matrices.
+  pub fn inverse(&self) -> Option<Self> {
+    let mut a = [[0.0f64; 8]; 4];
+    for (i, row) in a.iter_mut().enumerate() {
+      for (value, m) in row.iter_mut().zip(self.m[i].iter()) {
+        *value = f64::from(*m);
+      }
+      row[4 + i] = 1.0;
+    }
+    for col in 0..4 {
+      let pivot = (col..4)
+        .max_by(|&x, &y| a[x][col].abs().total_cmp(&a[y][col].abs()))
+        .unwrap();
+      if a[pivot][col].abs() < 1e-12 {
+        return None;
+      }
+      a.swap(col, pivot);
+      let p = a[col][col];
+      for value in a[col].iter_mut() {
+        *value /= p;
+      }
+      for row in 0..4 {
+        if row != col {
+          let factor = a[row][col];
+          let pivot_row = a[col];
+          for (value, pivot_value) in a[row].iter_mut().zip(pivot_row.iter()) {
+            *value -= factor * pivot_value;
+          }
+        }
+      }
+    }
+    let mut m = [[0.0; 4]; 4];
+    for (row, augmented) in m.iter_mut().zip(a.iter()) {
+      for (value, inverse_value) in row.iter_mut().zip(augmented[4..].iter()) {
+        *value = *inverse_value as f32;
+      }
+    }
+    Some(Mat4 { m })
+  }
+}
+
+impl Mul for Mat4 {
+  type Output = Mat4;
+
+  /// `self * rhs` applies `rhs` first.
+  fn mul(self, rhs: Mat4) -> Mat4 {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+      for (j, value) in row.iter_mut().enumerate() {
+        *value = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+      }
+    }
+    Mat4 { m }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn assert_close(a: Vec3, b: Vec3) {
+    assert!((a - b).length() < 1e-5, "{:?} != {:?}", a, b);
+  }
+
+  #[test]
+  fn compose_and_apply() {
+    let t = Mat4::translation(Vec3(1.0, 2.0, 3.0))
+      * Mat4::rotation(Vec3(0.0, 1.0, 0.0), 90.0)
+      * Mat4::scaling(Vec3(2.0, 2.0, 2.0));
+    assert_close(Vec3(1.0, 2.0, 1.0), t.transform_point(Vec3(1.0, 0.0, 0.0)));
+    assert_close(
+      Vec3(0.0, 0.0, -2.0),
+      t.transform_vector(Vec3(1.0, 0.0, 0.0)),
+    );
+    assert!((8.0 - t.determinant3()).abs() < 1e-5);
+  }
+
+  #[test]
+  fn inverse() {
+    let t = Mat4::translation(Vec3(1.0, -2.0, 3.0))
+      * Mat4::rotation(Vec3(1.0, 1.0, 0.0), 30.0)
+      * Mat4::scaling(Vec3(2.0, 0.5, 3.0));
+    let p = Vec3(0.3, -0.7, 1.1);
+    let inverse = t.inverse().unwrap();
+    assert_close(p, inverse.transform_point(t.transform_point(p)));
+    assert_close(p, t.transform_point(inverse.transform_point(p)));
+    assert!(Mat4::scaling(Vec3(1.0, 0.0, 1.0)).inverse().is_none());
+  }
+}
@@ -1,46 +1,108 @@
 use super::hittable::HitRecord;
+use super::microfacet::{self, fresnel_conductor, fresnel_dielectric, Conductor, Ggx};
+use super::onb::Onb;
 use super::ray::Ray;
-use super::vec3::{random_in_unit_sphere, reflect, refract, Vec3};
+use super::sampler::Sampler;
+use super::texture::{SolidColor, Texture};
+use super::vec3::{random_in_unit_sphere, random_unit_vector, reflect, refract, Vec3};
+use std::f32;
+use std::sync::Arc;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Scatter {
   pub scattered: Ray,
+  /// BSDF times cosine divided by `pdf`, what the path throughput is multiplied by.
   pub attenuation: Vec3,
+  /// Solid angle density `scattered` was sampled with. `None` for mirror-like
+  /// scattering that `eval` and `pdf` cannot describe, which lights are
+  /// never sampled for.
+  pub pdf: Option<f32>,
 }
 
-pub trait Material {
-  fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter>;
+pub trait Material: Send + Sync {
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter>;
+
+  /// Radiance given off by the surface itself, black unless it is a light.
+  fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Vec3 {
+    Vec3(0.0, 0.0, 0.0)
+  }
+
+  /// Whether `emitted` can be non-black, so the surface is worth sampling as a light.
+  fn is_emissive(&self) -> bool {
+    false
+  }
+
+  /// BSDF times cosine for light arriving along `direction` and leaving
+  /// towards where `r_in` came from.
+  fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _direction: Vec3) -> Vec3 {
+    Vec3(0.0, 0.0, 0.0)
+  }
+
+  /// Solid angle density with which `scatter` picks `direction`.
+  fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _direction: Vec3) -> f32 {
+    0.0
+  }
 }
 
 pub struct Lambertian {
-  pub albedo: Vec3,
+  pub albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
   pub fn new(albedo: Vec3) -> Self {
+    Lambertian::textured(Arc::new(SolidColor::new(albedo)))
+  }
+
+  pub fn textured(albedo: Arc<dyn Texture>) -> Self {
     Lambertian { albedo }
   }
 }
 
 impl Material for Lambertian {
-  fn scatter(&self, _ray: &Ray, rec: &HitRecord) -> Option<Scatter> {
-    let target = rec.p + rec.normal + random_in_unit_sphere();
-    let scattered = Ray::new(rec.p, target - rec.p);
-    let attenuation = self.albedo;
+  fn scatter(&self, ray: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+    // a point on the unit sphere around the normal's tip gives cosine
+    // weighted directions, for which albedo is exactly f * cos / pdf
+    let normal = rec.normal;
+    let direction = normal + random_unit_vector(sampler);
+    let direction = if direction.squared_length() < 1e-8 {
+      normal
+    } else {
+      direction
+    };
+    let scattered = Ray::new_at_time(rec.p, direction, ray.time);
     Some(Scatter {
       scattered,
-      attenuation,
+      attenuation: self.albedo.value(rec.u, rec.v, rec.p),
+      pdf: Some(self.pdf(ray, rec, direction)),
     })
   }
+
+  fn eval(&self, _ray: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+    let cosine = rec.normal.dot(direction.to_unit_vector());
+    if cosine > 0.0 {
+      self.albedo.value(rec.u, rec.v, rec.p) * (cosine / f32::consts::PI)
+    } else {
+      Vec3(0.0, 0.0, 0.0)
+    }
+  }
+
+  fn pdf(&self, _ray: &Ray, rec: &HitRecord, direction: Vec3) -> f32 {
+    let cosine = rec.normal.dot(direction.to_unit_vector());
+    cosine.max(0.0) / f32::consts::PI
+  }
 }
 
 pub struct Metal {
-  pub albedo: Vec3,
+  pub albedo: Arc<dyn Texture>,
   pub fuzz: f32,
 }
 
 impl Metal {
   pub fn new(albedo: Vec3, fuzz: f32) -> Self {
+    Metal::textured(Arc::new(SolidColor::new(albedo)), fuzz)
+  }
+
+  pub fn textured(albedo: Arc<dyn Texture>, fuzz: f32) -> Self {
     Metal {
       albedo,
       fuzz: if fuzz < 1.0 { fuzz } else { 1.0 },
@@ -49,15 +111,16 @@ impl Metal {
 }
 
 impl Material for Metal {
-  fn scatter(&self, ray: &Ray, rec: &HitRecord) -> Option<Scatter> {
+  fn scatter(&self, ray: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
     let reflected = reflect(ray.direction.to_unit_vector(), rec.normal);
-    let reflected_fuzzed = reflected + self.fuzz * random_in_unit_sphere();
+    let reflected_fuzzed = reflected + self.fuzz * random_in_unit_sphere(sampler);
     if reflected_fuzzed.dot(rec.normal) > 0.0 {
-      let scattered = Ray::new(rec.p, reflected_fuzzed);
-      let attenuation = self.albedo;
+      let scattered = Ray::new_at_time(rec.p, reflected_fuzzed, ray.time);
+      let attenuation = self.albedo.value(rec.u, rec.v, rec.p);
       Some(Scatter {
         scattered,
         attenuation,
+        pdf: None,
       })
     } else {
       None
@@ -76,23 +139,22 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-  fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<Scatter> {
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
     let attenuation = Vec3(1.0, 1.0, 1.0);
-    let (outward_normal, ni_over_nt, cosine) = if r_in.direction.dot(rec.normal) > 0.0 {
-      let cosine = self.ref_idx * r_in.direction.dot(rec.normal) / r_in.direction.length();
-      (-rec.normal, self.ref_idx, cosine)
+    let cosine = -r_in.direction.dot(rec.normal) / r_in.direction.length();
+    let (ni_over_nt, cosine) = if rec.front_face {
+      (1.0 / self.ref_idx, cosine)
     } else {
-      let cosine = -r_in.direction.dot(rec.normal) / r_in.direction.length();
-      (rec.normal, 1.0 / self.ref_idx, cosine)
+      (self.ref_idx, self.ref_idx * cosine)
     };
-    let reflected = Ray::new(rec.p, reflect(r_in.direction, rec.normal));
-    let scattered = match refract(r_in.direction, outward_normal, ni_over_nt) {
+    let reflected = Ray::new_at_time(rec.p, reflect(r_in.direction, rec.normal), r_in.time);
+    let scattered = match refract(r_in.direction, rec.normal, ni_over_nt) {
       Some(refracted_dir) => {
         let reflect_prob = schlick(cosine, self.ref_idx);
-        if rand::random::<f32>() < reflect_prob {
+        if sampler.next_1d() < reflect_prob {
           reflected
         } else {
-          Ray::new(rec.p, refracted_dir)
+          Ray::new_at_time(rec.p, refracted_dir, r_in.time)
         }
       }
       None => reflected,
@@ -100,8 +162,349 @@ impl Material for Dielectric {
     Some(Scatter {
       scattered,
       attenuation,
+      pdf: None,
+    })
+  }
+}
+
+/// Metal whose microfacets follow the GGX distribution, reflecting as much
+/// as the Fresnel equations for its complex refractive index `eta` + i `k`
+/// say. A `roughness` of 0 is a perfect mirror.
+pub struct RoughConductor {
+  pub eta: Vec3,
+  pub k: Vec3,
+  pub distribution: Ggx,
+}
+
+impl RoughConductor {
+  pub fn new(eta: Vec3, k: Vec3, roughness: f32) -> Self {
+    RoughConductor {
+      eta,
+      k,
+      distribution: Ggx::from_roughness(roughness.clamp(0.0, 1.0)),
+    }
+  }
+
+  pub fn preset(conductor: Conductor, roughness: f32) -> Self {
+    let (eta, k) = conductor.ior();
+    RoughConductor::new(eta, k, roughness)
+  }
+}
+
+/// Frame around the normal of `rec` facing `r_in`, and the unit direction
+/// back along `r_in` in it.
+fn shading_frame(r_in: &Ray, rec: &HitRecord) -> (Onb, Vec3) {
+  let frame = Onb::from_w(rec.normal);
+  let wo = frame.coordinates(-r_in.direction.to_unit_vector());
+  (frame, wo)
+}
+
+impl Material for RoughConductor {
+  fn scatter(&self, ray: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+    let (frame, wo) = shading_frame(ray, rec);
+    if wo.z() <= 0.0 {
+      return None;
+    }
+    let ggx = &self.distribution;
+    if ggx.is_smooth() {
+      let wi = Vec3(-wo.x(), -wo.y(), wo.z());
+      return Some(Scatter {
+        scattered: Ray::new_at_time(rec.p, frame.local(wi), ray.time),
+        attenuation: fresnel_conductor(wo.z(), self.eta, self.k),
+        pdf: None,
+      });
+    }
+    let m = ggx.sample_visible(wo, sampler.next_2d());
+    let wi = reflect(-wo, m);
+    if wi.z() <= 0.0 {
+      return None;
+    }
+    // f cos / pdf, with the visible normal density cancelling all but G / G1
+    let attenuation = fresnel_conductor(wo.dot(m), self.eta, self.k) * ggx.g(wo, wi) / ggx.g1(wo);
+    Some(Scatter {
+      scattered: Ray::new_at_time(rec.p, frame.local(wi), ray.time),
+      attenuation,
+      pdf: Some(ggx.pdf_visible(wo, m) / (4.0 * wo.dot(m))),
     })
   }
+
+  fn eval(&self, ray: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+    let (frame, wo) = shading_frame(ray, rec);
+    let wi = frame.coordinates(direction.to_unit_vector());
+    let ggx = &self.distribution;
+    if ggx.is_smooth() || wo.z() <= 0.0 || wi.z() <= 0.0 {
+      return Vec3(0.0, 0.0, 0.0);
+    }
+    let m = (wo + wi).to_unit_vector();
+    fresnel_conductor(wo.dot(m), self.eta, self.k) * (ggx.d(m) * ggx.g(wo, wi) / (4.0 * wo.z()))
+  }
+
+  fn pdf(&self, ray: &Ray, rec: &HitRecord, direction: Vec3) -> f32 {
+    let (frame, wo) = shading_frame(ray, rec);
+    let wi = frame.coordinates(direction.to_unit_vector());
+    let ggx = &self.distribution;
+    if ggx.is_smooth() || wo.z() <= 0.0 || wi.z() <= 0.0 {
+      return 0.0;
+    }
+    let m = (wo + wi).to_unit_vector();
+    ggx.pdf_visible(wo, m) / (4.0 * wo.dot(m))
+  }
+}
+
+/// Glass whose microfacets follow the GGX distribution, reflecting and
+/// refracting light as the Fresnel equations say. A `roughness` of 0 is a
+/// perfectly smooth interface.
+pub struct RoughDielectric {
+  pub ref_idx: f32,
+  pub distribution: Ggx,
+}
+
+impl RoughDielectric {
+  pub fn new(ref_idx: f32, roughness: f32) -> Self {
+    RoughDielectric {
+      ref_idx,
+      distribution: Ggx::from_roughness(roughness.clamp(0.0, 1.0)),
+    }
+  }
+
+  /// Refractive index of the side `r_in` goes to over the side it comes from.
+  fn eta(&self, rec: &HitRecord) -> f32 {
+    if rec.front_face {
+      self.ref_idx
+    } else {
+      1.0 / self.ref_idx
+    }
+  }
+
+  /// Microfacet normal turning `wo` into `wi`, and whether `wi` is reflected.
+  fn half_vector(wo: Vec3, wi: Vec3, eta: f32) -> (Vec3, bool) {
+    let reflected = wi.z() > 0.0;
+    let m = if reflected { wo + wi } else { eta * wi + wo }.to_unit_vector();
+    (if m.z() < 0.0 { -m } else { m }, reflected)
+  }
+}
+
+impl Material for RoughDielectric {
+  fn scatter(&self, ray: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+    let (frame, wo) = shading_frame(ray, rec);
+    if wo.z() <= 0.0 {
+      return None;
+    }
+    let eta = self.eta(rec);
+    let ggx = &self.distribution;
+    let smooth = ggx.is_smooth();
+    let m = if smooth {
+      Vec3(0.0, 0.0, 1.0)
+    } else {
+      ggx.sample_visible(wo, sampler.next_2d())
+    };
+    let cos_o = wo.dot(m);
+    if cos_o <= 0.0 {
+      return None;
+    }
+    let fresnel = fresnel_dielectric(cos_o, eta);
+    let (wi, pdf) = if sampler.next_1d() < fresnel {
+      let wi = reflect(-wo, m);
+      let pdf = fresnel * ggx.pdf_visible(wo, m) / (4.0 * cos_o);
+      (wi, pdf)
+    } else {
+      let wi = microfacet::refract(wo, m, eta)?;
+      let cos_i = wi.dot(m);
+      let denominator = (cos_i + cos_o / eta) * (cos_i + cos_o / eta);
+      let pdf = (1.0 - fresnel) * ggx.pdf_visible(wo, m) * cos_i.abs() / denominator;
+      (wi, pdf)
+    };
+    // microfacets may send light to the wrong side of the surface
+    let reflected = wi.dot(m) > 0.0;
+    if reflected != (wi.z() > 0.0) || wi.z() == 0.0 {
+      return None;
+    }
+    // radiance is concentrated by the square of the ratio of indices
+    let spread = if reflected { 1.0 } else { 1.0 / (eta * eta) };
+    let masking = if smooth {
+      1.0
+    } else {
+      ggx.g(wo, wi) / ggx.g1(wo)
+    };
+    Some(Scatter {
+      scattered: Ray::new_at_time(rec.p, frame.local(wi), ray.time),
+      attenuation: Vec3(1.0, 1.0, 1.0) * (spread * masking),
+      pdf: if smooth { None } else { Some(pdf) },
+    })
+  }
+
+  fn eval(&self, ray: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+    let (frame, wo) = shading_frame(ray, rec);
+    let wi = frame.coordinates(direction.to_unit_vector());
+    let ggx = &self.distribution;
+    if ggx.is_smooth() || wo.z() <= 0.0 || wi.z() == 0.0 {
+      return Vec3(0.0, 0.0, 0.0);
+    }
+    let eta = self.eta(rec);
+    let (m, reflected) = RoughDielectric::half_vector(wo, wi, eta);
+    let (cos_o, cos_i) = (wo.dot(m), wi.dot(m));
+    // microfacets seen from the wrong side
+    if cos_o <= 0.0 || (cos_i > 0.0) != reflected {
+      return Vec3(0.0, 0.0, 0.0);
+    }
+    let fresnel = fresnel_dielectric(cos_o, eta);
+    let f = if reflected {
+      fresnel * ggx.d(m) * ggx.g(wo, wi) / (4.0 * wo.z())
+    } else {
+      let denominator = (cos_i + cos_o / eta) * (cos_i + cos_o / eta);
+      (1.0 - fresnel) * ggx.d(m) * ggx.g(wo, wi) * (cos_i * cos_o).abs()
+        / (wo.z() * denominator * eta * eta)
+    };
+    Vec3(f, f, f)
+  }
+
+  fn pdf(&self, ray: &Ray, rec: &HitRecord, direction: Vec3) -> f32 {
+    let (frame, wo) = shading_frame(ray, rec);
+    let wi = frame.coordinates(direction.to_unit_vector());
+    let ggx = &self.distribution;
+    if ggx.is_smooth() || wo.z() <= 0.0 || wi.z() == 0.0 {
+      return 0.0;
+    }
+    let eta = self.eta(rec);
+    let (m, reflected) = RoughDielectric::half_vector(wo, wi, eta);
+    let (cos_o, cos_i) = (wo.dot(m), wi.dot(m));
+    if cos_o <= 0.0 || (cos_i > 0.0) != reflected {
+      return 0.0;
+    }
+    let fresnel = fresnel_dielectric(cos_o, eta);
+    if reflected {
+      fresnel * ggx.pdf_visible(wo, m) / (4.0 * cos_o)
+    } else {
+      let denominator = (cos_i + cos_o / eta) * (cos_i + cos_o / eta);
+      (1.0 - fresnel) * ggx.pdf_visible(wo, m) * cos_i.abs() / denominator
+    }
+  }
+}
+
+/// Area light: emits `emit` from every point of the surface it is on and
+/// scatters nothing.
+pub struct DiffuseLight {
+  pub emit: Vec3,
+}
+
+impl DiffuseLight {
+  pub fn new(emit: Vec3) -> Self {
+    DiffuseLight { emit }
+  }
+}
+
+impl Material for DiffuseLight {
+  fn scatter(&self, _r_in: &Ray, _rec: &HitRecord, _sampler: &mut dyn Sampler) -> Option<Scatter> {
+    None
+  }
+
+  fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Vec3 {
+    self.emit
+  }
+
+  fn is_emissive(&self) -> bool {
+    self.emit.squared_length() > 0.0
+  }
+}
+
+/// Phase function of a participating medium scattering equally in every
+/// direction, `albedo` being the fraction of light scattered rather than absorbed.
+pub struct Isotropic {
+  pub albedo: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+  pub fn new(albedo: Vec3) -> Self {
+    Isotropic::textured(Arc::new(SolidColor::new(albedo)))
+  }
+
+  pub fn textured(albedo: Arc<dyn Texture>) -> Self {
+    Isotropic { albedo }
+  }
+}
+
+impl Material for Isotropic {
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+    Some(Scatter {
+      scattered: Ray::new_at_time(rec.p, random_unit_vector(sampler), r_in.time),
+      attenuation: self.albedo.value(rec.u, rec.v, rec.p),
+      pdf: Some(1.0 / (4.0 * f32::consts::PI)),
+    })
+  }
+
+  fn eval(&self, _r_in: &Ray, rec: &HitRecord, _direction: Vec3) -> Vec3 {
+    self.albedo.value(rec.u, rec.v, rec.p) / (4.0 * f32::consts::PI)
+  }
+
+  fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _direction: Vec3) -> f32 {
+    1.0 / (4.0 * f32::consts::PI)
+  }
+}
+
+/// Henyey-Greenstein phase function: `g` in (-1, 1) is the mean cosine of
+/// the scattering angle, positive values scattering forwards as in fog and
+/// negative ones backwards.
+pub struct HenyeyGreenstein {
+  pub albedo: Arc<dyn Texture>,
+  pub g: f32,
+}
+
+impl HenyeyGreenstein {
+  pub fn new(albedo: Vec3, g: f32) -> Self {
+    HenyeyGreenstein::textured(Arc::new(SolidColor::new(albedo)), g)
+  }
+
+  pub fn textured(albedo: Arc<dyn Texture>, g: f32) -> Self {
+    HenyeyGreenstein {
+      albedo,
+      g: g.clamp(-0.999, 0.999),
+    }
+  }
+
+  /// Density of scattering by an angle of cosine `cos_theta`.
+  fn phase(&self, cos_theta: f32) -> f32 {
+    let g = self.g;
+    let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * f32::consts::PI * denominator * denominator.sqrt())
+  }
+}
+
+impl Material for HenyeyGreenstein {
+  fn scatter(&self, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Option<Scatter> {
+    let g = self.g;
+    let (xi, u) = sampler.next_2d();
+    // inverted cumulative distribution of the cosine
+    let cos_theta = if g.abs() < 1e-3 {
+      1.0 - 2.0 * xi
+    } else {
+      let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+      ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * f32::consts::PI * u;
+    let direction = Onb::from_w(r_in.direction).local(Vec3(
+      phi.cos() * sin_theta,
+      phi.sin() * sin_theta,
+      cos_theta,
+    ));
+    Some(Scatter {
+      scattered: Ray::new_at_time(rec.p, direction, r_in.time),
+      attenuation: self.albedo.value(rec.u, rec.v, rec.p),
+      pdf: Some(self.phase(cos_theta)),
+    })
+  }
+
+  fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+    self.albedo.value(rec.u, rec.v, rec.p) * self.pdf(r_in, rec, direction)
+  }
+
+  fn pdf(&self, r_in: &Ray, _rec: &HitRecord, direction: Vec3) -> f32 {
+    let cos_theta = r_in
+      .direction
+      .to_unit_vector()
+      .dot(direction.to_unit_vector());
+    self.phase(cos_theta)
+  }
 }
 
 /// Christophe Schlick's approximation is a formula for approximating the contribution of the Fresnel factor in the specular reflection of light from a non-conducting interface
@@ -110,3 +513,140 @@ fn schlick(cosine: f32, ref_idx: f32) -> f32 {
   let r0 = r0 * r0;
   r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
 }
+
+#[cfg(test)]
+mod tests {
+  use super::super::sampler::IndependentSampler;
+  use super::*;
+
+  /// Albedo of `material` for light leaving along -`r_in`, by its sampling
+  /// and by integrating `eval` over uniform directions, checking that
+  /// `scatter`'s densities agree with `pdf` on the way.
+  fn albedos(material: &dyn Material, r_in: &Ray, rec: &HitRecord) -> (Vec3, Vec3) {
+    let mut sampler = IndependentSampler::new(4);
+    let n = 200_000;
+    let mut sampled = Vec3(0.0, 0.0, 0.0);
+    let mut integrated = Vec3(0.0, 0.0, 0.0);
+    for i in 0..n {
+      sampler.start_sample(0, 0, i);
+      if let Some(scatter) = material.scatter(r_in, rec, &mut sampler) {
+        let direction = scatter.scattered.direction;
+        let pdf = material.pdf(r_in, rec, direction);
+        assert!(
+          (scatter.pdf.unwrap() - pdf).abs() <= 1e-3 * pdf,
+          "{:?} {}",
+          scatter.pdf,
+          pdf
+        );
+        let expected = material.eval(r_in, rec, direction) / pdf;
+        assert!((scatter.attenuation - expected).length() < 1e-3);
+        sampled += scatter.attenuation / n as f32;
+      }
+      let direction = random_unit_vector(&mut sampler);
+      integrated += material.eval(r_in, rec, direction) * (4.0 * f32::consts::PI / n as f32);
+    }
+    (sampled, integrated)
+  }
+
+  /// Hit of `r_in` on a surface facing +y.
+  fn hit_record<'m>(r_in: &Ray, material: &'m dyn Material) -> HitRecord<'m> {
+    HitRecord::new(r_in, 1.0, Vec3(0.0, 1.0, 0.0), material)
+  }
+
+  #[test]
+  fn rough_conductor_is_consistent_and_conserves_energy() {
+    let gold = RoughConductor::preset(Conductor::Gold, 0.5);
+    let r_in = Ray::new(Vec3(-1.0, 1.0, 0.0), Vec3(1.0, -0.7, 0.2));
+    let rec = hit_record(&r_in, &gold);
+    let (sampled, integrated) = albedos(&gold, &r_in, &rec);
+    assert!(
+      (sampled - integrated).length() < 0.03,
+      "{:?} {:?}",
+      sampled,
+      integrated
+    );
+    // reddish, and losing no more than the light shadowed between microfacets
+    assert!(sampled.r() > sampled.b() && sampled.r() < 1.0 && sampled.r() > 0.8);
+  }
+
+  #[test]
+  fn rough_dielectric_is_consistent_and_conserves_energy() {
+    for &(direction, entering) in &[(Vec3(0.5, -1.0, 0.2), true), (Vec3(0.3, 1.0, 0.0), false)] {
+      let glass = RoughDielectric::new(1.5, 0.5);
+      let r_in = Ray::new(Vec3(0.0, 0.0, 0.0) - direction, direction);
+      let rec = hit_record(&r_in, &glass);
+      assert_eq!(entering, rec.front_face);
+      assert_eq!(entering, glass.eta(&rec) > 1.0);
+      let (sampled, integrated) = albedos(&glass, &r_in, &rec);
+      let error = (sampled - integrated).length() / sampled.length();
+      assert!(error < 0.02, "{:?} {:?}", sampled, integrated);
+      // no more energy than comes in, once transmitted radiance is spread back out
+      let mut sampler = IndependentSampler::new(9);
+      let n = 20000;
+      let mut energy = 0.0;
+      for i in 0..n {
+        sampler.start_sample(0, 0, i);
+        if let Some(scatter) = glass.scatter(&r_in, &rec, &mut sampler) {
+          let reflected = scatter.scattered.direction.dot(rec.normal) > 0.0;
+          let eta = glass.eta(&rec);
+          let spread = if reflected { 1.0 } else { eta * eta };
+          energy += scatter.attenuation.r() * spread / n as f32;
+        }
+      }
+      assert!(energy <= 1.0 && energy > 0.85, "{}", energy);
+    }
+  }
+
+  #[test]
+  fn smooth_microfacet_materials_are_mirrors() {
+    let mut sampler = IndependentSampler::new(1);
+    let mirror = RoughConductor::preset(Conductor::Aluminium, 0.0);
+    let r_in = Ray::new(Vec3(-1.0, 1.0, 0.0), Vec3(1.0, -1.0, 0.0));
+    let rec = hit_record(&r_in, &mirror);
+    let scatter = mirror.scatter(&r_in, &rec, &mut sampler).unwrap();
+    assert!(scatter.pdf.is_none());
+    assert!((scatter.scattered.direction - Vec3(1.0, 1.0, 0.0).to_unit_vector()).length() < 1e-5);
+    let glass = RoughDielectric::new(1.5, 0.0);
+    let scatter = glass.scatter(&r_in, &rec, &mut sampler).unwrap();
+    assert!(scatter.pdf.is_none());
+    assert_eq!(
+      Vec3(0.0, 0.0, 0.0),
+      glass.eval(&r_in, &rec, Vec3(0.0, -1.0, 0.0))
+    );
+  }
+
+  #[test]
+  fn henyey_greenstein_is_normalized() {
+    // integrate the phase function over the sphere in cos(theta)
+    for g in [-0.7, 0.0, 0.3, 0.9] {
+      let hg = HenyeyGreenstein::new(Vec3(1.0, 1.0, 1.0), g);
+      let n = 100_000;
+      let integral: f32 = (0..n)
+        .map(|i| {
+          let cos_theta = -1.0 + 2.0 * (i as f32 + 0.5) / n as f32;
+          2.0 * f32::consts::PI * hg.phase(cos_theta) * 2.0 / n as f32
+        })
+        .sum();
+      assert!((1.0 - integral).abs() < 1e-3, "g = {}: {}", g, integral);
+    }
+  }
+
+  #[test]
+  fn henyey_greenstein_sampling_matches_pdf() {
+    let hg = HenyeyGreenstein::new(Vec3(0.5, 0.5, 0.5), 0.6);
+    let material = Isotropic::new(Vec3(0.0, 0.0, 0.0));
+    let r_in = Ray::new(Vec3(0.0, 0.0, -1.0), Vec3(0.0, 0.0, 2.0));
+    let rec = HitRecord::new(&r_in, 0.5, Vec3(1.0, 0.0, 0.0), &material);
+    let mut mean_cosine = 0.0;
+    let n = 20000;
+    let mut sampler = IndependentSampler::new(1);
+    for _ in 0..n {
+      let scatter = hg.scatter(&r_in, &rec, &mut sampler).unwrap();
+      let direction = scatter.scattered.direction;
+      assert!((scatter.pdf.unwrap() - hg.pdf(&r_in, &rec, direction)).abs() < 1e-3);
+      mean_cosine += direction.to_unit_vector().z();
+    }
+    // the mean cosine of Henyey-Greenstein is g
+    assert!((0.6 - mean_cosine / n as f32).abs() < 0.02);
+  }
+}
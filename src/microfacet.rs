@@ -0,0 +1,301 @@
+extern crate serde;
+
+use super::vec3::Vec3;
+use serde::Deserialize;
+use std::f32;
+use std::fmt;
+use std::str::FromStr;
+
+/// GGX (Trowbridge-Reitz) distribution of microfacet normals, in the local
+/// frame of a surface whose normal is +z.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ggx {
+  pub alpha: f32,
+}
+
+impl Ggx {
+  /// Distribution for a perceptual `roughness` in [0, 1], whose square is
+  /// alpha as in the Disney and glTF models.
+  pub fn from_roughness(roughness: f32) -> Self {
+    Ggx {
+      alpha: roughness * roughness,
+    }
+  }
+
+  /// So smooth that the surface is better treated as a perfect mirror.
+  pub fn is_smooth(&self) -> bool {
+    self.alpha < 1e-3
+  }
+
+  /// Density of microfacet normals `m` per unit of projected area.
+  pub fn d(&self, m: Vec3) -> f32 {
+    if m.z() <= 0.0 {
+      return 0.0;
+    }
+    let a2 = self.alpha * self.alpha;
+    let t = m.z() * m.z() * (a2 - 1.0) + 1.0;
+    a2 / (f32::consts::PI * t * t)
+  }
+
+  fn lambda(&self, w: Vec3) -> f32 {
+    let cos2 = w.z() * w.z();
+    if cos2 == 0.0 {
+      return f32::INFINITY;
+    }
+    let tan2 = (1.0 - cos2).max(0.0) / cos2;
+    0.5 * ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0)
+  }
+
+  /// Fraction of the microfacets facing `w` that are not masked from it.
+  pub fn g1(&self, w: Vec3) -> f32 {
+    1.0 / (1.0 + self.lambda(w))
+  }
+
+  /// Height-correlated masking and shadowing for `wo` and `wi`.
+  pub fn g(&self, wo: Vec3, wi: Vec3) -> f32 {
+    1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+  }
+
+  /// Microfacet normal seen from the unit direction `wo`, picked in
+  /// proportion to its visible area, after Heitz's "Sampling the GGX
+  /// Distribution of Visible Normals".
+  pub fn sample_visible(&self, wo: Vec3, u: (f32, f32)) -> Vec3 {
+    // stretch to the hemisphere of alpha 1, where visible normals are
+    // uniform over the projected disk
+    let vh = Vec3(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()).to_unit_vector();
+    let length2 = vh.x() * vh.x() + vh.y() * vh.y();
+    let t1 = if length2 > 0.0 {
+      Vec3(-vh.y(), vh.x(), 0.0) / length2.sqrt()
+    } else {
+      Vec3(1.0, 0.0, 0.0)
+    };
+    let t2 = vh.cross(t1);
+    let r = u.0.sqrt();
+    let phi = 2.0 * f32::consts::PI * u.1;
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + vh.z());
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+    let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+    Vec3(self.alpha * nh.x(), self.alpha * nh.y(), nh.z().max(1e-6)).to_unit_vector()
+  }
+
+  /// Density of `sample_visible` picking `m` from `wo`, over solid angle.
+  pub fn pdf_visible(&self, wo: Vec3, m: Vec3) -> f32 {
+    if wo.z() <= 0.0 {
+      return 0.0;
+    }
+    self.g1(wo) * self.d(m) * wo.dot(m).max(0.0) / wo.z()
+  }
+}
+
+/// Fraction of light reflected by a smooth dielectric interface, for an
+/// angle of cosine `cos_i` on the side of the normal and `eta` the
+/// refractive index of the other side over this one's.
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+  let (cos_i, eta) = if cos_i < 0.0 {
+    (-cos_i, 1.0 / eta)
+  } else {
+    (cos_i.min(1.0), eta)
+  };
+  let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+  if sin2_t >= 1.0 {
+    return 1.0;
+  }
+  let cos_t = (1.0 - sin2_t).sqrt();
+  let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+  let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+  0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+/// Fraction of light reflected by a conductor of complex refractive index
+/// `eta` + i `k`, per channel, seen from air at an angle of cosine `cos_i`.
+pub fn fresnel_conductor(cos_i: f32, eta: Vec3, k: Vec3) -> Vec3 {
+  let cos_i = cos_i.clamp(0.0, 1.0);
+  let channel = |eta: f32, k: f32| {
+    let cos2 = cos_i * cos_i;
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_i * a;
+    let rs = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rp + rs)
+  };
+  Vec3(
+    channel(eta.r(), k.r()),
+    channel(eta.g(), k.g()),
+    channel(eta.b(), k.b()),
+  )
+}
+
+/// Direction `wo` (pointing away from the surface, on the side of `m`)
+/// refracts into, `eta` being the refractive index of the other side over
+/// this one's. `None` on total internal reflection.
+pub fn refract(wo: Vec3, m: Vec3, eta: f32) -> Option<Vec3> {
+  let cos_i = wo.dot(m);
+  let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+  if sin2_t >= 1.0 {
+    return None;
+  }
+  let cos_t = (1.0 - sin2_t).sqrt();
+  Some(-wo / eta + (cos_i / eta - cos_t) * m)
+}
+
+/// Metals with measured refractive indices.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Conductor {
+  Gold,
+  Copper,
+  Aluminium,
+}
+
+impl Conductor {
+  /// Complex refractive index (`eta`, `k`) at the red, green and blue
+  /// wavelengths of 650, 550 and 450 nm.
+  pub fn ior(self) -> (Vec3, Vec3) {
+    match self {
+      Conductor::Gold => (Vec3(0.143, 0.374, 1.442), Vec3(3.983, 2.385, 1.603)),
+      Conductor::Copper => (Vec3(0.200, 0.924, 1.102), Vec3(3.912, 2.452, 2.142)),
+      Conductor::Aluminium => (Vec3(1.657, 0.880, 0.521), Vec3(9.224, 6.270, 4.837)),
+    }
+  }
+}
+
+impl FromStr for Conductor {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "gold" => Ok(Conductor::Gold),
+      "copper" => Ok(Conductor::Copper),
+      "aluminium" => Ok(Conductor::Aluminium),
+      _ => Err(format!(
+        "unknown conductor `{}`, expected gold, copper or aluminium",
+        s
+      )),
+    }
+  }
+}
+
+impl fmt::Display for Conductor {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      Conductor::Gold => "gold",
+      Conductor::Copper => "copper",
+      Conductor::Aluminium => "aluminium",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::random::Pcg32;
+  use super::*;
+
+  /// Unit direction of polar angle `theta` and azimuth `phi`.
+  fn spherical(theta: f32, phi: f32) -> Vec3 {
+    Vec3(
+      theta.sin() * phi.cos(),
+      theta.sin() * phi.sin(),
+      theta.cos(),
+    )
+  }
+
+  #[test]
+  fn conductors_parse_their_names() {
+    for conductor in &[Conductor::Gold, Conductor::Copper, Conductor::Aluminium] {
+      assert_eq!(Ok(*conductor), conductor.to_string().parse());
+    }
+    assert!("silver".parse::<Conductor>().is_err());
+  }
+
+  #[test]
+  fn normals_project_to_unit_area() {
+    // ∫ D(m) cos θm dωm = 1, by the midpoint rule in θ
+    for alpha in &[0.05, 0.3, 1.0] {
+      let ggx = Ggx { alpha: *alpha };
+      let n = 20000;
+      let projected: f32 = (0..n)
+        .map(|i| {
+          let theta = (i as f32 + 0.5) / n as f32 * f32::consts::FRAC_PI_2;
+          let m = spherical(theta, 0.0);
+          ggx.d(m) * m.z() * 2.0 * f32::consts::PI * theta.sin() * f32::consts::FRAC_PI_2 / n as f32
+        })
+        .sum();
+      assert!((projected - 1.0).abs() < 1e-2, "{} {}", alpha, projected);
+    }
+  }
+
+  #[test]
+  fn visible_normals_follow_their_density() {
+    let ggx = Ggx::from_roughness(0.6);
+    let wo = spherical(1.2, 0.4);
+    let mut rng = Pcg32::new(3);
+    // mean of a function of m by sampling and by integrating the density
+    let f = |m: Vec3| m.x() + 2.0 * m.z() * m.z();
+    let n = 50000;
+    let mut sampled = 0.0;
+    for _ in 0..n {
+      let m = ggx.sample_visible(wo, (rng.next_f32(), rng.next_f32()));
+      assert!(m.z() > 0.0 && wo.dot(m) > -1e-4);
+      assert!((m.length() - 1.0).abs() < 1e-4);
+      sampled += f(m) / n as f32;
+    }
+    let (nt, np) = (400, 400);
+    let mut integrated = 0.0;
+    for i in 0..nt {
+      for j in 0..np {
+        let theta = (i as f32 + 0.5) / nt as f32 * f32::consts::FRAC_PI_2;
+        let phi = (j as f32 + 0.5) / np as f32 * 2.0 * f32::consts::PI;
+        let m = spherical(theta, phi);
+        let area =
+          theta.sin() * f32::consts::FRAC_PI_2 / nt as f32 * 2.0 * f32::consts::PI / np as f32;
+        integrated += f(m) * ggx.pdf_visible(wo, m) * area;
+      }
+    }
+    assert!(
+      (sampled - integrated).abs() < 1e-2,
+      "{} {}",
+      sampled,
+      integrated
+    );
+  }
+
+  #[test]
+  fn fresnel_reflectance() {
+    // ((n - 1) / (n + 1))² head on, and everything at grazing angles
+    assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-6);
+    assert!((fresnel_dielectric(-1.0, 1.0 / 1.5) - 0.04).abs() < 1e-6);
+    assert!((fresnel_dielectric(0.0, 1.5) - 1.0).abs() < 1e-6);
+    // total internal reflection past the critical angle of glass
+    assert_eq!(1.0, fresnel_dielectric(0.7, 1.0 / 1.5));
+    assert!(fresnel_dielectric(0.8, 1.0 / 1.5) < 1.0);
+    // a conductor without absorption is a dielectric
+    for cos_i in &[1.0, 0.7, 0.2] {
+      let conductor = fresnel_conductor(*cos_i, Vec3(1.5, 1.5, 1.5), Vec3(0.0, 0.0, 0.0));
+      assert!((conductor.r() - fresnel_dielectric(*cos_i, 1.5)).abs() < 1e-5);
+    }
+    // gold reflects red more than blue
+    let (eta, k) = Conductor::Gold.ior();
+    let gold = fresnel_conductor(1.0, eta, k);
+    assert!(gold.r() > 0.9 && gold.b() < 0.5, "{:?}", gold);
+  }
+
+  #[test]
+  fn refraction_obeys_snell() {
+    let wo = spherical(0.5, 1.0);
+    let m = Vec3(0.0, 0.0, 1.0);
+    let wi = refract(wo, m, 1.5).unwrap();
+    assert!((wi.length() - 1.0).abs() < 1e-5);
+    let sin_t = (1.0 - wi.z() * wi.z()).sqrt();
+    assert!((0.5f32.sin() - 1.5 * sin_t).abs() < 1e-5);
+    assert!(wi.z() < 0.0);
+    assert!(refract(spherical(1.0, 0.0), m, 1.0 / 1.5).is_none());
+  }
+}
@@ -0,0 +1,121 @@
+use super::aabb::Aabb;
+use super::hittable::{HitRecord, Hittable, Span};
+use super::material::Material;
+use super::ray::Ray;
+use super::sampler::Sampler;
+use super::sphere::{cone_pdf, cone_sample, hit_sphere, sphere_spans};
+use super::vec3::Vec3;
+
+/// Sphere moving in a straight line from `center0` at `time0` to `center1`
+/// at `time1`, and carrying on at the same speed outside that interval.
+pub struct MovingSphere {
+  pub center0: Vec3,
+  pub center1: Vec3,
+  pub time0: f32,
+  pub time1: f32,
+  pub radius: f32,
+  pub material: Box<dyn Material>,
+}
+
+impl MovingSphere {
+  pub fn new(
+    center0: Vec3,
+    center1: Vec3,
+    time0: f32,
+    time1: f32,
+    radius: f32,
+    material: Box<dyn Material>,
+  ) -> Self {
+    MovingSphere {
+      center0,
+      center1,
+      time0,
+      time1,
+      radius,
+      material,
+    }
+  }
+
+  pub fn center(&self, time: f32) -> Vec3 {
+    if self.time1 == self.time0 {
+      return self.center0;
+    }
+    let s = (time - self.time0) / (self.time1 - self.time0);
+    self.center0 + s * (self.center1 - self.center0)
+  }
+}
+
+impl Hittable for MovingSphere {
+  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+    hit_sphere(
+      self.center(r.time),
+      self.radius,
+      &*self.material,
+      r,
+      t_min,
+      t_max,
+    )
+  }
+
+  fn spans(&self, r: &Ray) -> Option<Vec<Span<'_>>> {
+    Some(sphere_spans(
+      self.center(r.time),
+      self.radius,
+      &*self.material,
+      r,
+    ))
+  }
+
+  /// Box around the sphere over `time0` to `time1`, which should span the
+  /// camera's shutter interval.
+  fn bounding_box(&self) -> Option<Aabb> {
+    let r = Vec3(self.radius, self.radius, self.radius);
+    let box0 = Aabb::new(self.center0 - r, self.center0 + r);
+    let box1 = Aabb::new(self.center1 - r, self.center1 + r);
+    Some(Aabb::surrounding(&box0, &box1))
+  }
+
+  fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+    if self
+      .hit(&Ray::new_at_time(origin, direction, time), 0.001, f32::MAX)
+      .is_none()
+    {
+      return 0.0;
+    }
+    cone_pdf(self.center(time), self.radius, origin)
+  }
+
+  fn random(&self, origin: Vec3, time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+    cone_sample(self.center(time), self.radius, origin, sampler)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::material::Lambertian;
+  use super::*;
+
+  #[test]
+  fn hit_depends_on_time() {
+    let sphere = MovingSphere::new(
+      Vec3(0.0, 0.0, -2.0),
+      Vec3(2.0, 0.0, -2.0),
+      0.0,
+      1.0,
+      0.5,
+      Box::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+    );
+    let origin = Vec3(0.0, 0.0, 0.0);
+    let direction = Vec3(0.0, 0.0, -1.0);
+    let rec = sphere.hit(&Ray::new_at_time(origin, direction, 0.0), 0.001, f32::MAX);
+    assert_eq!(1.5, rec.unwrap().t);
+    assert!(sphere
+      .hit(&Ray::new_at_time(origin, direction, 1.0), 0.001, f32::MAX)
+      .is_none());
+    assert_eq!(Vec3(1.0, 0.0, -2.0), sphere.center(0.5));
+
+    let bbox = sphere.bounding_box().unwrap();
+    assert_eq!(Vec3(-0.5, -0.5, -2.5), bbox.min);
+    assert_eq!(Vec3(2.5, 0.5, -1.5), bbox.max);
+  }
+}
@@ -0,0 +1,198 @@
+extern crate tobj;
+
+use super::material::{Dielectric, DiffuseLight, Lambertian, Material, Metal};
+use super::texture::{ImageError, ImageTexture, WrapMode};
+use super::triangle::TriangleMesh;
+use super::vec3::Vec3;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub use self::tobj::LoadError;
+
+/// Why an OBJ file could not be loaded.
+#[derive(Debug)]
+pub enum ObjError {
+  /// Unreadable or malformed OBJ or MTL file.
+  Obj(LoadError),
+  /// `map_Kd` image that could not be loaded.
+  Texture(PathBuf, ImageError),
+}
+
+impl fmt::Display for ObjError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ObjError::Obj(e) => write!(f, "{}", e),
+      ObjError::Texture(path, e) => write!(f, "could not load {}: {}", path.display(), e),
+    }
+  }
+}
+
+impl std::error::Error for ObjError {}
+
+impl From<LoadError> for ObjError {
+  fn from(e: LoadError) -> Self {
+    ObjError::Obj(e)
+  }
+}
+
+/// Albedo of faces without a material in the MTL file.
+const DEFAULT_ALBEDO: Vec3 = Vec3(0.8, 0.8, 0.8);
+
+/// Loads every object of a Wavefront OBJ file as a triangle mesh, polygons
+/// are triangulated. MTL materials are mapped onto our own:
+///
+/// - emissive ones (non-black `Ke`) become `DiffuseLight`,
+/// - transparent ones (`d` < 1 or a refraction `illum` model) become
+///   `Dielectric` with `Ni` as refractive index,
+/// - reflective ones (`illum` 3 or 5) become `Metal` tinted by `Ks`, rougher
+///   the lower `Ns` is,
+/// - everything else is `Lambertian` with `Kd` as albedo, or the `map_Kd`
+///   image if there is one.
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<Vec<TriangleMesh>, ObjError> {
+  let path = path.as_ref();
+  let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+  let (models, materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS)?;
+  let materials = materials?;
+  models
+    .into_iter()
+    .map(|model| {
+      let mesh = model.mesh;
+      let material = match mesh.material_id.and_then(|id| materials.get(id)) {
+        Some(m) => mtl_material(m, base_dir)?,
+        None => Box::new(Lambertian::new(DEFAULT_ALBEDO)),
+      };
+      Ok(TriangleMesh {
+        positions: mesh
+          .positions
+          .chunks_exact(3)
+          .map(|p| Vec3(p[0], p[1], p[2]))
+          .collect(),
+        normals: mesh
+          .normals
+          .chunks_exact(3)
+          .map(|n| Vec3(n[0], n[1], n[2]))
+          .collect(),
+        uvs: mesh
+          .texcoords
+          .chunks_exact(2)
+          .map(|t| (t[0], t[1]))
+          .collect(),
+        indices: mesh
+          .indices
+          .chunks_exact(3)
+          .map(|i| [i[0] as usize, i[1] as usize, i[2] as usize])
+          .collect(),
+        material,
+      })
+    })
+    .collect()
+}
+
+fn mtl_material(m: &tobj::Material, base_dir: &Path) -> Result<Box<dyn Material>, ObjError> {
+  let color = |c: [f32; 3]| Vec3(c[0], c[1], c[2]);
+  let illum = m.illumination_model;
+  if let Some(emit) = m.emissive.map(color) {
+    if emit.squared_length() > 0.0 {
+      return Ok(Box::new(DiffuseLight::new(emit)));
+    }
+  }
+  let transparent =
+    m.dissolve.is_some_and(|d| d < 1.0) || matches!(illum, Some(4) | Some(6) | Some(7) | Some(9));
+  if transparent {
+    let ref_idx = m.optical_density.filter(|n| *n >= 1.0).unwrap_or(1.5);
+    return Ok(Box::new(Dielectric::new(ref_idx)));
+  }
+  if let (Some(3), Some(specular)) | (Some(5), Some(specular)) = (illum, m.specular) {
+    // Blinn-Phong exponent to roughness
+    let fuzz = m
+      .shininess
+      .map_or(0.0, |ns| (2.0 / (ns.max(0.0) + 2.0)).sqrt());
+    return Ok(Box::new(Metal::new(color(specular), fuzz)));
+  }
+  if let Some(texture) = &m.diffuse_texture {
+    let path = base_dir.join(texture);
+    let texture =
+      ImageTexture::load(&path, WrapMode::Repeat).map_err(|e| ObjError::Texture(path, e))?;
+    return Ok(Box::new(Lambertian::textured(Arc::new(texture))));
+  }
+  Ok(Box::new(Lambertian::new(
+    m.diffuse.map_or(DEFAULT_ALBEDO, color),
+  )))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::hittable::Hittable;
+  use super::super::ray::Ray;
+  use super::super::triangle::TriangleMesh;
+  use super::*;
+  use std::fs;
+  use std::sync::Arc;
+
+  #[test]
+  fn load_quad_with_materials() {
+    let dir =
+      std::env::temp_dir().join(format!("rust_ray_tracing_obj_test_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+      dir.join("quad.mtl"),
+      "newmtl red\nKd 0.8 0.1 0.1\nillum 2\n\nnewmtl glass\nNi 1.33\nd 0.1\nillum 4\n",
+    )
+    .unwrap();
+    fs::write(
+      dir.join("quad.obj"),
+      "mtllib quad.mtl\n\
+       v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+       vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+       o quad\nusemtl red\nf 1/1 2/2 3/3 4/4\n\
+       o tri\nusemtl glass\nf 1/1 2/2 3/3\n",
+    )
+    .unwrap();
+
+    let meshes = load_obj(dir.join("quad.obj")).unwrap();
+    assert_eq!(2, meshes.len());
+    assert_eq!(2, meshes[0].indices.len());
+    assert_eq!(meshes[0].positions.len(), meshes[0].uvs.len());
+    assert!(meshes[0].normals.is_empty());
+
+    let mut meshes = meshes.into_iter();
+    let quad = TriangleMesh::triangles(Arc::new(meshes.next().unwrap()));
+    let r = Ray::new(Vec3(0.25, 0.75, 1.0), Vec3(0.0, 0.0, -1.0));
+    assert_eq!(
+      1,
+      quad
+        .iter()
+        .filter_map(|t| t.hit(&r, 0.0001, f32::MAX))
+        .count()
+    );
+  }
+
+  #[test]
+  fn missing_file() {
+    assert!(load_obj("does/not/exist.obj").is_err());
+  }
+
+  #[test]
+  fn missing_texture_says_why() {
+    let dir = std::env::temp_dir().join(format!(
+      "rust_ray_tracing_obj_texture_test_{}",
+      std::process::id()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("quad.mtl"), "newmtl wood\nmap_Kd wood.png\n").unwrap();
+    fs::write(
+      dir.join("quad.obj"),
+      "mtllib quad.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nusemtl wood\nf 1 2 3\n",
+    )
+    .unwrap();
+    let message = load_obj(dir.join("quad.obj")).err().unwrap().to_string();
+    // the path and the image error behind it
+    let prefix = format!("could not load {}: ", dir.join("wood.png").display());
+    assert!(
+      message.starts_with(&prefix) && message.len() > prefix.len(),
+      "{}",
+      message
+    );
+  }
+}
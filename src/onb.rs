@@ -0,0 +1,52 @@
+use super::vec3::Vec3;
+
+/// Orthonormal basis, `w` being the axis it was built around.
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+  pub u: Vec3,
+  pub v: Vec3,
+  pub w: Vec3,
+}
+
+impl Onb {
+  pub fn from_w(n: Vec3) -> Self {
+    let w = n.to_unit_vector();
+    let a = if w.x().abs() > 0.9 {
+      Vec3(0.0, 1.0, 0.0)
+    } else {
+      Vec3(1.0, 0.0, 0.0)
+    };
+    let v = w.cross(a).to_unit_vector();
+    let u = w.cross(v);
+    Onb { u, v, w }
+  }
+
+  /// World space vector with coordinates `a` in this basis.
+  pub fn local(&self, a: Vec3) -> Vec3 {
+    a.x() * self.u + a.y() * self.v + a.z() * self.w
+  }
+
+  /// Coordinates in this basis of the world space vector `a`.
+  pub fn coordinates(&self, a: Vec3) -> Vec3 {
+    Vec3(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn orthonormal() {
+    let onb = Onb::from_w(Vec3(1.0, 2.0, 3.0));
+    assert!((onb.u.length() - 1.0).abs() < 1e-6);
+    assert!((onb.v.length() - 1.0).abs() < 1e-6);
+    assert!(onb.u.dot(onb.v).abs() < 1e-6);
+    assert!(onb.u.dot(onb.w).abs() < 1e-6);
+    assert!(onb.v.dot(onb.w).abs() < 1e-6);
+    let w = onb.local(Vec3(0.0, 0.0, 1.0));
+    assert!((w - Vec3(1.0, 2.0, 3.0).to_unit_vector()).length() < 1e-6);
+    let a = Vec3(0.3, -1.0, 2.0);
+    assert!((onb.local(onb.coordinates(a)) - a).length() < 1e-5);
+  }
+}
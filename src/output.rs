@@ -0,0 +1,477 @@
+extern crate png;
+
+use super::image::Image;
+use super::random::mix_seed;
+use super::tonemap::ToneMapping;
+use super::vec3::Vec3;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// File formats an `Image` can be written as.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+  Png8,
+  Png16,
+  /// Binary (P6) portable pixmap.
+  Ppm,
+  /// Portable float map, linear 32-bit float.
+  Pfm,
+  /// Uncompressed OpenEXR with 16-bit half channels.
+  ExrHalf,
+  /// Uncompressed OpenEXR with 32-bit float channels.
+  ExrFloat,
+}
+
+impl ImageFormat {
+  /// Picks the format from the file extension, `None` if it is not recognized.
+  pub fn from_path<P: AsRef<Path>>(path: P) -> Option<ImageFormat> {
+    let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+      "png" => Some(ImageFormat::Png8),
+      "ppm" => Some(ImageFormat::Ppm),
+      "pfm" => Some(ImageFormat::Pfm),
+      "exr" => Some(ImageFormat::ExrHalf),
+      _ => None,
+    }
+  }
+
+  /// Same container with more bits per channel: 16-bit PNG and float EXR.
+  pub fn high_precision(self) -> ImageFormat {
+    match self {
+      ImageFormat::Png8 => ImageFormat::Png16,
+      ImageFormat::ExrHalf => ImageFormat::ExrFloat,
+      other => other,
+    }
+  }
+}
+
+/// Writes `image` to `path` in the format given by its extension.
+pub fn save<P: AsRef<Path>>(image: &Image, path: P, tone_mapping: &ToneMapping) -> io::Result<()> {
+  let format = ImageFormat::from_path(&path).ok_or_else(|| {
+    io::Error::new(
+      io::ErrorKind::InvalidInput,
+      format!(
+        "unsupported image extension for {} (expected png, ppm, pfm or exr)",
+        path.as_ref().display()
+      ),
+    )
+  })?;
+  save_as(image, path, format, tone_mapping)
+}
+
+pub fn save_as<P: AsRef<Path>>(
+  image: &Image,
+  path: P,
+  format: ImageFormat,
+  tone_mapping: &ToneMapping,
+) -> io::Result<()> {
+  let mut w = BufWriter::new(File::create(path)?);
+  write(image, &mut w, format, tone_mapping)?;
+  w.flush()
+}
+
+/// Encodes `image` into `w`. 8 and 16-bit formats are tone mapped and sRGB
+/// encoded, float formats keep the linear values untouched.
+pub fn write<W: Write>(
+  image: &Image,
+  w: &mut W,
+  format: ImageFormat,
+  tone_mapping: &ToneMapping,
+) -> io::Result<()> {
+  match format {
+    ImageFormat::Png8 | ImageFormat::Png16 => {
+      write_png(image, w, format == ImageFormat::Png16, tone_mapping)
+    }
+    ImageFormat::Ppm => write_ppm(image, w, tone_mapping),
+    ImageFormat::Pfm => write_pfm(image, w),
+    ImageFormat::ExrHalf => write_exr(image, w, ExrPixel::Half),
+    ImageFormat::ExrFloat => write_exr(image, w, ExrPixel::Float),
+  }
+}
+
+/// 8-bit sRGB values of the image, row by row.
+fn to_u8(image: &Image, tone_mapping: &ToneMapping) -> Vec<u8> {
+  let mut data = Vec::with_capacity(image.pixels().len() * 3);
+  for (i, col) in image.pixels().iter().enumerate() {
+    let col = tone_mapping.encode(*col);
+    for (channel, c) in [col.r(), col.g(), col.b()].iter().enumerate() {
+      let noise = if tone_mapping.dither {
+        dither_noise(mix_seed(i as u64, channel as u64))
+      } else {
+        0.0
+      };
+      data.push((255.0 * c + noise).round().clamp(0.0, 255.0) as u8);
+    }
+  }
+  data
+}
+
+/// Triangular noise in (-1, 1) from a hash, the same for every run: the sum
+/// of two uniform numbers, which unlike one makes the error's variance
+/// independent of the signal.
+fn dither_noise(hash: u64) -> f32 {
+  let uniform = |bits: u64| (bits & 0xff_ffff) as f32 / (1u32 << 24) as f32;
+  uniform(hash) + uniform(hash >> 32) - 1.0
+}
+
+fn to_u16(c: f32) -> u16 {
+  (65535.0 * c).round() as u16
+}
+
+fn write_png<W: Write>(
+  image: &Image,
+  w: &mut W,
+  sixteen_bit: bool,
+  tone_mapping: &ToneMapping,
+) -> io::Result<()> {
+  let mut encoder = png::Encoder::new(w, image.width as u32, image.height as u32);
+  encoder.set_color(png::ColorType::Rgb);
+  let data = if sixteen_bit {
+    encoder.set_depth(png::BitDepth::Sixteen);
+    let mut data = Vec::with_capacity(image.pixels().len() * 6);
+    for col in image.pixels().iter().map(|c| tone_mapping.encode(*c)) {
+      for c in &[col.r(), col.g(), col.b()] {
+        // PNG stores 16-bit samples big endian
+        data.extend_from_slice(&to_u16(*c).to_be_bytes());
+      }
+    }
+    data
+  } else {
+    encoder.set_depth(png::BitDepth::Eight);
+    to_u8(image, tone_mapping)
+  };
+  let mut writer = encoder.write_header().map_err(png_error)?;
+  writer.write_image_data(&data).map_err(png_error)?;
+  writer.finish().map_err(png_error)
+}
+
+fn png_error(e: png::EncodingError) -> io::Error {
+  match e {
+    png::EncodingError::IoError(e) => e,
+    e => io::Error::other(e),
+  }
+}
+
+fn write_ppm<W: Write>(image: &Image, w: &mut W, tone_mapping: &ToneMapping) -> io::Result<()> {
+  write!(w, "P6\n{} {}\n255\n", image.width, image.height)?;
+  w.write_all(&to_u8(image, tone_mapping))
+}
+
+fn write_pfm<W: Write>(image: &Image, w: &mut W) -> io::Result<()> {
+  // a negative scale marks little endian data
+  write!(w, "PF\n{} {}\n-1.0\n", image.width, image.height)?;
+  let mut data = Vec::with_capacity(image.pixels().len() * 12);
+  // rows are stored bottom to top
+  for y in (0..image.height).rev() {
+    for x in 0..image.width {
+      let col = image.get(x, y);
+      for c in &[col.r(), col.g(), col.b()] {
+        data.extend_from_slice(&c.to_le_bytes());
+      }
+    }
+  }
+  w.write_all(&data)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ExrPixel {
+  Half,
+  Float,
+}
+
+impl ExrPixel {
+  fn type_id(self) -> i32 {
+    match self {
+      ExrPixel::Half => 1,
+      ExrPixel::Float => 2,
+    }
+  }
+
+  fn size(self) -> usize {
+    match self {
+      ExrPixel::Half => 2,
+      ExrPixel::Float => 4,
+    }
+  }
+}
+
+fn exr_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+  header.extend_from_slice(name.as_bytes());
+  header.push(0);
+  header.extend_from_slice(kind.as_bytes());
+  header.push(0);
+  header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+  header.extend_from_slice(value);
+}
+
+/// Single part, scanline, uncompressed OpenEXR file with B, G and R channels.
+fn write_exr<W: Write>(image: &Image, w: &mut W, pixel: ExrPixel) -> io::Result<()> {
+  let mut header = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+
+  // channels have to be listed in alphabetical order
+  let mut channels = Vec::new();
+  for name in &["B", "G", "R"] {
+    channels.extend_from_slice(name.as_bytes());
+    channels.push(0);
+    channels.extend_from_slice(&pixel.type_id().to_le_bytes());
+    channels.extend_from_slice(&[0, 0, 0, 0]); // pLinear and reserved
+    channels.extend_from_slice(&1i32.to_le_bytes()); // x sampling
+    channels.extend_from_slice(&1i32.to_le_bytes()); // y sampling
+  }
+  channels.push(0);
+  exr_attribute(&mut header, "channels", "chlist", &channels);
+  exr_attribute(&mut header, "compression", "compression", &[0]);
+  let mut window = Vec::new();
+  for v in &[0, 0, image.width as i32 - 1, image.height as i32 - 1] {
+    window.extend_from_slice(&v.to_le_bytes());
+  }
+  exr_attribute(&mut header, "dataWindow", "box2i", &window);
+  exr_attribute(&mut header, "displayWindow", "box2i", &window);
+  exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+  exr_attribute(
+    &mut header,
+    "pixelAspectRatio",
+    "float",
+    &1.0f32.to_le_bytes(),
+  );
+  exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+  exr_attribute(
+    &mut header,
+    "screenWindowWidth",
+    "float",
+    &1.0f32.to_le_bytes(),
+  );
+  header.push(0);
+
+  // one scanline per block: y, byte count, then each channel's row in turn
+  let line_size = image.width * 3 * pixel.size();
+  let block_size = 8 + line_size;
+  let first_block = header.len() + 8 * image.height;
+  for y in 0..image.height {
+    header.extend_from_slice(&((first_block + y * block_size) as u64).to_le_bytes());
+  }
+  w.write_all(&header)?;
+
+  let mut block = Vec::with_capacity(block_size);
+  for y in 0..image.height {
+    block.clear();
+    block.extend_from_slice(&(y as i32).to_le_bytes());
+    block.extend_from_slice(&(line_size as i32).to_le_bytes());
+    for channel in &[Vec3::b, Vec3::g, Vec3::r] {
+      for x in 0..image.width {
+        let c = channel(&image.get(x, y));
+        match pixel {
+          ExrPixel::Half => block.extend_from_slice(&f32_to_f16(c).to_le_bytes()),
+          ExrPixel::Float => block.extend_from_slice(&c.to_le_bytes()),
+        }
+      }
+    }
+    w.write_all(&block)?;
+  }
+  Ok(())
+}
+
+/// Converts to IEEE 754 half precision bits, rounding to nearest.
+fn f32_to_f16(value: f32) -> u16 {
+  let bits = value.to_bits();
+  let sign = ((bits >> 16) & 0x8000) as u16;
+  let exponent = ((bits >> 23) & 0xff) as i32;
+  let mantissa = bits & 0x7f_ffff;
+  if exponent == 0xff {
+    // infinity stays infinity, NaN stays NaN
+    return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+  }
+  let e = exponent - 127 + 15;
+  if e >= 0x1f {
+    return sign | 0x7c00;
+  }
+  if e <= 0 {
+    // subnormal half, or too small and flushed to zero
+    if e < -10 {
+      return sign;
+    }
+    let m = mantissa | 0x80_0000;
+    let shift = (14 - e) as u32;
+    let round = (m >> (shift - 1)) & 1;
+    return sign | ((m >> shift) + round) as u16;
+  }
+  let half = sign | ((e as u16) << 10) | (mantissa >> 13) as u16;
+  // a carry out of the mantissa correctly bumps the exponent
+  half + ((mantissa >> 12) & 1) as u16
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::texture::srgb_to_linear;
+  use super::super::tonemap::ToneMap;
+  use super::*;
+
+  fn test_image() -> Image {
+    let mut image = Image::new(2, 2);
+    image.set(0, 0, Vec3(1.0, 0.25, 0.0));
+    image.set(1, 0, Vec3(4.0, -1.0, 0.5));
+    image.set(0, 1, Vec3(0.0, 0.0, 1.0));
+    image
+  }
+
+  #[test]
+  fn format_from_extension() {
+    assert_eq!(Some(ImageFormat::Png8), ImageFormat::from_path("out.png"));
+    assert_eq!(Some(ImageFormat::Ppm), ImageFormat::from_path("out.PPM"));
+    assert_eq!(Some(ImageFormat::Pfm), ImageFormat::from_path("a/b.pfm"));
+    assert_eq!(
+      Some(ImageFormat::ExrHalf),
+      ImageFormat::from_path("out.exr")
+    );
+    assert_eq!(None, ImageFormat::from_path("out.jpg"));
+    assert_eq!(None, ImageFormat::from_path("out"));
+    assert_eq!(ImageFormat::Png16, ImageFormat::Png8.high_precision());
+    assert_eq!(ImageFormat::ExrFloat, ImageFormat::ExrHalf.high_precision());
+  }
+
+  #[test]
+  fn ppm_is_clamped_and_srgb_encoded() {
+    let mut out = Vec::new();
+    write(
+      &test_image(),
+      &mut out,
+      ImageFormat::Ppm,
+      &ToneMapping::default(),
+    )
+    .unwrap();
+    let header = b"P6\n2 2\n255\n";
+    assert_eq!(&header[..], &out[..header.len()]);
+    assert_eq!(
+      &[255, 137, 0, 255, 0, 188, 0, 0, 255, 0, 0, 0][..],
+      &out[header.len()..]
+    );
+  }
+
+  #[test]
+  fn exposure_and_tone_map_leave_float_formats_alone() {
+    let tone_mapping = ToneMapping {
+      tone_map: ToneMap::Aces,
+      exposure: 2.0,
+      dither: true,
+    };
+    for format in &[ImageFormat::Pfm, ImageFormat::ExrFloat] {
+      let (mut plain, mut mapped) = (Vec::new(), Vec::new());
+      write(&test_image(), &mut plain, *format, &ToneMapping::default()).unwrap();
+      write(&test_image(), &mut mapped, *format, &tone_mapping).unwrap();
+      assert_eq!(plain, mapped);
+    }
+  }
+
+  #[test]
+  fn dithering_keeps_the_mean_between_code_values() {
+    // 100.3 in 8-bit sRGB
+    let value = srgb_to_linear(100.3 / 255.0);
+    let mut image = Image::new(64, 64);
+    for y in 0..64 {
+      for x in 0..64 {
+        image.set(x, y, Vec3(value, value, value));
+      }
+    }
+    let plain = to_u8(&image, &ToneMapping::default());
+    assert!(plain.iter().all(|&c| c == 100));
+    let dithered = to_u8(
+      &image,
+      &ToneMapping {
+        dither: true,
+        ..ToneMapping::default()
+      },
+    );
+    assert!(dithered.iter().all(|&c| (99..=102).contains(&c)));
+    let mean = dithered.iter().map(|&c| f32::from(c)).sum::<f32>() / dithered.len() as f32;
+    assert!((mean - 100.3).abs() < 0.02, "{}", mean);
+    assert_eq!(
+      dithered,
+      to_u8(
+        &image,
+        &ToneMapping {
+          dither: true,
+          ..ToneMapping::default()
+        }
+      )
+    );
+  }
+
+  #[test]
+  fn pfm_rows_bottom_to_top() {
+    let mut out = Vec::new();
+    write(
+      &test_image(),
+      &mut out,
+      ImageFormat::Pfm,
+      &ToneMapping::default(),
+    )
+    .unwrap();
+    let header = b"PF\n2 2\n-1.0\n";
+    assert_eq!(&header[..], &out[..header.len()]);
+    let data = &out[header.len()..];
+    assert_eq!(48, data.len());
+    assert_eq!(1.0f32.to_le_bytes(), data[8..12]);
+    assert_eq!(1.0f32.to_le_bytes(), data[24..28]);
+    assert_eq!(4.0f32.to_le_bytes(), data[36..40]);
+  }
+
+  #[test]
+  fn png_round_trip() {
+    let mut out = Vec::new();
+    write(
+      &test_image(),
+      &mut out,
+      ImageFormat::Png16,
+      &ToneMapping::default(),
+    )
+    .unwrap();
+    let decoder = png::Decoder::new(&out[..]);
+    let mut reader = decoder.read_info().unwrap();
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).unwrap();
+    assert_eq!((2, 2), (info.width, info.height));
+    assert_eq!(png::BitDepth::Sixteen, info.bit_depth);
+    // 0.25 is 35199 in 16-bit sRGB
+    assert_eq!(&[255, 255, 137, 127, 0, 0][..], &buf[..6]);
+  }
+
+  #[test]
+  fn exr_layout() {
+    let mut out = Vec::new();
+    write(
+      &test_image(),
+      &mut out,
+      ImageFormat::ExrFloat,
+      &ToneMapping::default(),
+    )
+    .unwrap();
+    assert_eq!(&[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0][..], &out[..8]);
+    let mut half = Vec::new();
+    write(
+      &test_image(),
+      &mut half,
+      ImageFormat::ExrHalf,
+      &ToneMapping::default(),
+    )
+    .unwrap();
+    // same header, two rows of 2 pixels x 3 channels, half the bytes each
+    assert_eq!(2 * 2 * 3 * 2, out.len() - half.len());
+    // the last block is row 1: y, size, then B, G and R of both pixels
+    let last = &out[out.len() - 32..];
+    assert_eq!(1i32.to_le_bytes(), last[..4]);
+    assert_eq!(24i32.to_le_bytes(), last[4..8]);
+    assert_eq!(1.0f32.to_le_bytes(), last[8..12]);
+  }
+
+  #[test]
+  fn half_conversion() {
+    assert_eq!(0x3c00, f32_to_f16(1.0));
+    assert_eq!(0xb800, f32_to_f16(-0.5));
+    assert_eq!(0x7bff, f32_to_f16(65504.0));
+    assert_eq!(0x7c00, f32_to_f16(1e6));
+    assert_eq!(0x0001, f32_to_f16(2f32.powi(-24)));
+    assert_eq!(0x0000, f32_to_f16(1e-10));
+    assert_eq!(0x3555, f32_to_f16(1.0 / 3.0));
+  }
+}
@@ -0,0 +1,106 @@
+extern crate rand;
+use super::vec3::Vec3;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+const POINT_COUNT: usize = 256;
+
+/// Perlin gradient noise, built from a seed so scenes using it are reproducible.
+pub struct Perlin {
+  gradients: Vec<Vec3>,
+  perm_x: Vec<usize>,
+  perm_y: Vec<usize>,
+  perm_z: Vec<usize>,
+}
+
+impl Perlin {
+  pub fn new(seed: u64) -> Self {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let gradients = (0..POINT_COUNT)
+      .map(|_| {
+        Vec3(
+          rng.gen_range(-1.0, 1.0),
+          rng.gen_range(-1.0, 1.0),
+          rng.gen_range(-1.0, 1.0),
+        )
+        .to_unit_vector()
+      })
+      .collect();
+    let mut permutation = || {
+      let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+      p.shuffle(&mut rng);
+      p
+    };
+    let perm_x = permutation();
+    let perm_y = permutation();
+    let perm_z = permutation();
+    Perlin {
+      gradients,
+      perm_x,
+      perm_y,
+      perm_z,
+    }
+  }
+
+  /// Smooth noise in [-1, 1].
+  pub fn noise(&self, p: Vec3) -> f32 {
+    let (fx, fy, fz) = (p.x().floor(), p.y().floor(), p.z().floor());
+    let (u, v, w) = (p.x() - fx, p.y() - fy, p.z() - fz);
+    let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+    let mut accum = 0.0;
+    // Hermite smoothing of the weights hides the lattice
+    let (uu, vv, ww) = (
+      u * u * (3.0 - 2.0 * u),
+      v * v * (3.0 - 2.0 * v),
+      w * w * (3.0 - 2.0 * w),
+    );
+    for di in 0..2 {
+      for dj in 0..2 {
+        for dk in 0..2 {
+          let index = self.perm_x[((i + di) & 255) as usize]
+            ^ self.perm_y[((j + dj) & 255) as usize]
+            ^ self.perm_z[((k + dk) & 255) as usize];
+          let weight = Vec3(u - di as f32, v - dj as f32, w - dk as f32);
+          let (di, dj, dk) = (di as f32, dj as f32, dk as f32);
+          accum += (di * uu + (1.0 - di) * (1.0 - uu))
+            * (dj * vv + (1.0 - dj) * (1.0 - vv))
+            * (dk * ww + (1.0 - dk) * (1.0 - ww))
+            * self.gradients[index].dot(weight);
+        }
+      }
+    }
+    accum
+  }
+
+  /// Sum of `depth` octaves of noise at doubling frequencies and halving amplitudes.
+  pub fn turbulence(&self, p: Vec3, depth: usize) -> f32 {
+    let mut accum = 0.0;
+    let mut p = p;
+    let mut weight = 1.0;
+    for _ in 0..depth {
+      accum += weight * self.noise(p);
+      weight *= 0.5;
+      p *= 2.0;
+    }
+    accum.abs()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn noise_is_seeded_and_bounded() {
+    let a = Perlin::new(1);
+    let b = Perlin::new(1);
+    for i in 0..50 {
+      let p = Vec3(i as f32 * 0.37, i as f32 * -0.21, 3.3);
+      assert_eq!(a.noise(p), b.noise(p));
+      assert!(a.noise(p).abs() <= 1.0);
+    }
+    // zero at lattice points
+    assert_eq!(0.0, a.noise(Vec3(2.0, -3.0, 5.0)));
+  }
+}
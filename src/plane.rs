@@ -0,0 +1,90 @@
+use super::aabb::Aabb;
+use super::hittable::{HitRecord, Hittable};
+use super::material::Material;
+use super::onb::Onb;
+use super::ray::Ray;
+use super::vec3::Vec3;
+
+/// Infinite plane through `point`, facing `normal`. Its surface coordinates
+/// are distances from `point` along two axes of the plane, so image
+/// textures repeat once per unit.
+pub struct Plane {
+  pub point: Vec3,
+  pub normal: Vec3,
+  pub material: Box<dyn Material>,
+  frame: Onb,
+}
+
+impl Plane {
+  pub fn new(point: Vec3, normal: Vec3, material: Box<dyn Material>) -> Self {
+    let frame = Onb::from_w(normal);
+    Plane {
+      point,
+      normal: frame.w,
+      material,
+      frame,
+    }
+  }
+}
+
+impl Hittable for Plane {
+  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+    let denominator = r.direction.dot(self.normal);
+    if denominator == 0.0 {
+      return None;
+    }
+    let t = (self.point - r.origin).dot(self.normal) / denominator;
+    if !(t_min < t && t < t_max) {
+      return None;
+    }
+    let local = self.frame.coordinates(r.point_at_parameter(t) - self.point);
+    Some(HitRecord::new(r, t, self.normal, &*self.material).with_uv(
+      local.x(),
+      local.y(),
+      self.frame.u,
+      self.frame.v,
+    ))
+  }
+
+  fn bounding_box(&self) -> Option<Aabb> {
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::material::Lambertian;
+  use super::*;
+
+  #[test]
+  fn plane_hit() {
+    let plane = Plane::new(
+      Vec3(0.0, -1.0, 0.0),
+      Vec3(0.0, 2.0, 0.0),
+      Box::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+    );
+    let r = Ray::new(Vec3(1.0, 1.0, 0.0), Vec3(1.0, -1.0, 0.0));
+    let rec = plane.hit(&r, 0.001, f32::MAX).unwrap();
+    assert_eq!(2.0, rec.t);
+    assert_eq!(Vec3(3.0, -1.0, 0.0), rec.p);
+    assert_eq!(Vec3(0.0, 1.0, 0.0), rec.normal);
+    // u and v measure distances in the plane
+    assert!((rec.u * rec.u + rec.v * rec.v - 9.0).abs() < 1e-5);
+    // from below, and parallel to it
+    assert!(plane
+      .hit(
+        &Ray::new(Vec3(0.0, -2.0, 0.0), Vec3(0.0, 1.0, 0.0)),
+        0.001,
+        f32::MAX
+      )
+      .is_some());
+    assert!(plane
+      .hit(
+        &Ray::new(Vec3(0.0, 1.0, 0.0), Vec3(1.0, 0.0, 0.0)),
+        0.001,
+        f32::MAX
+      )
+      .is_none());
+    assert!(plane.bounding_box().is_none());
+  }
+}
@@ -0,0 +1,175 @@
+use super::film::Film;
+use super::image::Image;
+use super::vec3::Vec3;
+
+/// Samples a pixel takes before its error estimate is trusted.
+pub const MIN_ADAPTIVE_SAMPLES: u32 = 16;
+
+/// Running estimate of one pixel: the sum of its samples, and the mean and
+/// sum of squared deviations of their luminance (Welford's algorithm).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PixelStats {
+  pub samples: u32,
+  pub sum: Vec3,
+  pub(crate) mean_luminance: f32,
+  pub(crate) m2: f32,
+}
+
+impl PixelStats {
+  pub fn add(&mut self, color: Vec3) {
+    self.samples += 1;
+    self.sum += color;
+    let l = luminance(color);
+    let delta = l - self.mean_luminance;
+    self.mean_luminance += delta / self.samples as f32;
+    self.m2 += delta * (l - self.mean_luminance);
+  }
+
+  /// Adds the samples summarized by `other` (Chan et al.'s pairwise update).
+  pub fn merge(&mut self, other: &PixelStats) {
+    if other.samples == 0 {
+      return;
+    }
+    let n = self.samples + other.samples;
+    let delta = other.mean_luminance - self.mean_luminance;
+    self.mean_luminance += delta * other.samples as f32 / n as f32;
+    self.m2 += other.m2 + delta * delta * self.samples as f32 * other.samples as f32 / n as f32;
+    self.samples = n;
+    self.sum += other.sum;
+  }
+
+  pub fn mean(&self) -> Vec3 {
+    if self.samples == 0 {
+      Vec3(0.0, 0.0, 0.0)
+    } else {
+      self.sum / self.samples as f32
+    }
+  }
+
+  /// Standard error of the square root of the mean luminance, that is the
+  /// luminance's standard error over twice the root of its mean. The square
+  /// root compresses roughly like perception does, so dark pixels are not
+  /// held to a stricter absolute standard than the eye; it does not depend on
+  /// the tone mapping or output encoding.
+  pub fn error(&self) -> f32 {
+    if self.samples < 2 {
+      return f32::INFINITY;
+    }
+    let n = self.samples as f32;
+    let standard_error = (self.m2.max(0.0) / ((n - 1.0) * n)).sqrt();
+    standard_error / (2.0 * self.mean_luminance.max(1e-4).sqrt())
+  }
+
+  /// Whether the pixel needs no more samples: it has `max_samples` or, with a
+  /// positive `noise_threshold`, an error below it.
+  pub fn is_done(&self, max_samples: usize, noise_threshold: f32) -> bool {
+    self.samples as usize >= max_samples
+      || (noise_threshold > 0.0
+        && self.samples >= MIN_ADAPTIVE_SAMPLES
+        && self.error() < noise_threshold)
+  }
+}
+
+/// Rec. 709 luminance of a linear color.
+pub(crate) fn luminance(c: Vec3) -> f32 {
+  0.2126 * c.r() + 0.7152 * c.g() + 0.0722 * c.b()
+}
+
+/// Image being rendered progressively: statistics of every pixel, used to
+/// decide where to sample next, the film the samples were splatted into and
+/// the number of passes made so far.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Progress {
+  pub width: usize,
+  pub height: usize,
+  pub passes: usize,
+  pixels: Vec<PixelStats>,
+  film: Film,
+}
+
+impl Progress {
+  pub fn new(width: usize, height: usize) -> Self {
+    Progress {
+      width,
+      height,
+      passes: 0,
+      pixels: vec![PixelStats::default(); width * height],
+      film: Film::new(width, height),
+    }
+  }
+
+  pub fn pixel(&self, x: usize, y: usize) -> &PixelStats {
+    &self.pixels[y * self.width + x]
+  }
+
+  pub fn pixel_mut(&mut self, x: usize, y: usize) -> &mut PixelStats {
+    &mut self.pixels[y * self.width + x]
+  }
+
+  pub fn film(&self) -> &Film {
+    &self.film
+  }
+
+  pub fn film_mut(&mut self) -> &mut Film {
+    &mut self.film
+  }
+
+  /// Number of pixels still taking samples.
+  pub fn active_pixels(&self, max_samples: usize, noise_threshold: f32) -> usize {
+    self
+      .pixels
+      .iter()
+      .filter(|p| !p.is_done(max_samples, noise_threshold))
+      .count()
+  }
+
+  pub fn total_samples(&self) -> u64 {
+    self.pixels.iter().map(|p| u64::from(p.samples)).sum()
+  }
+
+  /// Current estimate of the image.
+  pub fn image(&self) -> Image {
+    self.film.image()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn welford_and_merge_agree() {
+    let colors: Vec<Vec3> = (0..10)
+      .map(|i| Vec3(i as f32 * 0.1, (i * i) as f32 * 0.01, 1.0))
+      .collect();
+    let mut all = PixelStats::default();
+    colors.iter().for_each(|c| all.add(*c));
+    let (mut first, mut second) = (PixelStats::default(), PixelStats::default());
+    colors[..3].iter().for_each(|c| first.add(*c));
+    colors[3..].iter().for_each(|c| second.add(*c));
+    first.merge(&second);
+    assert_eq!(10, first.samples);
+    assert!((all.mean_luminance - first.mean_luminance).abs() < 1e-6);
+    assert!((all.m2 - first.m2).abs() < 1e-5);
+
+    // variance of the luminances computed directly
+    let l: Vec<f32> = colors.iter().map(|c| luminance(*c)).collect();
+    let mean = l.iter().sum::<f32>() / 10.0;
+    let m2: f32 = l.iter().map(|x| (x - mean) * (x - mean)).sum();
+    assert!((m2 - all.m2).abs() < 1e-5);
+  }
+
+  #[test]
+  fn constant_pixels_converge() {
+    let mut stats = PixelStats::default();
+    for _ in 0..MIN_ADAPTIVE_SAMPLES - 1 {
+      stats.add(Vec3(0.5, 0.5, 0.5));
+    }
+    assert!(!stats.is_done(100, 0.01));
+    stats.add(Vec3(0.5, 0.5, 0.5));
+    assert_eq!(0.0, stats.error());
+    assert!(stats.is_done(100, 0.01));
+    assert!(!stats.is_done(100, 0.0));
+    assert!(stats.is_done(16, 0.0));
+  }
+}
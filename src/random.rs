@@ -0,0 +1,79 @@
+/// PCG32 (XSH RR variant) random number generator, small and fast enough to
+/// be seeded afresh for every sample.
+#[derive(Clone, Debug)]
+pub struct Pcg32 {
+  state: u64,
+  increment: u64,
+}
+
+impl Pcg32 {
+  pub fn new(seed: u64) -> Self {
+    // the stream comes from the seed as well, so nearby seeds give unrelated sequences
+    let mut rng = Pcg32 {
+      state: 0,
+      increment: (mix_seed(seed, 0) << 1) | 1,
+    };
+    rng.next_u32();
+    rng.state = rng.state.wrapping_add(seed);
+    rng.next_u32();
+    rng
+  }
+
+  pub fn next_u32(&mut self) -> u32 {
+    let old = self.state;
+    self.state = old
+      .wrapping_mul(6_364_136_223_846_793_005)
+      .wrapping_add(self.increment);
+    let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+    xorshifted.rotate_right((old >> 59) as u32)
+  }
+
+  /// Uniform in [0, 1).
+  pub fn next_f32(&mut self) -> f32 {
+    (self.next_u32() >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+  }
+}
+
+/// Derives an independent seed for stream `index` from a global `seed` (SplitMix64 finalizer).
+pub fn mix_seed(seed: u64, index: u64) -> u64 {
+  let mut z = seed ^ index.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+  z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+  z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reseed_repeats_sequence() {
+    let mut rng = Pcg32::new(42);
+    let a: Vec<f32> = (0..4).map(|_| rng.next_f32()).collect();
+    let mut rng = Pcg32::new(42);
+    let b: Vec<f32> = (0..4).map(|_| rng.next_f32()).collect();
+    assert_eq!(a, b);
+    let mut rng = Pcg32::new(43);
+    let c: Vec<f32> = (0..4).map(|_| rng.next_f32()).collect();
+    assert_ne!(a, c);
+  }
+
+  #[test]
+  fn uniform() {
+    let mut rng = Pcg32::new(1);
+    let n = 100_000;
+    let mut buckets = [0; 10];
+    for _ in 0..n {
+      let x = rng.next_f32();
+      assert!((0.0..1.0).contains(&x));
+      buckets[(x * 10.0) as usize] += 1;
+    }
+    assert!(buckets.iter().all(|&b| (b - n / 10i32).abs() < 500));
+  }
+
+  #[test]
+  fn mixed_seeds_differ() {
+    assert_ne!(mix_seed(7, 0), mix_seed(7, 1));
+    assert_ne!(mix_seed(7, 0), mix_seed(8, 0));
+  }
+}
@@ -4,6 +4,9 @@ use super::vec3::Vec3;
 pub struct Ray {
   pub origin: Vec3,
   pub direction: Vec3,
+  /// Instant within the camera's shutter interval the ray exists at, which
+  /// places moving objects.
+  pub time: f32,
 }
 
 impl Ray {
@@ -11,6 +14,15 @@ impl Ray {
     Ray {
       origin: o,
       direction: d,
+      time: 0.0,
+    }
+  }
+
+  pub fn new_at_time(o: Vec3, d: Vec3, time: f32) -> Self {
+    Ray {
+      origin: o,
+      direction: d,
+      time,
     }
   }
 
@@ -0,0 +1,176 @@
+use super::aabb::{axis_of, Aabb};
+use super::hittable::{HitRecord, Hittable};
+use super::material::Material;
+use super::ray::Ray;
+use super::sampler::Sampler;
+use super::vec3::Vec3;
+
+/// Rectangle from (`a0`, `b0`) to (`a1`, `b1`) in an axis-aligned plane at
+/// `k` along the remaining axis, facing that axis' positive direction. u and
+/// v go from 0 to 1 across it.
+pub struct AxisRect {
+  /// Axes spanning the rectangle and the one it faces, 0 = x, 1 = y, 2 = z.
+  axes: (usize, usize, usize),
+  pub a0: f32,
+  pub a1: f32,
+  pub b0: f32,
+  pub b1: f32,
+  pub k: f32,
+  pub material: Box<dyn Material>,
+}
+
+impl AxisRect {
+  /// In the plane z = `k`.
+  pub fn xy(x0: f32, x1: f32, y0: f32, y1: f32, k: f32, material: Box<dyn Material>) -> Self {
+    AxisRect::new((0, 1, 2), (x0, x1, y0, y1), k, material)
+  }
+
+  /// In the plane y = `k`.
+  pub fn xz(x0: f32, x1: f32, z0: f32, z1: f32, k: f32, material: Box<dyn Material>) -> Self {
+    AxisRect::new((0, 2, 1), (x0, x1, z0, z1), k, material)
+  }
+
+  /// In the plane x = `k`.
+  pub fn yz(y0: f32, y1: f32, z0: f32, z1: f32, k: f32, material: Box<dyn Material>) -> Self {
+    AxisRect::new((1, 2, 0), (y0, y1, z0, z1), k, material)
+  }
+
+  fn new(
+    axes: (usize, usize, usize),
+    (a0, a1, b0, b1): (f32, f32, f32, f32),
+    k: f32,
+    material: Box<dyn Material>,
+  ) -> Self {
+    AxisRect {
+      axes,
+      a0: a0.min(a1),
+      a1: a0.max(a1),
+      b0: b0.min(b1),
+      b1: b0.max(b1),
+      k,
+      material,
+    }
+  }
+
+  /// Point with coordinates `a`, `b` and `k` on the rectangle's axes.
+  fn point(&self, a: f32, b: f32, k: f32) -> Vec3 {
+    let mut coordinates = [0.0; 3];
+    coordinates[self.axes.0] = a;
+    coordinates[self.axes.1] = b;
+    coordinates[self.axes.2] = k;
+    Vec3(coordinates[0], coordinates[1], coordinates[2])
+  }
+
+  fn area(&self) -> f32 {
+    (self.a1 - self.a0) * (self.b1 - self.b0)
+  }
+}
+
+impl Hittable for AxisRect {
+  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+    let (axis_a, axis_b, axis_k) = self.axes;
+    let t = (self.k - axis_of(r.origin, axis_k)) / axis_of(r.direction, axis_k);
+    if !(t_min < t && t < t_max) {
+      return None;
+    }
+    let p = r.point_at_parameter(t);
+    let (a, b) = (axis_of(p, axis_a), axis_of(p, axis_b));
+    if a < self.a0 || a > self.a1 || b < self.b0 || b > self.b1 {
+      return None;
+    }
+    let rec = HitRecord::new(r, t, self.point(0.0, 0.0, 1.0), &*self.material).with_uv(
+      (a - self.a0) / (self.a1 - self.a0),
+      (b - self.b0) / (self.b1 - self.b0),
+      self.point(self.a1 - self.a0, 0.0, 0.0),
+      self.point(0.0, self.b1 - self.b0, 0.0),
+    );
+    Some(HitRecord {
+      p: self.point(a, b, self.k),
+      ..rec
+    })
+  }
+
+  fn bounding_box(&self) -> Option<Aabb> {
+    // padded, as a box without thickness would never be hit
+    Some(Aabb::new(
+      self.point(self.a0, self.b0, self.k - 1e-4),
+      self.point(self.a1, self.b1, self.k + 1e-4),
+    ))
+  }
+
+  fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+    match self.hit(&Ray::new_at_time(origin, direction, time), 0.001, f32::MAX) {
+      Some(rec) => {
+        let distance_squared = rec.t * rec.t * direction.squared_length();
+        let cosine = (axis_of(direction, self.axes.2) / direction.length()).abs();
+        distance_squared / (cosine * self.area())
+      }
+      None => 0.0,
+    }
+  }
+
+  /// Towards a point picked uniformly on the rectangle.
+  fn random(&self, origin: Vec3, _time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+    let (u1, u2) = sampler.next_2d();
+    let a = self.a0 + u1 * (self.a1 - self.a0);
+    let b = self.b0 + u2 * (self.b1 - self.b0);
+    self.point(a, b, self.k) - origin
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::material::Lambertian;
+  use super::super::sampler::IndependentSampler;
+  use super::*;
+
+  fn gray() -> Box<dyn Material> {
+    Box::new(Lambertian::new(Vec3(0.5, 0.5, 0.5)))
+  }
+
+  #[test]
+  fn rects_hit_on_their_planes() {
+    let rects = [
+      (
+        AxisRect::xy(0.0, 2.0, 0.0, 1.0, -3.0, gray()),
+        Vec3(0.0, 0.0, 1.0),
+      ),
+      (
+        AxisRect::xz(0.0, 2.0, 0.0, 1.0, -3.0, gray()),
+        Vec3(0.0, 1.0, 0.0),
+      ),
+      (
+        AxisRect::yz(0.0, 2.0, 0.0, 1.0, -3.0, gray()),
+        Vec3(1.0, 0.0, 0.0),
+      ),
+    ];
+    for (rect, normal) in &rects {
+      // aimed at the point a quarter of the way along a and half way along b
+      let target = rect.point(0.5, 0.5, -3.0);
+      let r = Ray::new(target + 3.0 * *normal, -*normal);
+      let rec = rect.hit(&r, 0.001, f32::MAX).unwrap();
+      assert_eq!(3.0, rec.t);
+      assert_eq!(target, rec.p);
+      assert_eq!(*normal, rec.normal);
+      assert_eq!((0.25, 0.5), (rec.u, rec.v));
+      let miss = Ray::new(rect.point(2.5, 0.5, 0.0), -*normal);
+      assert!(rect.hit(&miss, 0.001, f32::MAX).is_none());
+      let bbox = rect.bounding_box().unwrap();
+      assert!(bbox.hit(&r, 0.001, f32::MAX));
+    }
+  }
+
+  #[test]
+  fn rect_sampling() {
+    let light = AxisRect::xz(-1.0, 1.0, -1.0, 1.0, 2.0, gray());
+    let origin = Vec3(0.0, 0.0, 0.0);
+    let mut sampler = IndependentSampler::new(1);
+    for _ in 0..100 {
+      let direction = light.random(origin, 0.0, &mut sampler);
+      assert!(light.pdf_value(origin, direction, 0.0) > 0.0);
+    }
+    // straight up the density is distance² / area
+    assert!((light.pdf_value(origin, Vec3(0.0, 1.0, 0.0), 0.0) - 1.0).abs() < 1e-6);
+    assert_eq!(0.0, light.pdf_value(origin, Vec3(1.0, 0.0, 0.0), 0.0));
+  }
+}
@@ -0,0 +1,473 @@
+use super::camera::Camera;
+use super::environment::{Environment, Gradient};
+use super::film::Film;
+use super::filter::Filter;
+use super::hittable::Hittable;
+use super::image::Image;
+use super::integrator::{radiance, Integrator};
+use super::progress::{PixelStats, Progress};
+use super::sampler::{Sampler, SamplerKind};
+use super::tonemap::ToneMapping;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Width and height in pixels of the square tiles handed out to render threads.
+pub const TILE_SIZE: usize = 16;
+
+#[derive(Clone, Debug)]
+pub struct RenderSettings {
+  pub width: usize,
+  pub height: usize,
+  /// Most samples any pixel gets.
+  pub samples_per_pixel: usize,
+  /// Samples a pixel takes per progressive pass.
+  pub pass_samples: usize,
+  /// Pixels stop sampling once their estimated error (see
+  /// `PixelStats::error`) is below this, 0 disables adaptive sampling.
+  pub noise_threshold: f32,
+  /// No pass is started after this much time, the first one excepted.
+  pub time_limit: Option<Duration>,
+  pub max_depth: i32,
+  /// Number of render threads, 0 uses every available core.
+  pub threads: usize,
+  pub seed: u64,
+  /// Radiance of rays that leave the scene without hitting anything.
+  pub background: Arc<dyn Environment>,
+  pub integrator: Integrator,
+  pub sampler: SamplerKind,
+  pub filter: Filter,
+  /// Applied when the image is saved in an 8 or 16-bit format.
+  pub tone_mapping: ToneMapping,
+}
+
+impl Default for RenderSettings {
+  fn default() -> Self {
+    RenderSettings {
+      width: 1200,
+      height: 800,
+      samples_per_pixel: 100,
+      pass_samples: 16,
+      noise_threshold: 0.0,
+      time_limit: None,
+      max_depth: 50,
+      threads: 0,
+      seed: 0,
+      background: Arc::new(Gradient::default()),
+      integrator: Integrator::default(),
+      sampler: SamplerKind::default(),
+      filter: Filter::default(),
+      tone_mapping: ToneMapping::default(),
+    }
+  }
+}
+
+impl RenderSettings {
+  fn thread_count(&self) -> usize {
+    if self.threads > 0 {
+      self.threads
+    } else {
+      thread::available_parallelism().map_or(1, |n| n.get())
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Tile {
+  x0: usize,
+  y0: usize,
+  x1: usize,
+  y1: usize,
+}
+
+fn tiles(width: usize, height: usize) -> Vec<Tile> {
+  let mut tiles = Vec::new();
+  for y0 in (0..height).step_by(TILE_SIZE) {
+    for x0 in (0..width).step_by(TILE_SIZE) {
+      tiles.push(Tile {
+        x0,
+        y0,
+        x1: (x0 + TILE_SIZE).min(width),
+        y1: (y0 + TILE_SIZE).min(height),
+      });
+    }
+  }
+  tiles
+}
+
+/// Renders `scene` seen through `camera` into a linear color image.
+///
+/// `lights` are the objects sampled directly by the MIS integrators, usually
+/// the emissive ones of `scene`. With an empty `Vec` lights are only found by
+/// following scattered rays.
+pub fn render(
+  scene: &dyn Hittable,
+  lights: &dyn Hittable,
+  camera: &Camera,
+  settings: &RenderSettings,
+) -> Image {
+  let mut progress = Progress::new(settings.width, settings.height);
+  render_progressive(scene, lights, camera, settings, &mut progress, |_| {});
+  progress.image()
+}
+
+/// Adds passes of `settings.pass_samples` samples per pixel to `progress`
+/// until every pixel is done (see `PixelStats::is_done`) or the time limit is
+/// reached, calling `after_pass` after each one.
+///
+/// Tiles are spread over `settings.threads` threads. Every sample takes its
+/// random numbers from a `settings.sampler` started from `settings.seed`, its pixel and
+/// its index in the pixel, so without a time limit the image is identical
+/// whatever the thread count, and the same up to rounding whatever the pass size.
+pub fn render_progressive<F: FnMut(&Progress)>(
+  scene: &dyn Hittable,
+  lights: &dyn Hittable,
+  camera: &Camera,
+  settings: &RenderSettings,
+  progress: &mut Progress,
+  mut after_pass: F,
+) {
+  assert!(settings.pass_samples > 0, "passes without samples");
+  assert!(
+    progress.width == settings.width && progress.height == settings.height,
+    "progress is for another image size"
+  );
+  let start = Instant::now();
+  let tiles = tiles(settings.width, settings.height);
+  let mut passes_here = 0;
+  while progress.active_pixels(settings.samples_per_pixel, settings.noise_threshold) > 0 {
+    if passes_here > 0
+      && settings
+        .time_limit
+        .is_some_and(|limit| start.elapsed() >= limit)
+    {
+      break;
+    }
+    let mut rendered = render_pass(&tiles, scene, lights, camera, settings, progress);
+    // the films of neighbouring tiles overlap, so they are added up in the
+    // same order whichever thread rendered them
+    rendered.sort_by_key(|(index, _, _)| *index);
+    for (index, pixels, film) in rendered {
+      let tile = &tiles[index];
+      let mut pixels = pixels.into_iter();
+      for y in tile.y0..tile.y1 {
+        for x in tile.x0..tile.x1 {
+          progress.pixel_mut(x, y).merge(&pixels.next().unwrap());
+        }
+      }
+      progress.film_mut().merge(&film);
+    }
+    progress.passes += 1;
+    passes_here += 1;
+    after_pass(progress);
+  }
+}
+
+/// One pass over every tile, giving the statistics of the new samples of
+/// each tile's pixels and the film they were splatted into.
+fn render_pass(
+  tiles: &[Tile],
+  scene: &dyn Hittable,
+  lights: &dyn Hittable,
+  camera: &Camera,
+  settings: &RenderSettings,
+  progress: &Progress,
+) -> Vec<(usize, Vec<PixelStats>, Film)> {
+  let next_tile = AtomicUsize::new(0);
+  thread::scope(|s| {
+    let workers: Vec<_> = (0..settings.thread_count().min(tiles.len().max(1)))
+      .map(|_| {
+        s.spawn(|| {
+          let mut done = Vec::new();
+          let mut sampler = settings
+            .sampler
+            .sampler(settings.seed, settings.samples_per_pixel);
+          loop {
+            let index = next_tile.fetch_add(1, Ordering::Relaxed);
+            if index >= tiles.len() {
+              return done;
+            }
+            let (pixels, film) = render_tile(
+              &tiles[index],
+              scene,
+              lights,
+              camera,
+              settings,
+              progress,
+              &mut *sampler,
+            );
+            done.push((index, pixels, film));
+          }
+        })
+      })
+      .collect();
+    workers
+      .into_iter()
+      .flat_map(|w| w.join().expect("render thread panicked"))
+      .collect()
+  })
+}
+
+fn render_tile(
+  tile: &Tile,
+  scene: &dyn Hittable,
+  lights: &dyn Hittable,
+  camera: &Camera,
+  settings: &RenderSettings,
+  progress: &Progress,
+  sampler: &mut dyn Sampler,
+) -> (Vec<PixelStats>, Film) {
+  let nx = settings.width;
+  let ny = settings.height;
+  let mut pixels = Vec::with_capacity((tile.x1 - tile.x0) * (tile.y1 - tile.y0));
+  // samples reach pixels up to the filter's radius away
+  let margin = settings.filter.radius.ceil() as usize;
+  let (x0, y0) = (
+    tile.x0.saturating_sub(margin),
+    tile.y0.saturating_sub(margin),
+  );
+  let mut film = Film::window(
+    x0,
+    y0,
+    (tile.x1 + margin).min(nx) - x0,
+    (tile.y1 + margin).min(ny) - y0,
+  );
+  for y in tile.y0..tile.y1 {
+    for i in tile.x0..tile.x1 {
+      let mut stats = PixelStats::default();
+      let so_far = progress.pixel(i, y);
+      if !so_far.is_done(settings.samples_per_pixel, settings.noise_threshold) {
+        let remaining = settings.samples_per_pixel - so_far.samples as usize;
+        for s in 0..settings.pass_samples.min(remaining) {
+          sampler.start_sample(i, y, so_far.samples + s as u32);
+          let (du, dv) = sampler.next_2d();
+          let (fx, fy) = (i as f32 + du, y as f32 + dv);
+          // image rows go top to bottom, the camera's v axis bottom to top
+          let r = camera.get_ray(fx / nx as f32, 1.0 - fy / ny as f32, sampler);
+          let color = radiance(&r, scene, lights, settings, sampler);
+          stats.add(color);
+          film.add_sample(&settings.filter, fx, fy, color);
+        }
+      }
+      pixels.push(stats);
+    }
+  }
+  (pixels, film)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::environment::Uniform;
+  use super::super::filter::FilterKind;
+  use super::super::material::{Dielectric, DiffuseLight, Lambertian, Metal};
+  use super::super::sphere::Sphere;
+  use super::super::vec3::Vec3;
+  use super::*;
+
+  fn test_scene() -> (Vec<Box<dyn Hittable>>, Camera) {
+    let world: Vec<Box<dyn Hittable>> = vec![
+      Box::new(Sphere::new(
+        Vec3(0.0, -100.5, -1.0),
+        100.0,
+        Box::new(Lambertian::new(Vec3(0.8, 0.8, 0.0))),
+      )),
+      Box::new(Sphere::new(
+        Vec3(0.0, 0.0, -1.0),
+        0.5,
+        Box::new(Lambertian::new(Vec3(0.1, 0.2, 0.5))),
+      )),
+      Box::new(Sphere::new(
+        Vec3(1.0, 0.0, -1.0),
+        0.5,
+        Box::new(Metal::new(Vec3(0.8, 0.6, 0.2), 0.3)),
+      )),
+      Box::new(Sphere::new(
+        Vec3(-1.0, 0.0, -1.0),
+        0.5,
+        Box::new(Dielectric::new(1.5)),
+      )),
+    ];
+    let camera = Camera::new(
+      Vec3(0.0, 0.0, 1.0),
+      Vec3(0.0, 0.0, -1.0),
+      Vec3(0.0, 1.0, 0.0),
+      60.0,
+      1.5,
+      0.1,
+      2.0,
+    );
+    (world, camera)
+  }
+
+  fn no_lights() -> Vec<Box<dyn Hittable>> {
+    Vec::new()
+  }
+
+  #[test]
+  fn tiles_cover_image() {
+    let tiles = tiles(40, 20);
+    assert_eq!(6, tiles.len());
+    assert_eq!(
+      Tile {
+        x0: 32,
+        y0: 16,
+        x1: 40,
+        y1: 20
+      },
+      tiles[5]
+    );
+  }
+
+  #[test]
+  fn output_independent_of_thread_count() {
+    let (world, camera) = test_scene();
+    let mut settings = RenderSettings {
+      width: 45,
+      height: 30,
+      samples_per_pixel: 4,
+      threads: 1,
+      seed: 3,
+      ..RenderSettings::default()
+    };
+    let single = render(&world, &no_lights(), &camera, &settings);
+    settings.threads = 4;
+    let multi = render(&world, &no_lights(), &camera, &settings);
+    assert_eq!(single, multi);
+    settings.seed = 4;
+    assert_ne!(single, render(&world, &no_lights(), &camera, &settings));
+  }
+
+  #[test]
+  fn film_reconstruction() {
+    let (world, camera) = test_scene();
+    let settings = RenderSettings {
+      width: 40,
+      height: 20,
+      samples_per_pixel: 4,
+      threads: 1,
+      ..RenderSettings::default()
+    };
+    // the box filter averages each pixel's own samples
+    let mut progress = Progress::new(40, 20);
+    render_progressive(
+      &world,
+      &no_lights(),
+      &camera,
+      &settings,
+      &mut progress,
+      |_| {},
+    );
+    let image = progress.image();
+    for y in 0..20 {
+      for x in 0..40 {
+        assert!((image.get(x, y) - progress.pixel(x, y).mean()).length() < 1e-5);
+      }
+    }
+    // wider filters splat across tiles, in an order that does not depend on threads
+    let mut settings = RenderSettings {
+      filter: Filter::new(FilterKind::Lanczos),
+      ..settings.clone()
+    };
+    let single = render(&world, &no_lights(), &camera, &settings);
+    settings.threads = 3;
+    assert_eq!(single, render(&world, &no_lights(), &camera, &settings));
+    assert_ne!(image, single);
+  }
+
+  #[test]
+  fn adaptive_sampling_and_time_limit() {
+    let (world, camera) = test_scene();
+    let settings = RenderSettings {
+      width: 16,
+      height: 8,
+      samples_per_pixel: 40,
+      pass_samples: 16,
+      threads: 1,
+      ..RenderSettings::default()
+    };
+    let mut passes = Vec::new();
+    let mut progress = Progress::new(16, 8);
+    render_progressive(
+      &world,
+      &no_lights(),
+      &camera,
+      &settings,
+      &mut progress,
+      |p| passes.push(p.total_samples()),
+    );
+    // 16 + 16 + 8 samples per pixel
+    assert_eq!(vec![16 * 128, 32 * 128, 40 * 128], passes);
+
+    // the sky is smooth, the spheres' edges and the glass are not
+    let adaptive = RenderSettings {
+      noise_threshold: 0.01,
+      ..settings.clone()
+    };
+    let mut progress = Progress::new(16, 8);
+    render_progressive(
+      &world,
+      &no_lights(),
+      &camera,
+      &adaptive,
+      &mut progress,
+      |_| {},
+    );
+    assert!(progress.total_samples() < 40 * 128);
+    assert!(progress.total_samples() > 16 * 128);
+    assert_eq!(0, progress.active_pixels(40, 0.01));
+
+    let timed = RenderSettings {
+      time_limit: Some(Duration::from_secs(0)),
+      ..settings.clone()
+    };
+    let mut progress = Progress::new(16, 8);
+    render_progressive(&world, &no_lights(), &camera, &timed, &mut progress, |_| {});
+    assert_eq!(1, progress.passes);
+    // and resuming continues where it stopped
+    render_progressive(
+      &world,
+      &no_lights(),
+      &camera,
+      &settings,
+      &mut progress,
+      |_| {},
+    );
+    assert_eq!(40 * 128, progress.total_samples());
+  }
+
+  #[test]
+  fn solid_background() {
+    let world: Vec<Box<dyn Hittable>> = Vec::new();
+    let (_, camera) = test_scene();
+    let settings = RenderSettings {
+      width: 4,
+      height: 3,
+      samples_per_pixel: 2,
+      background: Arc::new(Uniform(Vec3(0.25, 0.5, 1.0))),
+      ..RenderSettings::default()
+    };
+    let image = render(&world, &no_lights(), &camera, &settings);
+    assert!(image.pixels().iter().all(|p| *p == Vec3(0.25, 0.5, 1.0)));
+  }
+
+  #[test]
+  fn lights_in_the_dark() {
+    // camera inside a glowing sphere, which is all it can see
+    let world: Vec<Box<dyn Hittable>> = vec![Box::new(Sphere::new(
+      Vec3(0.0, 0.0, 0.0),
+      10.0,
+      Box::new(DiffuseLight::new(Vec3(4.0, 2.0, 1.0))),
+    ))];
+    let (_, camera) = test_scene();
+    let settings = RenderSettings {
+      width: 4,
+      height: 3,
+      samples_per_pixel: 2,
+      background: Arc::new(Uniform(Vec3(0.0, 0.0, 0.0))),
+      ..RenderSettings::default()
+    };
+    let image = render(&world, &no_lights(), &camera, &settings);
+    assert!(image.pixels().iter().all(|p| *p == Vec3(4.0, 2.0, 1.0)));
+  }
+}
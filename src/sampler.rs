@@ -0,0 +1,610 @@
+extern crate serde;
+
+use super::random::{mix_seed, Pcg32};
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// Largest `f32` below 1, what low-discrepancy values are clamped to.
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+/// Source of the random numbers of a camera sample. Everything random in a
+/// path (lens and shutter position, scattering, light selection) asks the
+/// sampler for its next dimensions, so that a sample is a function of the
+/// seed, the pixel and the sample's index alone.
+pub trait Sampler {
+  /// Starts sample `index` of pixel (`x`, `y`), the dimensions asked for
+  /// afterwards being those of that sample.
+  fn start_sample(&mut self, x: usize, y: usize, index: u32);
+
+  /// Next dimension of the current sample, uniform in [0, 1).
+  fn next_1d(&mut self) -> f32;
+
+  /// Next two dimensions, for choices made jointly such as a point on a lens.
+  fn next_2d(&mut self) -> (f32, f32) {
+    let a = self.next_1d();
+    (a, self.next_1d())
+  }
+}
+
+/// Independent uniform random numbers, from a generator seeded per sample.
+#[derive(Clone, Debug)]
+pub struct IndependentSampler {
+  seed: u64,
+  rng: Pcg32,
+}
+
+impl IndependentSampler {
+  pub fn new(seed: u64) -> Self {
+    IndependentSampler {
+      seed,
+      rng: Pcg32::new(seed),
+    }
+  }
+}
+
+impl Sampler for IndependentSampler {
+  fn start_sample(&mut self, x: usize, y: usize, index: u32) {
+    let pixel = mix_seed(mix_seed(self.seed, x as u64), y as u64);
+    self.rng = Pcg32::new(mix_seed(pixel, u64::from(index)));
+  }
+
+  fn next_1d(&mut self) -> f32 {
+    self.rng.next_f32()
+  }
+}
+
+/// Which `Sampler` a render takes its random numbers from.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplerKind {
+  #[default]
+  Independent,
+  /// Jittered strata, as many as there are samples per pixel.
+  Stratified,
+  /// Owen-scrambled Halton sequence.
+  Halton,
+  /// Owen-scrambled Sobol sequence.
+  Sobol,
+  /// A Sobol sequence shared by all pixels and shifted by a blue noise mask,
+  /// so that the error of neighbouring pixels differs.
+  BlueNoise,
+}
+
+impl SamplerKind {
+  /// A sampler of this kind for renders of `samples_per_pixel` samples.
+  pub fn sampler(self, seed: u64, samples_per_pixel: usize) -> Box<dyn Sampler> {
+    match self {
+      SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+      SamplerKind::Stratified => Box::new(StratifiedSampler::new(seed, samples_per_pixel)),
+      SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+      SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+      SamplerKind::BlueNoise => Box::new(BlueNoiseSampler::new(seed)),
+    }
+  }
+}
+
+impl FromStr for SamplerKind {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "independent" => Ok(SamplerKind::Independent),
+      "stratified" => Ok(SamplerKind::Stratified),
+      "halton" => Ok(SamplerKind::Halton),
+      "sobol" => Ok(SamplerKind::Sobol),
+      "blue_noise" => Ok(SamplerKind::BlueNoise),
+      _ => Err(format!(
+        "unknown sampler `{}`, expected independent, stratified, halton, sobol or blue_noise",
+        s
+      )),
+    }
+  }
+}
+
+impl fmt::Display for SamplerKind {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      SamplerKind::Independent => "independent",
+      SamplerKind::Stratified => "stratified",
+      SamplerKind::Halton => "halton",
+      SamplerKind::Sobol => "sobol",
+      SamplerKind::BlueNoise => "blue_noise",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+/// Hash of a pixel, or of a pixel and a dimension, for scrambling.
+fn hash(seed: u64, index: u64) -> u32 {
+  (mix_seed(seed, index) >> 32) as u32
+}
+
+fn to_f32(bits: u32) -> f32 {
+  (bits >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+}
+
+/// Element `i` of a random permutation of 0..`length` chosen by `seed`
+/// (Kensler, "Correlated Multi-Jittered Sampling").
+fn permutation_element(mut i: u32, length: u32, seed: u32) -> u32 {
+  let mut w = length - 1;
+  w |= w >> 1;
+  w |= w >> 2;
+  w |= w >> 4;
+  w |= w >> 8;
+  w |= w >> 16;
+  loop {
+    i ^= seed;
+    i = i.wrapping_mul(0xe170_893d);
+    i ^= seed >> 16;
+    i ^= (i & w) >> 4;
+    i ^= seed >> 8;
+    i = i.wrapping_mul(0x0929_eb3f);
+    i ^= seed >> 23;
+    i ^= (i & w) >> 1;
+    i = i.wrapping_mul(1 | seed >> 27);
+    i = i.wrapping_mul(0x6935_fa69);
+    i ^= (i & w) >> 11;
+    i = i.wrapping_mul(0x74dc_b303);
+    i ^= (i & w) >> 2;
+    i = i.wrapping_mul(0x9e50_1cc3);
+    i ^= (i & w) >> 2;
+    i = i.wrapping_mul(0xc860_a3df);
+    i &= w;
+    i ^= i >> 5;
+    if i < length {
+      return (i.wrapping_add(seed)) % length;
+    }
+  }
+}
+
+/// Jittered stratified samples: the samples of a pixel fall in different
+/// strata of every dimension, or of every pair of dimensions for `next_2d`,
+/// visited in an order shuffled per pixel and dimension.
+#[derive(Clone, Debug)]
+pub struct StratifiedSampler {
+  seed: u64,
+  strata: u32,
+  pixel: u64,
+  index: u32,
+  dimension: u64,
+  rng: Pcg32,
+}
+
+impl StratifiedSampler {
+  pub fn new(seed: u64, samples_per_pixel: usize) -> Self {
+    StratifiedSampler {
+      seed,
+      strata: samples_per_pixel.clamp(1, u32::MAX as usize) as u32,
+      pixel: seed,
+      index: 0,
+      dimension: 0,
+      rng: Pcg32::new(seed),
+    }
+  }
+
+  fn stratum(&mut self, strata: u32) -> u32 {
+    let seed = hash(self.pixel, self.dimension);
+    permutation_element(self.index % strata, strata, seed)
+  }
+}
+
+impl Sampler for StratifiedSampler {
+  fn start_sample(&mut self, x: usize, y: usize, index: u32) {
+    self.pixel = mix_seed(mix_seed(self.seed, x as u64), y as u64);
+    self.index = index;
+    self.dimension = 0;
+    self.rng = Pcg32::new(mix_seed(self.pixel, u64::from(index)));
+  }
+
+  fn next_1d(&mut self) -> f32 {
+    let stratum = self.stratum(self.strata);
+    self.dimension += 1;
+    ((stratum as f32 + self.rng.next_f32()) / self.strata as f32).min(ONE_MINUS_EPSILON)
+  }
+
+  fn next_2d(&mut self) -> (f32, f32) {
+    // the largest grid with no more cells than samples
+    let columns = (self.strata as f32).sqrt() as u32;
+    let rows = self.strata / columns;
+    let cell = self.stratum(columns * rows);
+    self.dimension += 2;
+    let x = (cell % columns) as f32 + self.rng.next_f32();
+    let y = (cell / columns) as f32 + self.rng.next_f32();
+    (
+      (x / columns as f32).min(ONE_MINUS_EPSILON),
+      (y / rows as f32).min(ONE_MINUS_EPSILON),
+    )
+  }
+}
+
+const PRIMES: [u32; 32] = [
+  2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+  101, 103, 107, 109, 113, 127, 131,
+];
+
+/// Radical inverse of `a` in `base` with its digits randomly permuted, each
+/// permutation depending on the digits before it (Owen scrambling).
+fn owen_scrambled_radical_inverse(base: u32, mut a: u64, seed: u64) -> f32 {
+  let base = u64::from(base);
+  let inverse_base = 1.0 / base as f64;
+  let mut inverse_base_power = 1.0;
+  let mut reversed = 0u64;
+  // as many digits as an f32 resolves
+  while inverse_base_power * base as f64 > 1.0 / (1u32 << 24) as f64 {
+    let digit = a % base;
+    a /= base;
+    let seed = hash(seed, reversed);
+    let digit = permutation_element(digit as u32, base as u32, seed);
+    reversed = reversed * base + u64::from(digit);
+    inverse_base_power *= inverse_base;
+  }
+  ((reversed as f64 * inverse_base_power) as f32).min(ONE_MINUS_EPSILON)
+}
+
+/// The Halton sequence, dimension `d` being the radical inverse in the `d`th
+/// prime, Owen-scrambled per pixel. Dimensions past the 32nd are independent.
+#[derive(Clone, Debug)]
+pub struct HaltonSampler {
+  seed: u64,
+  pixel: u64,
+  index: u32,
+  dimension: usize,
+  rng: Pcg32,
+}
+
+impl HaltonSampler {
+  pub fn new(seed: u64) -> Self {
+    HaltonSampler {
+      seed,
+      pixel: seed,
+      index: 0,
+      dimension: 0,
+      rng: Pcg32::new(seed),
+    }
+  }
+}
+
+impl Sampler for HaltonSampler {
+  fn start_sample(&mut self, x: usize, y: usize, index: u32) {
+    self.pixel = mix_seed(mix_seed(self.seed, x as u64), y as u64);
+    self.index = index;
+    self.dimension = 0;
+    self.rng = Pcg32::new(mix_seed(self.pixel, u64::from(index)));
+  }
+
+  fn next_1d(&mut self) -> f32 {
+    let dimension = self.dimension;
+    self.dimension += 1;
+    match PRIMES.get(dimension) {
+      Some(&base) => owen_scrambled_radical_inverse(
+        base,
+        u64::from(self.index),
+        mix_seed(self.pixel, dimension as u64),
+      ),
+      None => self.rng.next_f32(),
+    }
+  }
+}
+
+/// Owen scrambling of the bits of `x` (Burley, "Practical Hash-based Owen
+/// Scrambling"): a Laine-Karras permutation applied to the reversed bits.
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+  let mut x = x.reverse_bits();
+  x = x.wrapping_add(seed);
+  x ^= x.wrapping_mul(0x6c50_b47c);
+  x ^= x.wrapping_mul(0xb82f_1e52);
+  x ^= x.wrapping_mul(0xc7af_e638);
+  x ^= x.wrapping_mul(0x8d22_f6e6);
+  x.reverse_bits()
+}
+
+/// Second dimension of the Sobol sequence, the first being the bit reversal
+/// of the index.
+fn sobol_second(mut index: u32) -> u32 {
+  let mut direction = 1u32 << 31;
+  let mut result = 0;
+  while index != 0 {
+    if index & 1 != 0 {
+      result ^= direction;
+    }
+    index >>= 1;
+    direction ^= direction >> 1;
+  }
+  result
+}
+
+/// Two dimensions of an Owen-scrambled Sobol sequence. Every pair of
+/// dimensions shuffles the sample index with its own `seed`, so that pairs
+/// are uncorrelated while each stays a (0, 2)-sequence.
+fn sobol_2d(index: u32, seed: u64) -> (u32, u32) {
+  let index = nested_uniform_scramble(index, hash(seed, 0));
+  (
+    nested_uniform_scramble(index.reverse_bits(), hash(seed, 1)),
+    nested_uniform_scramble(sobol_second(index), hash(seed, 2)),
+  )
+}
+
+/// Owen-scrambled Sobol points, scrambled per pixel and padded pairwise:
+/// each `next_2d` (or `next_1d`) takes a fresh two dimensional sequence.
+#[derive(Clone, Debug)]
+pub struct SobolSampler {
+  seed: u64,
+  pixel: u64,
+  index: u32,
+  dimension: u64,
+}
+
+impl SobolSampler {
+  pub fn new(seed: u64) -> Self {
+    SobolSampler {
+      seed,
+      pixel: seed,
+      index: 0,
+      dimension: 0,
+    }
+  }
+}
+
+impl Sampler for SobolSampler {
+  fn start_sample(&mut self, x: usize, y: usize, index: u32) {
+    self.pixel = mix_seed(mix_seed(self.seed, x as u64), y as u64);
+    self.index = index;
+    self.dimension = 0;
+  }
+
+  fn next_1d(&mut self) -> f32 {
+    self.next_2d().0
+  }
+
+  fn next_2d(&mut self) -> (f32, f32) {
+    let (x, y) = sobol_2d(self.index, mix_seed(self.pixel, self.dimension));
+    self.dimension += 1;
+    (to_f32(x), to_f32(y))
+  }
+}
+
+/// Side of the tiled blue noise mask.
+const BLUE_NOISE_SIZE: usize = 64;
+
+/// A `size`×`size` tileable blue noise mask of values in (0, 1), made by
+/// void filling: each pixel in turn is the one furthest from those already
+/// placed, as measured by a Gaussian energy, and gets the next value.
+pub fn blue_noise_mask(size: usize) -> Vec<f32> {
+  const SIGMA: f32 = 1.5;
+  const RADIUS: isize = 6;
+  let count = size * size;
+  let mut rng = Pcg32::new(0);
+  // tiny random energies break the ties of the empty mask
+  let mut energy: Vec<f32> = (0..count).map(|_| 1e-4 * rng.next_f32()).collect();
+  let mut mask = vec![-1.0f32; count];
+  for rank in 0..count {
+    let (void, _) = energy
+      .iter()
+      .enumerate()
+      .filter(|&(i, _)| mask[i] < 0.0)
+      .fold(
+        (0, f32::INFINITY),
+        |best, (i, &e)| {
+          if e < best.1 {
+            (i, e)
+          } else {
+            best
+          }
+        },
+      );
+    mask[void] = (rank as f32 + 0.5) / count as f32;
+    let (x, y) = ((void % size) as isize, (void / size) as isize);
+    for dy in -RADIUS..=RADIUS {
+      for dx in -RADIUS..=RADIUS {
+        let qx = (x + dx).rem_euclid(size as isize) as usize;
+        let qy = (y + dy).rem_euclid(size as isize) as usize;
+        energy[qy * size + qx] += (-((dx * dx + dy * dy) as f32) / (2.0 * SIGMA * SIGMA)).exp();
+      }
+    }
+  }
+  mask
+}
+
+fn shared_blue_noise_mask() -> &'static [f32] {
+  static MASK: OnceLock<Vec<f32>> = OnceLock::new();
+  MASK.get_or_init(|| blue_noise_mask(BLUE_NOISE_SIZE))
+}
+
+/// The same Owen-scrambled Sobol points for every pixel, each dimension
+/// shifted modulo 1 by a blue noise mask at an offset of its own. At low
+/// sample counts the error then varies from pixel to pixel as blue noise,
+/// which looks finer than white noise.
+#[derive(Clone, Debug)]
+pub struct BlueNoiseSampler {
+  seed: u64,
+  mask: &'static [f32],
+  x: usize,
+  y: usize,
+  index: u32,
+  dimension: u64,
+}
+
+impl BlueNoiseSampler {
+  pub fn new(seed: u64) -> Self {
+    BlueNoiseSampler {
+      seed,
+      mask: shared_blue_noise_mask(),
+      x: 0,
+      y: 0,
+      index: 0,
+      dimension: 0,
+    }
+  }
+
+  fn shift(&self, dimension: u64) -> f32 {
+    let offset = mix_seed(self.seed, dimension);
+    let x = (self.x + offset as usize) % BLUE_NOISE_SIZE;
+    let y = (self.y + (offset >> 32) as usize) % BLUE_NOISE_SIZE;
+    self.mask[y * BLUE_NOISE_SIZE + x]
+  }
+}
+
+impl Sampler for BlueNoiseSampler {
+  fn start_sample(&mut self, x: usize, y: usize, index: u32) {
+    self.x = x;
+    self.y = y;
+    self.index = index;
+    self.dimension = 0;
+  }
+
+  fn next_1d(&mut self) -> f32 {
+    self.next_2d().0
+  }
+
+  fn next_2d(&mut self) -> (f32, f32) {
+    let seed = mix_seed(self.seed, self.dimension);
+    let (x, y) = sobol_2d(self.index, seed);
+    let shift = (
+      self.shift(2 * self.dimension),
+      self.shift(2 * self.dimension + 1),
+    );
+    self.dimension += 1;
+    let wrap = |v: f32| if v >= 1.0 { v - 1.0 } else { v }.min(ONE_MINUS_EPSILON);
+    (wrap(to_f32(x) + shift.0), wrap(to_f32(y) + shift.1))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::f32;
+
+  #[test]
+  fn samples_depend_on_pixel_and_index_only() {
+    let mut a = IndependentSampler::new(5);
+    let mut b = IndependentSampler::new(5);
+    a.start_sample(3, 4, 7);
+    let first: Vec<f32> = (0..6).map(|_| a.next_1d()).collect();
+    // whatever b did before
+    b.start_sample(0, 0, 0);
+    b.next_2d();
+    b.start_sample(3, 4, 7);
+    let second: Vec<f32> = (0..6).map(|_| b.next_1d()).collect();
+    assert_eq!(first, second);
+    b.start_sample(4, 3, 7);
+    assert_ne!(first[0], b.next_1d());
+  }
+
+  const KINDS: [SamplerKind; 5] = [
+    SamplerKind::Independent,
+    SamplerKind::Stratified,
+    SamplerKind::Halton,
+    SamplerKind::Sobol,
+    SamplerKind::BlueNoise,
+  ];
+
+  #[test]
+  fn kinds_parse_their_names() {
+    for kind in &KINDS {
+      assert_eq!(Ok(*kind), kind.to_string().parse());
+    }
+    assert!("random".parse::<SamplerKind>().is_err());
+  }
+
+  #[test]
+  fn every_kind_is_deterministic_and_in_range() {
+    for kind in &KINDS {
+      let mut a = kind.sampler(9, 16);
+      let mut b = kind.sampler(9, 16);
+      for index in 0..16 {
+        a.start_sample(5, 2, index);
+        b.start_sample(5, 2, index);
+        for _ in 0..40 {
+          let (x, y) = a.next_2d();
+          assert_eq!((x, y), b.next_2d(), "{}", kind);
+          let z = a.next_1d();
+          assert_eq!(z, b.next_1d(), "{}", kind);
+          assert!([x, y, z].iter().all(|v| (0.0..1.0).contains(v)), "{}", kind);
+        }
+      }
+    }
+  }
+
+  /// Dimensions `skip`, `skip + 1` of the first 16 samples of a pixel fall
+  /// in different cells of a 4×4 grid.
+  fn fills_grid(kind: SamplerKind, skip: usize) -> bool {
+    let mut sampler = kind.sampler(3, 16);
+    let mut cells = [false; 16];
+    for index in 0..16 {
+      sampler.start_sample(7, 1, index);
+      for _ in 0..skip {
+        sampler.next_2d();
+      }
+      let (x, y) = sampler.next_2d();
+      cells[(y * 4.0) as usize * 4 + (x * 4.0) as usize] = true;
+    }
+    cells.iter().all(|&c| c)
+  }
+
+  #[test]
+  fn low_discrepancy_samples_are_stratified() {
+    // the blue noise shift moves points across cell boundaries
+    for kind in &[SamplerKind::Stratified, SamplerKind::Sobol] {
+      for skip in 0..4 {
+        assert!(fills_grid(*kind, skip), "{} {}", kind, skip);
+      }
+    }
+    // Halton is stratified in powers of its bases instead
+    let mut sampler = HaltonSampler::new(3);
+    let mut strata = [false; 8];
+    for index in 0..8 {
+      sampler.start_sample(7, 1, index);
+      strata[(sampler.next_1d() * 8.0) as usize] = true;
+    }
+    assert!(strata.iter().all(|&s| s));
+  }
+
+  #[test]
+  fn low_discrepancy_samples_converge_faster() {
+    // area of the quarter disc, estimated in many pixels with 64 samples
+    let rmse = |kind: SamplerKind| {
+      let mut sampler = kind.sampler(1, 64);
+      let mut squared_error = 0.0;
+      for pixel in 0..64 {
+        let mut inside = 0;
+        for index in 0..64 {
+          sampler.start_sample(pixel, 0, index);
+          sampler.next_1d();
+          let (x, y) = sampler.next_2d();
+          inside += (x * x + y * y < 1.0) as u32;
+        }
+        let error = inside as f32 / 64.0 - f32::consts::FRAC_PI_4;
+        squared_error += error * error / 64.0;
+      }
+      squared_error.sqrt()
+    };
+    let independent = rmse(SamplerKind::Independent);
+    for kind in &KINDS[1..] {
+      assert!(rmse(*kind) < 0.6 * independent, "{}", kind);
+    }
+  }
+
+  #[test]
+  fn blue_noise_mask_is_a_permutation_without_low_frequencies() {
+    let size = 16;
+    let mask = blue_noise_mask(size);
+    let mut ranks: Vec<usize> = mask
+      .iter()
+      .map(|v| (v * (size * size) as f32) as usize)
+      .collect();
+    ranks.sort_unstable();
+    assert!(ranks.iter().enumerate().all(|(i, &r)| i == r));
+    // neighbours differ more than independent values, whose mean squared
+    // difference is 1/6
+    let mut squared = 0.0;
+    for y in 0..size {
+      for x in 0..size {
+        let d = mask[y * size + x] - mask[y * size + (x + 1) % size];
+        squared += d * d / (size * size) as f32;
+      }
+    }
+    assert!(squared > 0.2, "{}", squared);
+  }
+}
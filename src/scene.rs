@@ -0,0 +1,1753 @@
+extern crate serde;
+extern crate toml;
+
+use super::bump::{BumpMap, Bumped};
+use super::bvh::BvhNode;
+use super::camera::Camera;
+use super::cone::Cone;
+use super::constant_medium::ConstantMedium;
+use super::csg::{Csg, CsgOp};
+use super::cuboid::Cuboid;
+use super::cylinder::Cylinder;
+use super::disk::Disk;
+use super::environment::{EnvironmentMap, Gradient, Uniform};
+use super::filter::{Filter, FilterKind};
+use super::hittable::{Hittable, Tagged};
+use super::integrator::Integrator;
+use super::mat4::Mat4;
+use super::material::{
+  Dielectric, DiffuseLight, HenyeyGreenstein, Isotropic, Lambertian, Material, Metal,
+  RoughConductor, RoughDielectric,
+};
+use super::microfacet::Conductor;
+use super::moving_sphere::MovingSphere;
+use super::obj::load_obj;
+use super::plane::Plane;
+use super::rect::AxisRect;
+use super::render::RenderSettings;
+use super::sampler::SamplerKind;
+use super::sphere::Sphere;
+use super::texture::{
+  CheckerTexture, ImageTexture, NoiseStyle, NoiseTexture, SolidColor, Texture, WrapMode,
+};
+use super::tonemap::ToneMap;
+use super::torus::Torus;
+use super::transformed::Transformed;
+use super::triangle::TriangleMesh;
+use super::vec3::Vec3;
+use serde::{Deserialize, Deserializer};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use toml::Spanned;
+
+/// Everything needed to render a scene file.
+pub struct Scene {
+  pub world: Vec<Box<dyn Hittable>>,
+  /// Copies of the emissive objects of `world`, for light sampling.
+  pub lights: Vec<Box<dyn Hittable>>,
+  pub camera: Camera,
+  pub settings: RenderSettings,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+  Io(PathBuf, io::Error),
+  /// Malformed TOML, or a field with the wrong name or type.
+  Parse(toml::de::Error),
+  /// Well formed file describing something invalid, e.g. a negative radius.
+  Invalid {
+    line: usize,
+    context: String,
+    message: String,
+  },
+}
+
+impl fmt::Display for SceneError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SceneError::Io(path, e) => write!(f, "could not read {}: {}", path.display(), e),
+      SceneError::Parse(e) => write!(f, "{}", e),
+      SceneError::Invalid {
+        line,
+        context,
+        message,
+      } => write!(f, "line {}: {}: {}", line, context, message),
+    }
+  }
+}
+
+impl std::error::Error for SceneError {}
+
+impl<'de> Deserialize<'de> for Vec3 {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let [x, y, z] = <[f32; 3]>::deserialize(deserializer)?;
+    Ok(Vec3(x, y, z))
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+  camera: Spanned<CameraDesc>,
+  #[serde(default)]
+  render: Option<Spanned<RenderOverrides>>,
+  #[serde(default)]
+  background: Option<Spanned<BackgroundDesc>>,
+  #[serde(default)]
+  textures: BTreeMap<String, Spanned<TextureDesc>>,
+  #[serde(default)]
+  materials: BTreeMap<String, Spanned<MaterialDesc>>,
+  #[serde(default)]
+  objects: Vec<Spanned<ObjectDesc>>,
+}
+
+/// Parameters of `Camera::new`, the aspect ratio comes from the image size.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+  look_from: Vec3,
+  look_at: Vec3,
+  #[serde(default = "default_view_up")]
+  view_up: Vec3,
+  vertical_fov: f32,
+  #[serde(default)]
+  aperture: f32,
+  /// Defaults to the distance between `look_from` and `look_at`.
+  focus_dist: Option<f32>,
+  #[serde(default)]
+  shutter_open: f32,
+  #[serde(default)]
+  shutter_close: f32,
+}
+
+fn default_view_up() -> Vec3 {
+  Vec3(0.0, 1.0, 0.0)
+}
+
+/// Render settings to change from their defaults, from the `[render]`
+/// table of a scene file or from the command line.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenderOverrides {
+  pub width: Option<usize>,
+  pub height: Option<usize>,
+  pub samples_per_pixel: Option<usize>,
+  pub pass_samples: Option<usize>,
+  pub noise_threshold: Option<f32>,
+  /// In seconds.
+  pub time_limit: Option<f32>,
+  pub max_depth: Option<i32>,
+  pub threads: Option<usize>,
+  pub seed: Option<u64>,
+  pub integrator: Option<Integrator>,
+  pub sampler: Option<SamplerKind>,
+  pub filter: Option<FilterKind>,
+  /// In pixels, the filter's usual radius by default.
+  pub filter_radius: Option<f32>,
+  pub tone_map: Option<ToneMap>,
+  /// In stops.
+  pub exposure: Option<f32>,
+  pub dither: Option<bool>,
+}
+
+impl RenderOverrides {
+  pub fn apply(&self, settings: &mut RenderSettings) {
+    settings.width = self.width.unwrap_or(settings.width);
+    settings.height = self.height.unwrap_or(settings.height);
+    settings.samples_per_pixel = self.samples_per_pixel.unwrap_or(settings.samples_per_pixel);
+    settings.pass_samples = self.pass_samples.unwrap_or(settings.pass_samples);
+    settings.noise_threshold = self.noise_threshold.unwrap_or(settings.noise_threshold);
+    if let Some(seconds) = self.time_limit {
+      settings.time_limit = Some(Duration::from_secs_f32(seconds));
+    }
+    settings.max_depth = self.max_depth.unwrap_or(settings.max_depth);
+    settings.threads = self.threads.unwrap_or(settings.threads);
+    settings.seed = self.seed.unwrap_or(settings.seed);
+    settings.integrator = self.integrator.unwrap_or(settings.integrator);
+    settings.sampler = self.sampler.unwrap_or(settings.sampler);
+    if let Some(kind) = self.filter {
+      settings.filter = Filter::new(kind);
+    }
+    if let Some(radius) = self.filter_radius {
+      settings.filter.radius = radius;
+    }
+    let tone_mapping = &mut settings.tone_mapping;
+    tone_mapping.tone_map = self.tone_map.unwrap_or(tone_mapping.tone_map);
+    tone_mapping.exposure = self.exposure.unwrap_or(tone_mapping.exposure);
+    tone_mapping.dither = self.dither.unwrap_or(tone_mapping.dither);
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackgroundDesc {
+  Gradient {
+    bottom: Vec3,
+    top: Vec3,
+  },
+  Solid {
+    color: Vec3,
+  },
+  /// Equirectangular map, turned by `rotation` degrees about the vertical axis.
+  Image {
+    path: String,
+    #[serde(default)]
+    rotation: f32,
+    #[serde(default = "default_intensity")]
+    intensity: f32,
+  },
+}
+
+fn default_intensity() -> f32 {
+  1.0
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+  Solid {
+    color: Vec3,
+  },
+  Checker {
+    #[serde(default = "default_scale")]
+    scale: f32,
+    even: Vec3,
+    odd: Vec3,
+  },
+  Noise {
+    #[serde(default = "default_scale")]
+    scale: f32,
+    #[serde(default)]
+    style: NoiseStyle,
+    #[serde(default)]
+    seed: u64,
+  },
+  /// PNG, JPEG or HDR file, relative to the scene file. Set `srgb` to
+  /// false for data such as normal and height maps, which are used as stored.
+  Image {
+    path: String,
+    #[serde(default)]
+    wrap: WrapMode,
+    #[serde(default = "default_srgb")]
+    srgb: bool,
+  },
+}
+
+fn default_scale() -> f32 {
+  1.0
+}
+
+fn default_srgb() -> bool {
+  true
+}
+
+/// Lambertian, metal and phase function materials take either a constant `albedo` or the
+/// name of a `texture`.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+  Lambertian {
+    albedo: Option<Vec3>,
+    texture: Option<String>,
+  },
+  Metal {
+    albedo: Option<Vec3>,
+    texture: Option<String>,
+    fuzz: f32,
+  },
+  Dielectric {
+    ref_idx: f32,
+  },
+  /// GGX metal, either a measured `conductor` or one of complex refractive
+  /// index `eta` + i `k`.
+  RoughConductor {
+    conductor: Option<Conductor>,
+    eta: Option<Vec3>,
+    k: Option<Vec3>,
+    #[serde(default)]
+    roughness: f32,
+  },
+  RoughDielectric {
+    ref_idx: f32,
+    #[serde(default)]
+    roughness: f32,
+  },
+  DiffuseLight {
+    emit: Vec3,
+  },
+  /// Phase functions, for media.
+  Isotropic {
+    albedo: Option<Vec3>,
+    texture: Option<String>,
+  },
+  HenyeyGreenstein {
+    albedo: Option<Vec3>,
+    texture: Option<String>,
+    g: f32,
+  },
+  /// Another `material` with its shading normals perturbed by either a
+  /// tangent space `normal_map` or a `height_map`, the heights being the
+  /// texture's luminance times `scale`.
+  Bumped {
+    material: String,
+    normal_map: Option<String>,
+    height_map: Option<String>,
+    #[serde(default = "default_scale")]
+    scale: f32,
+  },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDesc {
+  Sphere {
+    center: Vec3,
+    radius: f32,
+    material: String,
+    transform: Option<TransformDesc>,
+  },
+  /// Sphere at `center0` at time 0 and `center1` at time 1, carrying on at
+  /// the same speed over the rest of the shutter interval.
+  MovingSphere {
+    center0: Vec3,
+    center1: Vec3,
+    radius: f32,
+    material: String,
+    transform: Option<TransformDesc>,
+  },
+  /// Sphere filled with a homogeneous medium of `density` (scattering events
+  /// per unit length), scattering with the phase function `material`.
+  Medium {
+    center: Vec3,
+    radius: f32,
+    density: f32,
+    material: String,
+    transform: Option<TransformDesc>,
+  },
+  /// Infinite plane through `point`.
+  Plane {
+    point: Vec3,
+    normal: Vec3,
+    material: String,
+    transform: Option<TransformDesc>,
+  },
+  Disk {
+    center: Vec3,
+    normal: Vec3,
+    radius: f32,
+    material: String,
+    transform: Option<TransformDesc>,
+  },
+  /// Axis-aligned rectangle between corners `min` and `max`, which share
+  /// one coordinate, facing that axis' positive direction.
+  Rect {
+    min: Vec3,
+    max: Vec3,
+    material: String,
+    transform: Option<TransformDesc>,
+  },
+  /// Axis-aligned box between corners `min` and `max`.
+  Box {
+    min: Vec3,
+    max: Vec3,
+    material: String,
+    transform: Option<TransformDesc>,
+  },
+  /// Standing on the disk of `radius` around `base`, along +y.
+  Cylinder {
+    base: Vec3,
+    radius: f32,
+    height: f32,
+    material: String,
+    transform: Option<TransformDesc>,
+  },
+  Cone {
+    base: Vec3,
+    radius: f32,
+    height: f32,
+    material: String,
+    transform: Option<TransformDesc>,
+  },
+  /// Ring around `center` in the xz plane.
+  Torus {
+    center: Vec3,
+    major_radius: f32,
+    minor_radius: f32,
+    material: String,
+    transform: Option<TransformDesc>,
+  },
+  /// Combination of the solids `a` and `b`, which are sphere, box,
+  /// cylinder, cone, torus or csg objects themselves.
+  Csg {
+    operation: CsgOp,
+    a: Box<ObjectDesc>,
+    b: Box<ObjectDesc>,
+    transform: Option<TransformDesc>,
+  },
+  /// Wavefront OBJ file, relative to the scene file. `material` replaces the
+  /// materials of its MTL file. Transformed meshes are instances sharing the
+  /// triangles of every other object with the same path, material and
+  /// displacement.
+  Mesh {
+    path: String,
+    material: Option<String>,
+    displacement: Option<DisplacementDesc>,
+    transform: Option<TransformDesc>,
+  },
+}
+
+/// Subdivides a mesh `subdivisions` times, then moves its vertices along
+/// their normals by the luminance of `texture` times `scale`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DisplacementDesc {
+  texture: String,
+  #[serde(default = "default_scale")]
+  scale: f32,
+  #[serde(default)]
+  subdivisions: u32,
+}
+
+/// Most triangles a displaced mesh file may have after subdivision, which
+/// quadruples them every round, so that too many rounds fail cleanly instead
+/// of running out of memory.
+const MAX_DISPLACED_TRIANGLES: usize = 1 << 22;
+
+/// Scaling, then rotation about x, y and z in degrees, then translation.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TransformDesc {
+  #[serde(default = "default_scaling")]
+  scale: Vec3,
+  #[serde(default)]
+  rotate: Vec3,
+  #[serde(default)]
+  translate: Vec3,
+}
+
+fn default_scaling() -> Vec3 {
+  Vec3(1.0, 1.0, 1.0)
+}
+
+impl TransformDesc {
+  fn matrix(&self) -> Mat4 {
+    Mat4::translation(self.translate)
+      * Mat4::rotation(Vec3(0.0, 0.0, 1.0), self.rotate.z())
+      * Mat4::rotation(Vec3(0.0, 1.0, 0.0), self.rotate.y())
+      * Mat4::rotation(Vec3(1.0, 0.0, 0.0), self.rotate.x())
+      * Mat4::scaling(self.scale)
+  }
+}
+
+/// Object built around the one material it is given.
+type Shape<'a> = Box<dyn Fn(Box<dyn Material>) -> Box<dyn Hittable> + 'a>;
+
+/// Builds the material named second, for the object at the context given first.
+type MaterialAt<'a> = dyn Fn(&str, &str) -> Result<Box<dyn Material>, SceneError> + 'a;
+
+/// Error about the field at the given path, with the given message.
+type FieldError<'a> = dyn Fn(String, String) -> SceneError + 'a;
+
+impl ObjectDesc {
+  fn transform(&self) -> &Option<TransformDesc> {
+    match self {
+      ObjectDesc::Sphere { transform, .. }
+      | ObjectDesc::MovingSphere { transform, .. }
+      | ObjectDesc::Medium { transform, .. }
+      | ObjectDesc::Plane { transform, .. }
+      | ObjectDesc::Disk { transform, .. }
+      | ObjectDesc::Rect { transform, .. }
+      | ObjectDesc::Box { transform, .. }
+      | ObjectDesc::Cylinder { transform, .. }
+      | ObjectDesc::Cone { transform, .. }
+      | ObjectDesc::Torus { transform, .. }
+      | ObjectDesc::Csg { transform, .. }
+      | ObjectDesc::Mesh { transform, .. } => transform,
+    }
+  }
+}
+
+/// The validated transform of the object `desc` at `context`, if it has one.
+fn transform_of(
+  desc: &ObjectDesc,
+  context: &str,
+  error: &FieldError,
+) -> Result<Option<Mat4>, SceneError> {
+  match desc.transform() {
+    Some(transform) => {
+      let matrix = transform.matrix();
+      if matrix.inverse().is_none() {
+        return Err(error(
+          format!("{}.transform.scale", context),
+          "must not be 0 on any axis".to_string(),
+        ));
+      }
+      Ok(Some(matrix))
+    }
+    None => Ok(None),
+  }
+}
+
+/// `desc` at `context` as a shape, if it is one of spheres, planes, disks,
+/// rectangles, boxes, cylinders, cones and tori, of which only spheres,
+/// rectangles and disks can be sampled as lights.
+fn shape_of<'a>(
+  desc: &'a ObjectDesc,
+  context: &str,
+  error: &FieldError,
+) -> Result<Option<(Shape<'a>, bool, &'a str)>, SceneError> {
+  let positive = |field: &str, value: f32| {
+    if value > 0.0 {
+      Ok(())
+    } else {
+      Err(error(
+        format!("{}.{}", context, field),
+        format!("{} must be positive", value),
+      ))
+    }
+  };
+  let nonzero = |field: &str, value: Vec3| {
+    if value.squared_length() > 0.0 {
+      Ok(())
+    } else {
+      Err(error(
+        format!("{}.{}", context, field),
+        "must not be 0".to_string(),
+      ))
+    }
+  };
+  // rects and boxes must have a size along their free axes
+  let extent = |axis: &str, min: f32, max: f32| {
+    if max > min {
+      Ok(())
+    } else {
+      Err(error(
+        format!("{}.max.{}", context, axis),
+        format!("{} is not above min.{} {}", max, axis, min),
+      ))
+    }
+  };
+  Ok(match desc {
+    ObjectDesc::Sphere {
+      center,
+      radius,
+      material,
+      ..
+    } => {
+      positive("radius", *radius)?;
+      let shape: Shape = Box::new(move |m| Box::new(Sphere::new(*center, *radius, m)));
+      Some((shape, true, material))
+    }
+    ObjectDesc::Plane {
+      point,
+      normal,
+      material,
+      ..
+    } => {
+      nonzero("normal", *normal)?;
+      let shape: Shape = Box::new(move |m| Box::new(Plane::new(*point, *normal, m)));
+      Some((shape, false, material))
+    }
+    ObjectDesc::Disk {
+      center,
+      normal,
+      radius,
+      material,
+      ..
+    } => {
+      nonzero("normal", *normal)?;
+      positive("radius", *radius)?;
+      let shape: Shape = Box::new(move |m| Box::new(Disk::new(*center, *normal, *radius, m)));
+      Some((shape, true, material))
+    }
+    ObjectDesc::Rect {
+      min, max, material, ..
+    } => {
+      let (min, max) = (*min, *max);
+      let shape: Shape = if min.x() == max.x() {
+        extent("y", min.y(), max.y())?;
+        extent("z", min.z(), max.z())?;
+        Box::new(move |m| Box::new(AxisRect::yz(min.y(), max.y(), min.z(), max.z(), min.x(), m)))
+      } else if min.y() == max.y() {
+        extent("x", min.x(), max.x())?;
+        extent("z", min.z(), max.z())?;
+        Box::new(move |m| Box::new(AxisRect::xz(min.x(), max.x(), min.z(), max.z(), min.y(), m)))
+      } else if min.z() == max.z() {
+        extent("x", min.x(), max.x())?;
+        extent("y", min.y(), max.y())?;
+        Box::new(move |m| Box::new(AxisRect::xy(min.x(), max.x(), min.y(), max.y(), min.z(), m)))
+      } else {
+        return Err(error(
+          format!("{}.max", context),
+          "must share a coordinate with min".to_string(),
+        ));
+      };
+      Some((shape, true, material))
+    }
+    ObjectDesc::Box {
+      min, max, material, ..
+    } => {
+      extent("x", min.x(), max.x())?;
+      extent("y", min.y(), max.y())?;
+      extent("z", min.z(), max.z())?;
+      let shape: Shape = Box::new(move |m| Box::new(Cuboid::new(*min, *max, m)));
+      Some((shape, false, material))
+    }
+    ObjectDesc::Cylinder {
+      base,
+      radius,
+      height,
+      material,
+      ..
+    } => {
+      positive("radius", *radius)?;
+      positive("height", *height)?;
+      let shape: Shape = Box::new(move |m| Box::new(Cylinder::new(*base, *radius, *height, m)));
+      Some((shape, false, material))
+    }
+    ObjectDesc::Cone {
+      base,
+      radius,
+      height,
+      material,
+      ..
+    } => {
+      positive("radius", *radius)?;
+      positive("height", *height)?;
+      let shape: Shape = Box::new(move |m| Box::new(Cone::new(*base, *radius, *height, m)));
+      Some((shape, false, material))
+    }
+    ObjectDesc::Torus {
+      center,
+      major_radius,
+      minor_radius,
+      material,
+      ..
+    } => {
+      positive("major_radius", *major_radius)?;
+      positive("minor_radius", *minor_radius)?;
+      let shape: Shape =
+        Box::new(move |m| Box::new(Torus::new(*center, *major_radius, *minor_radius, m)));
+      Some((shape, false, material))
+    }
+    _ => None,
+  })
+}
+
+/// `desc` at `context` as one of the solids of a CSG object: a sphere, box,
+/// cylinder, cone, torus or another CSG object.
+fn solid(
+  desc: &ObjectDesc,
+  context: &str,
+  error: &FieldError,
+  material_at: &MaterialAt,
+) -> Result<Box<dyn Hittable>, SceneError> {
+  let transform = transform_of(desc, context, error)?;
+  let object = match desc {
+    ObjectDesc::Csg {
+      operation, a, b, ..
+    } => Box::new(Csg::new(
+      *operation,
+      solid(a, &format!("{}.a", context), error, material_at)?,
+      solid(b, &format!("{}.b", context), error, material_at)?,
+    )),
+    ObjectDesc::Sphere { .. }
+    | ObjectDesc::Box { .. }
+    | ObjectDesc::Cylinder { .. }
+    | ObjectDesc::Cone { .. }
+    | ObjectDesc::Torus { .. } => {
+      let (shape, _, material) = shape_of(desc, context, error)?.unwrap();
+      shape(material_at(context, material)?)
+    }
+    _ => {
+      return Err(error(
+        context.to_string(),
+        "only spheres, boxes, cylinders, cones, tori and csg objects can be combined".to_string(),
+      ))
+    }
+  };
+  Ok(placed(object, transform))
+}
+
+/// `object` moved by `transform`, if any.
+fn placed(object: Box<dyn Hittable>, transform: Option<Mat4>) -> Box<dyn Hittable> {
+  match transform {
+    Some(transform) => Box::new(Transformed::<dyn Hittable>::new(
+      Arc::from(object),
+      transform,
+    )),
+    None => object,
+  }
+}
+
+/// Loads a TOML scene file, see `scenes/` for examples. `overrides` take
+/// precedence over the file's `[render]` table.
+pub fn load_scene<P: AsRef<Path>>(
+  path: P,
+  overrides: &RenderOverrides,
+) -> Result<Scene, SceneError> {
+  let source =
+    fs::read_to_string(&path).map_err(|e| SceneError::Io(path.as_ref().to_path_buf(), e))?;
+  let base_dir = path.as_ref().parent().unwrap_or_else(|| Path::new(""));
+  parse_scene(&source, base_dir, overrides)
+}
+
+/// Builds a scene from TOML `source`, resolving file paths in it against `base_dir`.
+pub fn parse_scene(
+  source: &str,
+  base_dir: &Path,
+  overrides: &RenderOverrides,
+) -> Result<Scene, SceneError> {
+  let file: SceneFile = toml::from_str(source).map_err(SceneError::Parse)?;
+  let invalid =
+    |span: std::ops::Range<usize>, context: String, message: String| SceneError::Invalid {
+      line: source[..span.start].matches('\n').count() + 1,
+      context,
+      message,
+    };
+
+  let mut settings = RenderSettings::default();
+  if let Some(render) = &file.render {
+    let desc = render.get_ref();
+    if desc
+      .time_limit
+      .is_some_and(|t| !(t >= 0.0 && t.is_finite()))
+    {
+      return Err(invalid(
+        render.span(),
+        "render.time_limit".to_string(),
+        "must be a number of seconds".to_string(),
+      ));
+    }
+    if desc.noise_threshold.is_some_and(|t| t < 0.0 || t.is_nan()) {
+      return Err(invalid(
+        render.span(),
+        "render.noise_threshold".to_string(),
+        "must not be negative".to_string(),
+      ));
+    }
+    if desc.exposure.is_some_and(|e| !e.is_finite()) {
+      return Err(invalid(
+        render.span(),
+        "render.exposure".to_string(),
+        "must be a finite number of stops".to_string(),
+      ));
+    }
+    if desc
+      .filter_radius
+      .is_some_and(|r| !(r > 0.0 && r.is_finite()))
+    {
+      return Err(invalid(
+        render.span(),
+        "render.filter_radius".to_string(),
+        "must be positive".to_string(),
+      ));
+    }
+    desc.apply(&mut settings);
+    if settings.width == 0
+      || settings.height == 0
+      || settings.samples_per_pixel == 0
+      || settings.pass_samples == 0
+    {
+      return Err(invalid(
+        render.span(),
+        "render".to_string(),
+        "width, height, samples_per_pixel and pass_samples must be positive".to_string(),
+      ));
+    }
+  }
+  overrides.apply(&mut settings);
+  if let Some(background) = &file.background {
+    settings.background = match background.get_ref() {
+      BackgroundDesc::Gradient { bottom, top } => Arc::new(Gradient {
+        bottom: *bottom,
+        top: *top,
+      }),
+      BackgroundDesc::Solid { color } => Arc::new(Uniform(*color)),
+      BackgroundDesc::Image {
+        path,
+        rotation,
+        intensity,
+      } => {
+        let map =
+          EnvironmentMap::load(base_dir.join(path), *rotation, *intensity).map_err(|e| {
+            invalid(
+              background.span(),
+              "background.path".to_string(),
+              format!("could not load {}: {}", path, e),
+            )
+          })?;
+        Arc::new(map)
+      }
+    };
+  }
+
+  let desc = file.camera.get_ref();
+  let focus_dist = desc
+    .focus_dist
+    .unwrap_or_else(|| (desc.look_from - desc.look_at).length());
+  if !(desc.vertical_fov > 0.0 && desc.vertical_fov < 180.0) {
+    return Err(invalid(
+      file.camera.span(),
+      "camera.vertical_fov".to_string(),
+      format!("{} is not between 0 and 180 degrees", desc.vertical_fov),
+    ));
+  }
+  if (desc.look_from - desc.look_at).squared_length() == 0.0 {
+    return Err(invalid(
+      file.camera.span(),
+      "camera".to_string(),
+      "look_from and look_at are the same point".to_string(),
+    ));
+  }
+  if desc.shutter_close < desc.shutter_open {
+    return Err(invalid(
+      file.camera.span(),
+      "camera.shutter_close".to_string(),
+      format!("{} is before shutter_open", desc.shutter_close),
+    ));
+  }
+  let camera = Camera::new(
+    desc.look_from,
+    desc.look_at,
+    desc.view_up,
+    desc.vertical_fov,
+    settings.width as f32 / settings.height as f32,
+    desc.aperture,
+    focus_dist,
+  )
+  .with_shutter(desc.shutter_open, desc.shutter_close);
+
+  let mut textures: BTreeMap<&str, Arc<dyn Texture>> = BTreeMap::new();
+  for (name, texture) in &file.textures {
+    let context = format!("textures.{}", name);
+    let built: Arc<dyn Texture> = match texture.get_ref() {
+      TextureDesc::Solid { color } => Arc::new(SolidColor::new(*color)),
+      TextureDesc::Checker { scale, even, odd } => {
+        if *scale <= 0.0 {
+          return Err(invalid(
+            texture.span(),
+            format!("{}.scale", context),
+            format!("{} must be positive", scale),
+          ));
+        }
+        Arc::new(CheckerTexture::new(
+          *scale,
+          Box::new(SolidColor::new(*even)),
+          Box::new(SolidColor::new(*odd)),
+        ))
+      }
+      TextureDesc::Noise { scale, style, seed } => {
+        Arc::new(NoiseTexture::new(*scale, *style, *seed))
+      }
+      TextureDesc::Image { path, wrap, srgb } => {
+        let load = if *srgb {
+          ImageTexture::load
+        } else {
+          ImageTexture::load_data
+        };
+        let image = load(base_dir.join(path), *wrap).map_err(|e| {
+          invalid(
+            texture.span(),
+            format!("{}.path", context),
+            format!("could not load {}: {}", path, e),
+          )
+        })?;
+        Arc::new(image)
+      }
+    };
+    textures.insert(name, built);
+  }
+
+  for (name, material) in &file.materials {
+    let albedo_message = |albedo: &Option<Vec3>, texture: &Option<String>| match (albedo, texture) {
+      (Some(_), Some(_)) => Some("has both an albedo and a texture".to_string()),
+      (None, None) => Some("needs an albedo or a texture".to_string()),
+      (None, Some(texture)) if !textures.contains_key(texture.as_str()) => {
+        Some(format!("unknown texture `{}`", texture))
+      }
+      _ => None,
+    };
+    let roughness_message = |roughness: f32| {
+      if (0.0..=1.0).contains(&roughness) {
+        None
+      } else {
+        Some(format!("roughness {} is not between 0 and 1", roughness))
+      }
+    };
+    let message = match material.get_ref() {
+      MaterialDesc::Lambertian { albedo, texture }
+      | MaterialDesc::Isotropic { albedo, texture } => albedo_message(albedo, texture),
+      MaterialDesc::HenyeyGreenstein { albedo, texture, g } => albedo_message(albedo, texture)
+        .or_else(|| {
+          if g.abs() < 1.0 {
+            None
+          } else {
+            Some(format!("g {} is not between -1 and 1", g))
+          }
+        }),
+      MaterialDesc::Metal {
+        albedo,
+        texture,
+        fuzz,
+      } => albedo_message(albedo, texture).or_else(|| {
+        if (0.0..=1.0).contains(fuzz) {
+          None
+        } else {
+          Some(format!("fuzz {} is not between 0 and 1", fuzz))
+        }
+      }),
+      MaterialDesc::Dielectric { ref_idx } if *ref_idx <= 0.0 => {
+        Some(format!("ref_idx {} must be positive", ref_idx))
+      }
+      MaterialDesc::RoughConductor {
+        conductor,
+        eta,
+        k,
+        roughness,
+      } => match (conductor, eta, k) {
+        (Some(_), None, None) | (None, Some(_), Some(_)) => roughness_message(*roughness),
+        _ => Some("needs either a conductor or both eta and k".to_string()),
+      },
+      MaterialDesc::RoughDielectric { ref_idx, roughness } => {
+        if *ref_idx <= 0.0 {
+          Some(format!("ref_idx {} must be positive", ref_idx))
+        } else {
+          roughness_message(*roughness)
+        }
+      }
+      MaterialDesc::Bumped {
+        material,
+        normal_map,
+        height_map,
+        ..
+      } => match file.materials.get(material).map(|m| m.get_ref()) {
+        None => Some(format!("unknown material `{}`", material)),
+        Some(MaterialDesc::Bumped { .. }) => Some(format!("`{}` is bumped already", material)),
+        Some(_) => match (normal_map, height_map) {
+          (Some(texture), None) | (None, Some(texture)) => {
+            if textures.contains_key(texture.as_str()) {
+              None
+            } else {
+              Some(format!("unknown texture `{}`", texture))
+            }
+          }
+          _ => Some("needs either a normal_map or a height_map".to_string()),
+        },
+      },
+      _ => None,
+    };
+    if let Some(message) = message {
+      return Err(invalid(
+        material.span(),
+        format!("materials.{}", name),
+        message,
+      ));
+    }
+  }
+
+  let mut world: Vec<Box<dyn Hittable>> = Vec::with_capacity(file.objects.len());
+  let mut lights: Vec<Box<dyn Hittable>> = Vec::new();
+  // shared triangles of instanced meshes, and their emissive ones
+  type Instance = (Arc<dyn Hittable>, Option<Arc<dyn Hittable>>);
+  // path, material and displacement texture, scale bits and subdivisions
+  type InstanceKey<'a> = (&'a str, Option<&'a str>, Option<(&'a str, u32, u32)>);
+  let mut instances: HashMap<InstanceKey, Instance> = HashMap::new();
+  for (index, object) in file.objects.iter().enumerate() {
+    let context = format!("objects[{}]", index);
+    let error = |field: String, message: String| invalid(object.span(), field, message);
+    let transform = transform_of(object.get_ref(), &context, &error)?;
+    let material_at = |context: &str, name: &str| {
+      file
+        .materials
+        .get(name)
+        .map(|m| build_material(m.get_ref(), &file.materials, &textures))
+        .ok_or_else(|| {
+          error(
+            format!("{}.material", context),
+            format!("unknown material `{}`", name),
+          )
+        })
+    };
+    let material_named = |name: &str| material_at(&context, name);
+    let load_meshes = |path: &str,
+                       material: &Option<String>,
+                       displacement: &Option<DisplacementDesc>|
+     -> Result<Vec<TriangleMesh>, SceneError> {
+      let mut meshes = load_obj(base_dir.join(path)).map_err(|e| {
+        error(
+          format!("{}.path", context),
+          format!("could not load {}: {}", path, e),
+        )
+      })?;
+      for mesh in &mut meshes {
+        if let Some(material) = material {
+          mesh.material = material_named(material)?;
+        }
+      }
+      if let Some(displacement) = displacement {
+        let height = textures.get(displacement.texture.as_str()).ok_or_else(|| {
+          error(
+            format!("{}.displacement.texture", context),
+            format!("unknown texture `{}`", displacement.texture),
+          )
+        })?;
+        let triangles: usize = meshes.iter().map(|mesh| mesh.indices.len()).sum();
+        let subdivided = 4usize
+          .checked_pow(displacement.subdivisions)
+          .and_then(|factor| factor.checked_mul(triangles));
+        if subdivided.is_none_or(|count| count > MAX_DISPLACED_TRIANGLES) {
+          return Err(error(
+            format!("{}.displacement.subdivisions", context),
+            format!(
+              "{} subdivisions of {} triangles make more than {}",
+              displacement.subdivisions, triangles, MAX_DISPLACED_TRIANGLES
+            ),
+          ));
+        }
+        for mesh in &mut meshes {
+          mesh.displace(
+            height.as_ref(),
+            displacement.scale,
+            displacement.subdivisions,
+          );
+        }
+      }
+      Ok(meshes)
+    };
+    let first = world.len();
+    if let Some((shape, sampled, material_name)) = shape_of(object.get_ref(), &context, &error)? {
+      let material = material_named(material_name)?;
+      if sampled && material.is_emissive() {
+        lights.push(placed(shape(material_named(material_name)?), transform));
+      }
+      world.push(placed(shape(material), transform));
+      tag_objects(&mut world, first, index);
+      continue;
+    }
+
+    match object.get_ref() {
+      ObjectDesc::Csg {
+        operation, a, b, ..
+      } => {
+        let csg = Csg::new(
+          *operation,
+          solid(a, &format!("{}.a", context), &error, &material_at)?,
+          solid(b, &format!("{}.b", context), &error, &material_at)?,
+        );
+        world.push(placed(Box::new(csg), transform));
+      }
+      ObjectDesc::MovingSphere {
+        center0,
+        center1,
+        radius,
+        material: material_name,
+        ..
+      } => {
+        if *radius <= 0.0 {
+          return Err(invalid(
+            object.span(),
+            format!("{}.radius", context),
+            format!("{} must be positive", radius),
+          ));
+        }
+        // built over the shutter interval, so its box covers every ray's time
+        let (open, close) = (desc.shutter_open, desc.shutter_close);
+        let at = |time: f32| *center0 + time * (*center1 - *center0);
+        let sphere =
+          |material| MovingSphere::new(at(open), at(close), open, close, *radius, material);
+        let material = material_named(material_name)?;
+        if material.is_emissive() {
+          lights.push(placed(
+            Box::new(sphere(material_named(material_name)?)),
+            transform,
+          ));
+        }
+        world.push(placed(Box::new(sphere(material)), transform));
+      }
+      ObjectDesc::Medium {
+        center,
+        radius,
+        density,
+        material: material_name,
+        ..
+      } => {
+        if *radius <= 0.0 {
+          return Err(invalid(
+            object.span(),
+            format!("{}.radius", context),
+            format!("{} must be positive", radius),
+          ));
+        }
+        if *density <= 0.0 {
+          return Err(invalid(
+            object.span(),
+            format!("{}.density", context),
+            format!("{} must be positive", density),
+          ));
+        }
+        // the boundary's material is never seen
+        let boundary = Sphere::new(*center, *radius, Box::new(Lambertian::new(Vec3::default())));
+        world.push(placed(
+          Box::new(ConstantMedium::with_phase(
+            Box::new(boundary),
+            *density,
+            material_named(material_name)?,
+          )),
+          transform,
+        ));
+      }
+      ObjectDesc::Mesh {
+        path,
+        material,
+        displacement,
+        transform: Some(_),
+      } => {
+        let key = (
+          path.as_str(),
+          material.as_deref(),
+          displacement
+            .as_ref()
+            .map(|d| (d.texture.as_str(), d.scale.to_bits(), d.subdivisions)),
+        );
+        if let Entry::Vacant(slot) = instances.entry(key) {
+          let mut triangles: Vec<Box<dyn Hittable>> = Vec::new();
+          let mut emissive: Vec<Box<dyn Hittable>> = Vec::new();
+          for mesh in load_meshes(path, material, displacement)? {
+            let mesh = Arc::new(mesh);
+            if mesh.material.is_emissive() {
+              for triangle in TriangleMesh::triangles(mesh.clone()) {
+                emissive.push(Box::new(triangle));
+              }
+            }
+            for triangle in TriangleMesh::triangles(mesh) {
+              triangles.push(Box::new(triangle));
+            }
+          }
+          let emissive: Option<Arc<dyn Hittable>> = if emissive.is_empty() {
+            None
+          } else {
+            Some(Arc::new(emissive))
+          };
+          slot.insert((Arc::new(BvhNode::new(triangles)), emissive));
+        }
+        let (triangles, emissive) = &instances[&key];
+        let transform = transform.unwrap();
+        world.push(Box::new(Transformed::new(triangles.clone(), transform)));
+        if let Some(emissive) = emissive {
+          lights.push(Box::new(Transformed::new(emissive.clone(), transform)));
+        }
+      }
+      ObjectDesc::Mesh {
+        path,
+        material,
+        displacement,
+        transform: None,
+      } => {
+        for mesh in load_meshes(path, material, displacement)? {
+          let mesh = Arc::new(mesh);
+          if mesh.material.is_emissive() {
+            for triangle in TriangleMesh::triangles(mesh.clone()) {
+              lights.push(Box::new(triangle));
+            }
+          }
+          for triangle in TriangleMesh::triangles(mesh) {
+            world.push(Box::new(triangle));
+          }
+        }
+      }
+      _ => unreachable!("shapes are built above"),
+    }
+    tag_objects(&mut world, first, index);
+  }
+
+  Ok(Scene {
+    world,
+    lights,
+    camera,
+    settings,
+  })
+}
+
+/// Wraps the objects `world` gained from `first` on in `Tagged`, so their
+/// hits report `index`, the scene object they were built from.
+fn tag_objects(world: &mut Vec<Box<dyn Hittable>>, first: usize, index: usize) {
+  let tagged: Vec<Box<dyn Hittable>> = world
+    .drain(first..)
+    .map(|hittable| Box::new(Tagged::new(hittable, index as u32)) as Box<dyn Hittable>)
+    .collect();
+  world.extend(tagged);
+}
+
+/// Builds the material of a validated `desc`.
+fn build_material(
+  desc: &MaterialDesc,
+  materials: &BTreeMap<String, Spanned<MaterialDesc>>,
+  textures: &BTreeMap<&str, Arc<dyn Texture>>,
+) -> Box<dyn Material> {
+  let albedo = |albedo: &Option<Vec3>, texture: &Option<String>| match (albedo, texture) {
+    (Some(albedo), _) => Arc::new(SolidColor::new(*albedo)),
+    (None, Some(texture)) => textures[texture.as_str()].clone(),
+    (None, None) => unreachable!("materials are validated before they are built"),
+  };
+  match desc {
+    MaterialDesc::Lambertian { albedo: a, texture } => {
+      Box::new(Lambertian::textured(albedo(a, texture)))
+    }
+    MaterialDesc::Metal {
+      albedo: a,
+      texture,
+      fuzz,
+    } => Box::new(Metal::textured(albedo(a, texture), *fuzz)),
+    MaterialDesc::Dielectric { ref_idx } => Box::new(Dielectric::new(*ref_idx)),
+    MaterialDesc::RoughConductor {
+      conductor,
+      eta,
+      k,
+      roughness,
+    } => Box::new(match (conductor, eta, k) {
+      (Some(conductor), _, _) => RoughConductor::preset(*conductor, *roughness),
+      (None, Some(eta), Some(k)) => RoughConductor::new(*eta, *k, *roughness),
+      _ => unreachable!("materials are validated before they are built"),
+    }),
+    MaterialDesc::RoughDielectric { ref_idx, roughness } => {
+      Box::new(RoughDielectric::new(*ref_idx, *roughness))
+    }
+    MaterialDesc::DiffuseLight { emit } => Box::new(DiffuseLight::new(*emit)),
+    MaterialDesc::Isotropic { albedo: a, texture } => {
+      Box::new(Isotropic::textured(albedo(a, texture)))
+    }
+    MaterialDesc::HenyeyGreenstein {
+      albedo: a,
+      texture,
+      g,
+    } => Box::new(HenyeyGreenstein::textured(albedo(a, texture), *g)),
+    MaterialDesc::Bumped {
+      material,
+      normal_map,
+      height_map,
+      scale,
+    } => {
+      let base = build_material(materials[material].get_ref(), materials, textures);
+      let map = match (normal_map, height_map) {
+        (Some(texture), _) => BumpMap::Normal(textures[texture.as_str()].clone()),
+        (None, Some(texture)) => BumpMap::Height {
+          texture: textures[texture.as_str()].clone(),
+          scale: *scale,
+        },
+        (None, None) => unreachable!("materials are validated before they are built"),
+      };
+      Box::new(Bumped::new(base, map))
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::tonemap::ToneMapping;
+  use super::*;
+
+  const SCENE: &str = r#"
+[camera]
+look_from = [0.0, 0.0, 1.0]
+look_at = [0.0, 0.0, -1.0]
+vertical_fov = 90.0
+
+[render]
+width = 40
+height = 20
+seed = 7
+
+[materials.blue]
+type = "lambertian"
+albedo = [0.1, 0.2, 0.5]
+
+[materials.glass]
+type = "dielectric"
+ref_idx = 1.5
+
+[[objects]]
+type = "sphere"
+center = [0.0, 0.0, -1.0]
+radius = 0.5
+material = "blue"
+
+[[objects]]
+type = "sphere"
+center = [1.0, 0.0, -1.0]
+radius = 0.5
+material = "glass"
+"#;
+
+  fn error_of(source: &str) -> String {
+    error_of_in(source, Path::new(""))
+  }
+
+  fn error_of_in(source: &str, base_dir: &Path) -> String {
+    match parse_scene(source, base_dir, &RenderOverrides::default()) {
+      Ok(_) => panic!("scene should not load"),
+      Err(e) => e.to_string(),
+    }
+  }
+
+  #[test]
+  fn load_objects_and_settings() {
+    let scene = parse_scene(SCENE, Path::new(""), &RenderOverrides::default()).unwrap();
+    assert_eq!(2, scene.world.len());
+    assert_eq!(40, scene.settings.width);
+    assert_eq!(20, scene.settings.height);
+    assert_eq!(7, scene.settings.seed);
+    assert_eq!(100, scene.settings.samples_per_pixel);
+    let r = super::super::ray::Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, -1.0));
+    let rec = scene.world.hit(&r, 0.001, f32::MAX).unwrap();
+    assert_eq!(0.5, rec.t);
+  }
+
+  #[test]
+  fn background_and_lights() {
+    let source = format!(
+      "{}\n[background]\ntype = \"solid\"\ncolor = [0.0, 0.0, 0.0]\n\n\
+       [materials.lamp]\ntype = \"diffuse_light\"\nemit = [4.0, 4.0, 4.0]\n",
+      SCENE
+    );
+    let scene = parse_scene(&source, Path::new(""), &RenderOverrides::default()).unwrap();
+    assert_eq!(
+      Vec3(0.0, 0.0, 0.0),
+      scene.settings.background.radiance(Vec3(0.0, 1.0, 0.0))
+    );
+    assert!(scene.lights.is_empty());
+    let source = source.replace("material = \"glass\"", "material = \"lamp\"");
+    let scene = parse_scene(&source, Path::new(""), &RenderOverrides::default()).unwrap();
+    assert_eq!(2, scene.world.len());
+    assert_eq!(1, scene.lights.len());
+    let source = format!(
+      "{}\n[background]\ntype = \"image\"\npath = \"missing.hdr\"\n",
+      SCENE
+    );
+    let message = error_of(&source);
+    assert!(
+      message.contains("background.path: could not load missing.hdr"),
+      "{}",
+      message
+    );
+  }
+
+  #[test]
+  fn overrides_beat_file_settings() {
+    let overrides = RenderOverrides {
+      width: Some(60),
+      samples_per_pixel: Some(3),
+      ..RenderOverrides::default()
+    };
+    let scene = parse_scene(SCENE, Path::new(""), &overrides).unwrap();
+    assert_eq!(60, scene.settings.width);
+    assert_eq!(20, scene.settings.height);
+    assert_eq!(3, scene.settings.samples_per_pixel);
+    assert_eq!(7, scene.settings.seed);
+  }
+
+  #[test]
+  fn missing_mesh_reports_path() {
+    let source = format!(
+      "{}\n[[objects]]\ntype = \"mesh\"\npath = \"missing.obj\"\n",
+      SCENE
+    );
+    let message = error_of(&source);
+    assert!(
+      message.starts_with("line 32: objects[2].path: could not load missing.obj"),
+      "{}",
+      message
+    );
+  }
+
+  #[test]
+  fn unknown_material_reports_line() {
+    let source = SCENE.replace("material = \"glass\"", "material = \"gold\"");
+    assert_eq!(
+      "line 26: objects[1].material: unknown material `gold`",
+      error_of(&source)
+    );
+  }
+
+  #[test]
+  fn invalid_radius_reports_field() {
+    let source = SCENE.replace(
+      "radius = 0.5\nmaterial = \"blue\"",
+      "radius = -1.0\nmaterial = \"blue\"",
+    );
+    assert_eq!(
+      "line 20: objects[0].radius: -1 must be positive",
+      error_of(&source)
+    );
+  }
+
+  #[test]
+  fn textured_materials() {
+    let source = SCENE.replace(
+      "albedo = [0.1, 0.2, 0.5]",
+      "texture = \"checks\"\n\n[textures.checks]\ntype = \"checker\"\nscale = 0.5\n\
+       even = [1.0, 1.0, 1.0]\nodd = [0.0, 0.0, 0.0]",
+    );
+    let scene = parse_scene(&source, Path::new(""), &RenderOverrides::default()).unwrap();
+    assert_eq!(2, scene.world.len());
+
+    let source = SCENE.replace("albedo = [0.1, 0.2, 0.5]", "texture = \"wood\"");
+    assert_eq!(
+      "line 12: materials.blue: unknown texture `wood`",
+      error_of(&source)
+    );
+    let source = SCENE.replace("albedo = [0.1, 0.2, 0.5]", "");
+    assert_eq!(
+      "line 12: materials.blue: needs an albedo or a texture",
+      error_of(&source)
+    );
+  }
+
+  #[test]
+  fn microfacet_materials() {
+    let source = SCENE
+      .replace(
+        "type = \"lambertian\"\nalbedo = [0.1, 0.2, 0.5]",
+        "type = \"rough_conductor\"\nconductor = \"copper\"\nroughness = 0.3",
+      )
+      .replace(
+        "type = \"dielectric\"",
+        "type = \"rough_dielectric\"\nroughness = 0.1",
+      );
+    let scene = parse_scene(&source, Path::new(""), &RenderOverrides::default()).unwrap();
+    assert_eq!(2, scene.world.len());
+
+    let custom = source.replace(
+      "conductor = \"copper\"",
+      "eta = [0.2, 0.9, 1.1]\nk = [3.9, 2.4, 2.1]",
+    );
+    assert!(parse_scene(&custom, Path::new(""), &RenderOverrides::default()).is_ok());
+    let source = source.replace("conductor = \"copper\"", "eta = [0.2, 0.9, 1.1]");
+    assert_eq!(
+      "line 12: materials.blue: needs either a conductor or both eta and k",
+      error_of(&source)
+    );
+    let source = custom.replace("roughness = 0.1", "roughness = 2.0");
+    assert_eq!(
+      "line 18: materials.glass: roughness 2 is not between 0 and 1",
+      error_of(&source)
+    );
+  }
+
+  #[test]
+  fn shapes() {
+    let shapes = "\n[materials.lamp]\ntype = \"diffuse_light\"\nemit = [4.0, 4.0, 4.0]\n\n\
+      [[objects]]\ntype = \"plane\"\npoint = [0.0, -0.5, 0.0]\nnormal = [0.0, 1.0, 0.0]\n\
+      material = \"blue\"\n\n\
+      [[objects]]\ntype = \"disk\"\ncenter = [0.0, 2.0, -1.0]\nnormal = [0.0, -1.0, 0.0]\n\
+      radius = 0.5\nmaterial = \"lamp\"\n\n\
+      [[objects]]\ntype = \"rect\"\nmin = [-1.0, 3.0, -2.0]\nmax = [1.0, 3.0, 0.0]\n\
+      material = \"lamp\"\n\n\
+      [[objects]]\ntype = \"box\"\nmin = [-2.0, -0.5, -3.0]\nmax = [-1.5, 0.5, -2.5]\n\
+      material = \"blue\"\n\n\
+      [[objects]]\ntype = \"cylinder\"\nbase = [1.5, -0.5, -3.0]\nradius = 0.3\nheight = 1.0\n\
+      material = \"lamp\"\n\n\
+      [[objects]]\ntype = \"cone\"\nbase = [0.0, -0.5, -3.0]\nradius = 0.3\nheight = 1.0\n\
+      material = \"glass\"\n\n\
+      [[objects]]\ntype = \"torus\"\ncenter = [0.0, 0.0, -5.0]\nmajor_radius = 1.0\n\
+      minor_radius = 0.2\nmaterial = \"blue\"\ntransform = { rotate = [90.0, 0.0, 0.0] }\n";
+    let source = format!("{}{}", SCENE, shapes);
+    let scene = parse_scene(&source, Path::new(""), &RenderOverrides::default()).unwrap();
+    assert_eq!(9, scene.world.len());
+    // the emissive cylinder is hit but not sampled
+    assert_eq!(2, scene.lights.len());
+    let r = super::super::ray::Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, -1.0, 0.0));
+    let rec = scene.world.hit(&r, 0.001, f32::MAX).unwrap();
+    assert_eq!((0.5, Some(2)), (rec.t, rec.object_id));
+
+    let source = source.replace("max = [1.0, 3.0, 0.0]", "max = [1.0, 3.5, 0.0]");
+    assert_eq!(
+      "line 49: objects[4].max: must share a coordinate with min",
+      error_of(&source)
+    );
+    // a rect flat in two coordinates, and a box inside out
+    let source =
+      format!("{}{}", SCENE, shapes).replace("max = [1.0, 3.0, 0.0]", "max = [1.0, 3.0, -2.0]");
+    assert_eq!(
+      "line 49: objects[4].max.z: -2 is not above min.z -2",
+      error_of(&source)
+    );
+    let source =
+      format!("{}{}", SCENE, shapes).replace("max = [-1.5, 0.5, -2.5]", "max = [-2.5, 0.5, -2.5]");
+    assert_eq!(
+      "line 55: objects[5].max.x: -2.5 is not above min.x -2",
+      error_of(&source)
+    );
+    let source = format!("{}{}", SCENE, shapes).replace("minor_radius = 0.2", "minor_radius = 0.0");
+    assert_eq!(
+      "line 75: objects[8].minor_radius: 0 must be positive",
+      error_of(&source)
+    );
+  }
+
+  #[test]
+  fn csg_objects() {
+    let csg = "\n[[objects]]\ntype = \"csg\"\noperation = \"difference\"\n\n\
+      [objects.a]\ntype = \"box\"\nmin = [-1.0, -1.0, -6.0]\nmax = [1.0, 1.0, -4.0]\n\
+      material = \"blue\"\n\n\
+      [objects.b]\ntype = \"sphere\"\ncenter = [0.0, 1.0, -5.0]\nradius = 0.5\n\
+      material = \"glass\"\n";
+    let source = format!("{}{}", SCENE, csg);
+    let scene = parse_scene(&source, Path::new(""), &RenderOverrides::default()).unwrap();
+    assert_eq!(3, scene.world.len());
+    // down into the dent the sphere leaves in the top of the box
+    let r = super::super::ray::Ray::new(Vec3(0.0, 3.0, -5.0), Vec3(0.0, -1.0, 0.0));
+    let rec = scene.world.hit(&r, 0.001, f32::MAX).unwrap();
+    assert!((2.5 - rec.t).abs() < 1e-5);
+    assert!((Vec3(0.0, 1.0, 0.0) - rec.normal).length() < 1e-5);
+
+    let source = format!("{}{}", SCENE, csg).replace(
+      "center = [0.0, 1.0, -5.0]\nradius = 0.5",
+      "center = [0.0, 1.0, -5.0]\nradius = -1.0",
+    );
+    assert_eq!(
+      "line 32: objects[2].b.radius: -1 must be positive",
+      error_of(&source)
+    );
+    let source = format!("{}{}", SCENE, csg).replace(
+      "type = \"sphere\"\ncenter = [0.0, 1.0, -5.0]\nradius = 0.5",
+      "type = \"plane\"\npoint = [0.0, 1.0, -5.0]\nnormal = [0.0, 1.0, 0.0]",
+    );
+    assert_eq!(
+      "line 32: objects[2].b: only spheres, boxes, cylinders, cones, tori and csg objects can be combined",
+      error_of(&source)
+    );
+  }
+
+  #[test]
+  fn moving_spheres_and_shutter() {
+    let source = SCENE
+      .replace(
+        "vertical_fov = 90.0",
+        "vertical_fov = 90.0\nshutter_open = 0.0\nshutter_close = 1.0",
+      )
+      .replace(
+        "type = \"sphere\"\ncenter = [1.0, 0.0, -1.0]",
+        "type = \"moving_sphere\"\ncenter0 = [1.0, 0.0, -1.0]\ncenter1 = [1.0, 0.5, -1.0]",
+      );
+    let scene = parse_scene(&source, Path::new(""), &RenderOverrides::default()).unwrap();
+    assert_eq!(2, scene.world.len());
+    let bbox = scene.world[1].bounding_box().unwrap();
+    assert_eq!(1.0, bbox.max.y());
+
+    // past time 1 the sphere keeps moving, and its box with it
+    let source = source.replace("shutter_close = 1.0", "shutter_close = 2.0");
+    let scene = parse_scene(&source, Path::new(""), &RenderOverrides::default()).unwrap();
+    let bbox = scene.world[1].bounding_box().unwrap();
+    assert_eq!(1.5, bbox.max.y());
+    let bvh = BvhNode::new(scene.world);
+    let r = super::super::ray::Ray::new_at_time(Vec3(1.0, 1.0, 1.0), Vec3(0.0, 0.0, -1.0), 2.0);
+    assert_eq!(1.5, bvh.hit(&r, 0.001, f32::MAX).unwrap().t);
+
+    let source = SCENE.replace(
+      "vertical_fov = 90.0",
+      "vertical_fov = 90.0\nshutter_open = 1.0",
+    );
+    assert_eq!(
+      "line 2: camera.shutter_close: 0 is before shutter_open",
+      error_of(&source)
+    );
+  }
+
+  #[test]
+  fn instanced_meshes() {
+    let dir = std::env::temp_dir().join(format!(
+      "rust_ray_tracing_scene_instances_{}",
+      std::process::id()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+      dir.join("quad.obj"),
+      "v -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\nf 1 2 3 4\n",
+    )
+    .unwrap();
+    let source = format!(
+      "{}\n[[objects]]\ntype = \"mesh\"\npath = \"quad.obj\"\n\
+       transform = {{ translate = [0.0, 0.0, -3.0] }}\n\n\
+       [[objects]]\ntype = \"mesh\"\npath = \"quad.obj\"\n\
+       transform = {{ rotate = [0.0, 90.0, 0.0], translate = [5.0, 0.0, 0.0] }}\n",
+      SCENE
+    );
+    let scene = parse_scene(&source, &dir, &RenderOverrides::default()).unwrap();
+    // two spheres and two instances rather than four triangles
+    assert_eq!(4, scene.world.len());
+    let r = super::super::ray::Ray::new(Vec3(0.0, 0.9, 0.0), Vec3(0.0, 0.0, -1.0));
+    assert_eq!(3.0, scene.world.hit(&r, 0.001, f32::MAX).unwrap().t);
+    let r = super::super::ray::Ray::new(Vec3(0.0, 0.0, 0.5), Vec3(1.0, 0.0, 0.0));
+    let rec = scene.world.hit(&r, 0.001, f32::MAX).unwrap();
+    assert!((5.0 - rec.t).abs() < 1e-5);
+    assert!((rec.normal.x().abs() - 1.0).abs() < 1e-5);
+
+    let source = source.replace("rotate = [0.0, 90.0, 0.0]", "scale = [1.0, 0.0, 1.0]");
+    let message = error_of_in(&source, &dir);
+    assert!(
+      message.ends_with("objects[3].transform.scale: must not be 0 on any axis"),
+      "{}",
+      message
+    );
+  }
+
+  #[test]
+  fn bump_maps_and_displacement() {
+    let dir = std::env::temp_dir().join(format!(
+      "rust_ray_tracing_scene_displacement_{}",
+      std::process::id()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+      dir.join("quad.obj"),
+      "v -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\nf 1 2 3 4\n",
+    )
+    .unwrap();
+    let source = format!(
+      "{}\n[[objects]]\ntype = \"mesh\"\npath = \"quad.obj\"\n\
+       displacement = {{ texture = \"lift\", scale = 0.5, subdivisions = 2 }}\n\n\
+       [textures.lift]\ntype = \"solid\"\ncolor = [1.0, 1.0, 1.0]\n\n\
+       [textures.hills]\ntype = \"noise\"\nscale = 3.0\n\n\
+       [materials.rough]\ntype = \"bumped\"\nmaterial = \"blue\"\nheight_map = \"lift\"\n",
+      SCENE.replace("material = \"glass\"", "material = \"rough\"")
+    );
+    let scene = parse_scene(&source, &dir, &RenderOverrides::default()).unwrap();
+    // two spheres and the quad's 32 triangles after two subdivisions
+    assert_eq!(34, scene.world.len());
+    let r = super::super::ray::Ray::new(Vec3(0.0, 0.9, 3.0), Vec3(0.0, 0.0, -1.0));
+    let rec = scene.world.hit(&r, 0.001, f32::MAX).unwrap();
+    assert!((2.5 - rec.t).abs() < 1e-5);
+
+    // noise lifts the quad unevenly, by 0 to 0.5
+    let hills = source.replace("texture = \"lift\", scale", "texture = \"hills\", scale");
+    let scene = parse_scene(&hills, &dir, &RenderOverrides::default()).unwrap();
+    let heights: Vec<f32> = (0..25)
+      .map(|i| {
+        let (x, y) = ((i % 5) as f32 * 0.4 - 0.8, (i / 5) as f32 * 0.4 - 0.8);
+        let r = super::super::ray::Ray::new(Vec3(x, y, 3.0), Vec3(0.0, 0.0, -1.0));
+        3.0 - scene.world.hit(&r, 0.001, f32::MAX).unwrap().t
+      })
+      .collect();
+    let lowest = heights.iter().cloned().fold(f32::MAX, f32::min);
+    let highest = heights.iter().cloned().fold(f32::MIN, f32::max);
+    assert!(lowest >= 0.0 && highest <= 0.5, "{:?}", heights);
+    assert!(highest - lowest > 0.05, "{:?}", heights);
+
+    let message = error_of_in(
+      &source.replace("height_map", "normal_map = \"lift\"\nheight_map"),
+      &dir,
+    );
+    assert!(
+      message.ends_with("materials.rough: needs either a normal_map or a height_map"),
+      "{}",
+      message
+    );
+    let message = error_of_in(
+      &source.replace("material = \"blue\"", "material = \"rough\""),
+      &dir,
+    );
+    assert!(
+      message.ends_with("materials.rough: `rough` is bumped already"),
+      "{}",
+      message
+    );
+    let message = error_of_in(
+      &source.replace("subdivisions = 2", "subdivisions = 11"),
+      &dir,
+    );
+    assert!(
+      message.ends_with(
+        "objects[2].displacement.subdivisions: 11 subdivisions of 2 triangles make more than 4194304"
+      ),
+      "{}",
+      message
+    );
+    let message = error_of_in(
+      &source.replace("texture = \"lift\"", "texture = \"sink\""),
+      &dir,
+    );
+    assert!(
+      message.ends_with("objects[2].displacement.texture: unknown texture `sink`"),
+      "{}",
+      message
+    );
+  }
+
+  #[test]
+  fn media() {
+    let source = format!(
+      "{}\n[materials.fog]\ntype = \"henyey_greenstein\"\nalbedo = [0.9, 0.9, 0.9]\ng = 0.5\n\n\
+       [[objects]]\ntype = \"medium\"\ncenter = [0.0, 0.0, 0.0]\nradius = 10.0\n\
+       density = 0.01\nmaterial = \"fog\"\n",
+      SCENE
+    );
+    let scene = parse_scene(&source, Path::new(""), &RenderOverrides::default()).unwrap();
+    assert_eq!(3, scene.world.len());
+
+    let message = error_of(&source.replace("g = 0.5", "g = 1.5"));
+    assert!(
+      message.ends_with("materials.fog: g 1.5 is not between -1 and 1"),
+      "{}",
+      message
+    );
+    let message = error_of(&source.replace("density = 0.01", "density = 0.0"));
+    assert!(
+      message.ends_with("objects[2].density: 0 must be positive"),
+      "{}",
+      message
+    );
+  }
+
+  #[test]
+  fn progressive_settings() {
+    let source = SCENE.replace(
+      "seed = 7",
+      "seed = 7\npass_samples = 8\nnoise_threshold = 0.02\ntime_limit = 1.5\nsampler = \"sobol\"\nfilter = \"mitchell\"\nfilter_radius = 1.5\ntone_map = \"agx\"\nexposure = -0.5\ndither = true",
+    );
+    let scene = parse_scene(&source, Path::new(""), &RenderOverrides::default()).unwrap();
+    assert_eq!(8, scene.settings.pass_samples);
+    assert_eq!(0.02, scene.settings.noise_threshold);
+    assert_eq!(Some(Duration::from_millis(1500)), scene.settings.time_limit);
+    assert_eq!(SamplerKind::Sobol, scene.settings.sampler);
+    assert_eq!(
+      Filter::with_radius(FilterKind::Mitchell, 1.5),
+      scene.settings.filter
+    );
+    assert_eq!(
+      ToneMapping {
+        tone_map: ToneMap::Agx,
+        exposure: -0.5,
+        dither: true
+      },
+      scene.settings.tone_mapping
+    );
+
+    let source = SCENE.replace("seed = 7", "time_limit = -1.0");
+    assert_eq!(
+      "line 7: render.time_limit: must be a number of seconds",
+      error_of(&source)
+    );
+    let source = SCENE.replace("seed = 7", "filter_radius = 0.0");
+    assert_eq!(
+      "line 7: render.filter_radius: must be positive",
+      error_of(&source)
+    );
+  }
+
+  #[test]
+  fn parse_errors_have_line_and_field() {
+    let source = SCENE.replace("ref_idx = 1.5", "ref_index = 1.5");
+    let message = error_of(&source);
+    assert!(message.contains("line 16"), "{}", message);
+    assert!(message.contains("ref_index"), "{}", message);
+    let message = error_of("[camera]\nlook_from = [0.0, 0.0]\n");
+    assert!(message.contains("line 2"), "{}", message);
+  }
+}
@@ -1,8 +1,11 @@
-use super::hittable::HitRecord;
-use super::hittable::Hittable;
+use super::aabb::Aabb;
+use super::hittable::{HitRecord, Hittable, Span};
 use super::material::Material;
+use super::onb::Onb;
 use super::ray::Ray;
-use super::vec3::Vec3;
+use super::sampler::Sampler;
+use super::vec3::{random_unit_vector, Vec3};
+use std::f32;
 
 pub struct Sphere {
   pub center: Vec3,
@@ -20,44 +23,150 @@ impl Sphere {
   }
 }
 
+/// Longitude/latitude coordinates of a point on the unit sphere: u goes
+/// around the y axis starting from -x, v from the bottom pole to the top one.
+pub fn sphere_uv(p: Vec3) -> (f32, f32) {
+  let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+  let phi = (-p.z()).atan2(p.x()) + f32::consts::PI;
+  (phi / (2.0 * f32::consts::PI), theta / f32::consts::PI)
+}
+
 impl Hittable for Sphere {
-  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-    let oc = r.origin - self.center;
-    let a = r.direction.dot(r.direction);
-    let b = oc.dot(r.direction);
-    let c = oc.dot(oc) - self.radius * self.radius;
-    let discriminant = b * b - a * c;
-    if discriminant > 0.0 {
-      let t = (-b - discriminant.sqrt()) / a;
-      if t_min < t && t < t_max {
-        let p = r.point_at_parameter(t);
-        let normal = (p - self.center) / self.radius;
-        return Some(HitRecord {
-          t,
-          p,
-          normal,
-          material: &*self.material,
-        });
-      }
-      let t = (-b + discriminant.sqrt()) / a;
-      if t_min < t && t < t_max {
-        let p = r.point_at_parameter(t);
-        let normal = (p - self.center) / self.radius;
-        return Some(HitRecord {
-          t,
-          p,
-          normal,
-          material: &*self.material,
-        });
-      }
+  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+    hit_sphere(self.center, self.radius, &*self.material, r, t_min, t_max)
+  }
+
+  fn spans(&self, r: &Ray) -> Option<Vec<Span<'_>>> {
+    Some(sphere_spans(self.center, self.radius, &*self.material, r))
+  }
+
+  fn bounding_box(&self) -> Option<Aabb> {
+    let r = Vec3(self.radius, self.radius, self.radius);
+    Some(Aabb::new(self.center - r, self.center + r))
+  }
+
+  fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+    if self
+      .hit(&Ray::new_at_time(origin, direction, time), 0.001, f32::MAX)
+      .is_none()
+    {
+      return 0.0;
+    }
+    cone_pdf(self.center, self.radius, origin)
+  }
+
+  fn random(&self, origin: Vec3, _time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+    cone_sample(self.center, self.radius, origin, sampler)
+  }
+}
+
+/// Intersection of `r` with the sphere of `center` and `radius`, shared by
+/// the static and moving spheres.
+pub(crate) fn hit_sphere<'m>(
+  center: Vec3,
+  radius: f32,
+  material: &'m dyn Material,
+  r: &Ray,
+  t_min: f32,
+  t_max: f32,
+) -> Option<HitRecord<'m>> {
+  let roots = sphere_roots(center, radius, r)?;
+  let t = roots.iter().copied().find(|t| t_min < *t && *t < t_max)?;
+  Some(sphere_record(center, radius, material, r, t))
+}
+
+/// Where the line through `r` enters and leaves the sphere, if it does.
+fn sphere_roots(center: Vec3, radius: f32, r: &Ray) -> Option<[f32; 2]> {
+  let oc = r.origin - center;
+  let a = r.direction.dot(r.direction);
+  let b = oc.dot(r.direction);
+  let c = oc.dot(oc) - radius * radius;
+  let discriminant = b * b - a * c;
+  if discriminant <= 0.0 {
+    return None;
+  }
+  let root = discriminant.sqrt();
+  Some([(-b - root) / a, (-b + root) / a])
+}
+
+fn sphere_record<'m>(
+  center: Vec3,
+  radius: f32,
+  material: &'m dyn Material,
+  r: &Ray,
+  t: f32,
+) -> HitRecord<'m> {
+  let n = (r.point_at_parameter(t) - center) / radius;
+  let (u, v) = sphere_uv(n);
+  // u turns the point around y, v lifts it from the bottom pole to the top;
+  // at the poles dpdv is not a number and `with_uv` makes up tangents
+  let s = (n.x() * n.x() + n.z() * n.z()).sqrt();
+  let dpdu = 2.0 * f32::consts::PI * radius * Vec3(n.z(), 0.0, -n.x());
+  let dpdv = f32::consts::PI * radius * Vec3(-n.x() * n.y() / s, s, -n.y() * n.z() / s);
+  HitRecord::new(r, t, n, material).with_uv(u, v, dpdu, dpdv)
+}
+
+/// The span of the line through `r` inside the sphere, shared by the static
+/// and moving spheres.
+pub(crate) fn sphere_spans<'m>(
+  center: Vec3,
+  radius: f32,
+  material: &'m dyn Material,
+  r: &Ray,
+) -> Vec<Span<'m>> {
+  match sphere_roots(center, radius, r) {
+    Some([enter, exit]) => vec![Span {
+      enter: sphere_record(center, radius, material, r, enter),
+      exit: sphere_record(center, radius, material, r, exit),
+    }],
+    None => Vec::new(),
+  }
+}
+
+/// Density of `cone_sample`, assuming the direction hits the sphere.
+pub(crate) fn cone_pdf(center: Vec3, radius: f32, origin: Vec3) -> f32 {
+  match cos_theta_max(center, radius, origin) {
+    Some(cos_theta_max) => 1.0 / (2.0 * f32::consts::PI * (1.0 - cos_theta_max)),
+    None => 1.0 / (4.0 * f32::consts::PI),
+  }
+}
+
+/// Uniform over the cone of directions the sphere covers seen from `origin`.
+pub(crate) fn cone_sample(
+  center: Vec3,
+  radius: f32,
+  origin: Vec3,
+  sampler: &mut dyn Sampler,
+) -> Vec3 {
+  match cos_theta_max(center, radius, origin) {
+    Some(cos_theta_max) => {
+      let (r1, r2) = sampler.next_2d();
+      let z = 1.0 + r2 * (cos_theta_max - 1.0);
+      let phi = 2.0 * f32::consts::PI * r1;
+      let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+      Onb::from_w(center - origin).local(Vec3(phi.cos() * sin_theta, phi.sin() * sin_theta, z))
     }
+    // inside the sphere every direction hits it
+    None => random_unit_vector(sampler),
+  }
+}
+
+/// Cosine of the half angle of the cone the sphere covers from `origin`,
+/// `None` when `origin` is inside.
+fn cos_theta_max(center: Vec3, radius: f32, origin: Vec3) -> Option<f32> {
+  let distance_squared = (center - origin).squared_length();
+  let radius_squared = radius * radius;
+  if distance_squared <= radius_squared {
     None
+  } else {
+    Some((1.0 - radius_squared / distance_squared).sqrt())
   }
 }
 
 #[cfg(test)]
 mod tests {
   use super::super::material::Lambertian;
+  use super::super::sampler::IndependentSampler;
   use super::*;
 
   #[test]
@@ -68,7 +177,7 @@ mod tests {
       2.0,
       Box::new(Lambertian::new(Vec3(0.3, 0.3, 0.3))),
     );
-    assert!(sphere.hit(&r, 0.0001, std::f32::MAX).is_none());
+    assert!(sphere.hit(&r, 0.0001, f32::MAX).is_none());
   }
 
   #[test]
@@ -79,7 +188,28 @@ mod tests {
       2.0,
       Box::new(Lambertian::new(Vec3(0.3, 0.3, 0.3))),
     );
-    assert!(sphere.hit(&r, 0.0001, std::f32::MAX).is_none());
+    assert!(sphere.hit(&r, 0.0001, f32::MAX).is_none());
+  }
+
+  #[test]
+  fn sphere_sampling() {
+    let sphere = Sphere::new(
+      Vec3(0.0, 0.0, -5.0),
+      3.0,
+      Box::new(Lambertian::new(Vec3(0.3, 0.3, 0.3))),
+    );
+    let origin = Vec3(0.0, 0.0, 0.0);
+    // cone with cos(theta_max) = 0.8
+    let expected = 1.0 / (2.0 * f32::consts::PI * 0.2);
+    let mut sampler = IndependentSampler::new(1);
+    for _ in 0..100 {
+      let direction = sphere.random(origin, 0.0, &mut sampler);
+      assert!(sphere
+        .hit(&Ray::new(origin, direction), 0.001, f32::MAX)
+        .is_some());
+      assert!((expected - sphere.pdf_value(origin, direction, 0.0)).abs() < 1e-4);
+    }
+    assert_eq!(0.0, sphere.pdf_value(origin, Vec3(0.0, 1.0, 0.0), 0.0));
   }
 
   #[test]
@@ -90,10 +220,23 @@ mod tests {
       3.0,
       Box::new(Lambertian::new(Vec3(0.3, 0.3, 0.3))),
     );
-    let rec = sphere.hit(&r, 0.0001, std::f32::MAX);
+    let rec = sphere.hit(&r, 0.0001, f32::MAX);
     assert!(rec.is_some());
     assert_eq!(4.236068, rec.unwrap().t);
     assert_eq!(Vec3(4.236068, 0.0, 0.0), rec.unwrap().p);
-    assert_eq!(Vec3(0.74535596, -0.6666667, 0.0), rec.unwrap().normal);
+    // from inside, with the normal turned back towards the ray
+    assert_eq!(
+      Vec3(0.74535596, -0.6666667, 0.0),
+      rec.unwrap().outward_normal()
+    );
+    assert!(!rec.unwrap().front_face);
+  }
+
+  #[test]
+  fn sphere_uv_mapping() {
+    assert_eq!((0.5, 0.5), sphere_uv(Vec3(1.0, 0.0, 0.0)));
+    assert_eq!((0.25, 0.5), sphere_uv(Vec3(0.0, 0.0, 1.0)));
+    assert_eq!(1.0, sphere_uv(Vec3(0.0, 1.0, 0.0)).1);
+    assert_eq!(0.0, sphere_uv(Vec3(0.0, -1.0, 0.0)).1);
   }
 }
@@ -0,0 +1,312 @@
+extern crate image;
+extern crate serde;
+
+use super::perlin::Perlin;
+use super::vec3::Vec3;
+use serde::Deserialize;
+use std::path::Path;
+
+pub use self::image::ImageError;
+
+/// Color varying over a surface, looked up by surface coordinates (u, v)
+/// and hit point `p`.
+pub trait Texture: Send + Sync {
+  fn value(&self, u: f32, v: f32, p: Vec3) -> Vec3;
+}
+
+pub struct SolidColor {
+  pub color: Vec3,
+}
+
+impl SolidColor {
+  pub fn new(color: Vec3) -> Self {
+    SolidColor { color }
+  }
+}
+
+impl Texture for SolidColor {
+  fn value(&self, _u: f32, _v: f32, _p: Vec3) -> Vec3 {
+    self.color
+  }
+}
+
+/// 3D checkerboard of cubes `scale` wide, alternating between two textures.
+pub struct CheckerTexture {
+  pub scale: f32,
+  pub even: Box<dyn Texture>,
+  pub odd: Box<dyn Texture>,
+}
+
+impl CheckerTexture {
+  pub fn new(scale: f32, even: Box<dyn Texture>, odd: Box<dyn Texture>) -> Self {
+    CheckerTexture { scale, even, odd }
+  }
+}
+
+impl Texture for CheckerTexture {
+  fn value(&self, u: f32, v: f32, p: Vec3) -> Vec3 {
+    let cell = (p.x() / self.scale).floor() as i64
+      + (p.y() / self.scale).floor() as i64
+      + (p.z() / self.scale).floor() as i64;
+    if cell.rem_euclid(2) == 0 {
+      self.even.value(u, v, p)
+    } else {
+      self.odd.value(u, v, p)
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseStyle {
+  /// Plain Perlin noise.
+  #[default]
+  Smooth,
+  /// Several octaves of noise summed.
+  Turbulence,
+  /// Sine stripes along z disturbed by turbulence.
+  Marble,
+}
+
+/// Grey Perlin noise, `scale` being its frequency.
+pub struct NoiseTexture {
+  perlin: Perlin,
+  pub scale: f32,
+  pub style: NoiseStyle,
+}
+
+impl NoiseTexture {
+  pub fn new(scale: f32, style: NoiseStyle, seed: u64) -> Self {
+    NoiseTexture {
+      perlin: Perlin::new(seed),
+      scale,
+      style,
+    }
+  }
+}
+
+impl Texture for NoiseTexture {
+  fn value(&self, _u: f32, _v: f32, p: Vec3) -> Vec3 {
+    let s = self.scale * p;
+    let grey = match self.style {
+      NoiseStyle::Smooth => 0.5 * (1.0 + self.perlin.noise(s)),
+      NoiseStyle::Turbulence => self.perlin.turbulence(s, 7),
+      NoiseStyle::Marble => 0.5 * (1.0 + (s.z() + 10.0 * self.perlin.turbulence(p, 7)).sin()),
+    };
+    Vec3(grey, grey, grey)
+  }
+}
+
+/// What lookups outside [0, 1] texture coordinates read.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WrapMode {
+  #[default]
+  Repeat,
+  Clamp,
+  Mirror,
+}
+
+impl WrapMode {
+  fn wrap(self, i: i64, size: usize) -> usize {
+    let n = size as i64;
+    let i = match self {
+      WrapMode::Repeat => i.rem_euclid(n),
+      WrapMode::Clamp => i.clamp(0, n - 1),
+      WrapMode::Mirror => {
+        let i = i.rem_euclid(2 * n);
+        if i < n {
+          i
+        } else {
+          2 * n - 1 - i
+        }
+      }
+    };
+    i as usize
+  }
+}
+
+/// Bilinearly filtered image, u running left to right and v bottom to top.
+pub struct ImageTexture {
+  width: usize,
+  height: usize,
+  /// Linear colors, top row first.
+  pixels: Vec<Vec3>,
+  pub wrap: WrapMode,
+}
+
+impl ImageTexture {
+  pub fn new(width: usize, height: usize, pixels: Vec<Vec3>, wrap: WrapMode) -> Self {
+    assert_eq!(width * height, pixels.len());
+    ImageTexture {
+      width,
+      height,
+      pixels,
+      wrap,
+    }
+  }
+
+  /// Loads a PNG, JPEG, Radiance HDR or OpenEXR file, see `load_linear`.
+  pub fn load<P: AsRef<Path>>(path: P, wrap: WrapMode) -> Result<Self, ImageError> {
+    let (width, height, pixels) = load_linear(path)?;
+    Ok(ImageTexture::new(width, height, pixels, wrap))
+  }
+
+  /// Loads an image file holding data rather than colors, such as a normal
+  /// or height map, whose values are used as they are stored.
+  pub fn load_data<P: AsRef<Path>>(path: P, wrap: WrapMode) -> Result<Self, ImageError> {
+    let (width, height, pixels) = load(path, false)?;
+    Ok(ImageTexture::new(width, height, pixels, wrap))
+  }
+
+  fn texel(&self, x: i64, y: i64) -> Vec3 {
+    let x = self.wrap.wrap(x, self.width);
+    let y = self.wrap.wrap(y, self.height);
+    self.pixels[y * self.width + x]
+  }
+}
+
+impl Texture for ImageTexture {
+  fn value(&self, u: f32, v: f32, _p: Vec3) -> Vec3 {
+    // texel centers sit at half integer coordinates
+    let x = u * self.width as f32 - 0.5;
+    let y = (1.0 - v) * self.height as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let top = (1.0 - tx) * self.texel(x0, y0) + tx * self.texel(x0 + 1, y0);
+    let bottom = (1.0 - tx) * self.texel(x0, y0 + 1) + tx * self.texel(x0 + 1, y0 + 1);
+    (1.0 - ty) * top + ty * bottom
+  }
+}
+
+/// Width, height and linear colors, top row first, of an image file. 8 and
+/// 16-bit images are taken to be sRGB encoded and converted to linear, float
+/// ones (HDR and EXR) are used as they are.
+pub fn load_linear<P: AsRef<Path>>(path: P) -> Result<(usize, usize, Vec<Vec3>), ImageError> {
+  load(path, true)
+}
+
+/// Width, height and values of an image file, decoded from sRGB if `srgb`
+/// and the image is not a float one.
+fn load<P: AsRef<Path>>(path: P, srgb: bool) -> Result<(usize, usize, Vec<Vec3>), ImageError> {
+  let decoded = image::open(path)?;
+  let linear = !srgb
+    || matches!(
+      decoded,
+      image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_)
+    );
+  let rgb = decoded.into_rgb32f();
+  let decode = |c: f32| if linear { c } else { srgb_to_linear(c) };
+  let pixels = rgb
+    .pixels()
+    .map(|p| Vec3(decode(p[0]), decode(p[1]), decode(p[2])))
+    .collect();
+  Ok((rgb.width() as usize, rgb.height() as usize, pixels))
+}
+
+/// sRGB electro-optical transfer function.
+pub fn srgb_to_linear(c: f32) -> f32 {
+  if c <= 0.04045 {
+    c / 12.92
+  } else {
+    ((c + 0.055) / 1.055).powf(2.4)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::tonemap::ToneMapping;
+  use super::*;
+
+  fn gradient() -> ImageTexture {
+    // 2x2: black, red on the top row, green, blue on the bottom one
+    ImageTexture::new(
+      2,
+      2,
+      vec![
+        Vec3(0.0, 0.0, 0.0),
+        Vec3(1.0, 0.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+        Vec3(0.0, 0.0, 1.0),
+      ],
+      WrapMode::Clamp,
+    )
+  }
+
+  #[test]
+  fn checker() {
+    let checker = CheckerTexture::new(
+      1.0,
+      Box::new(SolidColor::new(Vec3(1.0, 1.0, 1.0))),
+      Box::new(SolidColor::new(Vec3(0.0, 0.0, 0.0))),
+    );
+    assert_eq!(
+      Vec3(1.0, 1.0, 1.0),
+      checker.value(0.0, 0.0, Vec3(0.5, 0.5, 0.5))
+    );
+    assert_eq!(
+      Vec3(0.0, 0.0, 0.0),
+      checker.value(0.0, 0.0, Vec3(1.5, 0.5, 0.5))
+    );
+    assert_eq!(
+      Vec3(1.0, 1.0, 1.0),
+      checker.value(0.0, 0.0, Vec3(-0.5, -0.5, 0.5))
+    );
+  }
+
+  #[test]
+  fn bilinear_lookup() {
+    let texture = gradient();
+    // texel centers
+    assert_eq!(
+      Vec3(0.0, 0.0, 0.0),
+      texture.value(0.25, 0.75, Vec3(0.0, 0.0, 0.0))
+    );
+    assert_eq!(
+      Vec3(0.0, 0.0, 1.0),
+      texture.value(0.75, 0.25, Vec3(0.0, 0.0, 0.0))
+    );
+    // halfway between all four
+    assert_eq!(
+      Vec3(0.25, 0.25, 0.25),
+      texture.value(0.5, 0.5, Vec3(0.0, 0.0, 0.0))
+    );
+    // clamped outside
+    assert_eq!(
+      Vec3(1.0, 0.0, 0.0),
+      texture.value(2.0, 2.0, Vec3(0.0, 0.0, 0.0))
+    );
+  }
+
+  #[test]
+  fn wrap_modes() {
+    assert_eq!(1, WrapMode::Repeat.wrap(-3, 4));
+    assert_eq!(0, WrapMode::Clamp.wrap(-3, 4));
+    assert_eq!(3, WrapMode::Clamp.wrap(9, 4));
+    assert_eq!(2, WrapMode::Mirror.wrap(-3, 4));
+    assert_eq!(3, WrapMode::Mirror.wrap(4, 4));
+    assert_eq!(0, WrapMode::Mirror.wrap(8, 4));
+  }
+
+  #[test]
+  fn load_png_as_linear() {
+    let path = std::env::temp_dir().join(format!(
+      "rust_ray_tracing_texture_test_{}.png",
+      std::process::id()
+    ));
+    let mut image = super::super::image::Image::new(1, 1);
+    image.set(0, 0, Vec3(0.25, 1.0, 0.0));
+    super::super::output::save(&image, &path, &ToneMapping::default()).unwrap();
+    let texture = ImageTexture::load(&path, WrapMode::Repeat).unwrap();
+    let c = texture.value(0.5, 0.5, Vec3(0.0, 0.0, 0.0));
+    // sRGB encoded on the way out and decoded on the way in
+    assert!((c.r() - srgb_to_linear(137.0 / 255.0)).abs() < 1e-6);
+    assert!((c.r() - 0.25).abs() < 2e-3);
+    assert_eq!(1.0, c.g());
+    // data images keep their stored values
+    let data = ImageTexture::load_data(&path, WrapMode::Repeat).unwrap();
+    let c = data.value(0.5, 0.5, Vec3(0.0, 0.0, 0.0));
+    assert_eq!(137.0 / 255.0, c.r());
+  }
+}
@@ -0,0 +1,237 @@
+extern crate serde;
+
+use super::vec3::Vec3;
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// Curve taking linear scene radiance to display values in [0, 1].
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ToneMap {
+  /// Values above 1 are clipped.
+  #[default]
+  Clamp,
+  /// x / (1 + x) on each channel.
+  Reinhard,
+  /// Stephen Hill's fit of the ACES reference and sRGB output transforms.
+  Aces,
+  /// Troy Sobotka's AgX, which desaturates highlights instead of skewing
+  /// their hue.
+  Agx,
+}
+
+impl FromStr for ToneMap {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "clamp" => Ok(ToneMap::Clamp),
+      "reinhard" => Ok(ToneMap::Reinhard),
+      "aces" => Ok(ToneMap::Aces),
+      "agx" => Ok(ToneMap::Agx),
+      _ => Err(format!(
+        "unknown tone map `{}`, expected clamp, reinhard, aces or agx",
+        s
+      )),
+    }
+  }
+}
+
+impl fmt::Display for ToneMap {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      ToneMap::Clamp => "clamp",
+      ToneMap::Reinhard => "reinhard",
+      ToneMap::Aces => "aces",
+      ToneMap::Agx => "agx",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+/// How a linear image is turned into 8 or 16-bit sRGB pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ToneMapping {
+  pub tone_map: ToneMap,
+  /// In stops: the image is scaled by 2^exposure before tone mapping.
+  pub exposure: f32,
+  /// Adds noise of one code value before rounding to 8 bits, turning the
+  /// banding of smooth gradients into grain.
+  pub dither: bool,
+}
+
+impl ToneMapping {
+  /// sRGB encoded display value of a linear color, each channel in [0, 1].
+  pub fn encode(&self, linear: Vec3) -> Vec3 {
+    let display = self.tone_map.apply(linear * 2f32.powf(self.exposure));
+    let encode = |c: f32| srgb_oetf(c.clamp(0.0, 1.0));
+    Vec3(
+      encode(display.r()),
+      encode(display.g()),
+      encode(display.b()),
+    )
+  }
+}
+
+impl ToneMap {
+  /// Display linear color of linear radiance `c`.
+  pub fn apply(self, c: Vec3) -> Vec3 {
+    let c = Vec3(c.r().max(0.0), c.g().max(0.0), c.b().max(0.0));
+    match self {
+      ToneMap::Clamp => c,
+      ToneMap::Reinhard => Vec3(
+        c.r() / (1.0 + c.r()),
+        c.g() / (1.0 + c.g()),
+        c.b() / (1.0 + c.b()),
+      ),
+      ToneMap::Aces => aces(c),
+      ToneMap::Agx => agx(c),
+    }
+  }
+}
+
+/// sRGB opto-electronic transfer function, linear [0, 1] to encoded [0, 1].
+pub fn srgb_oetf(c: f32) -> f32 {
+  if c <= 0.003_130_8 {
+    12.92 * c
+  } else {
+    1.055 * c.powf(1.0 / 2.4) - 0.055
+  }
+}
+
+fn mul(m: &[[f32; 3]; 3], c: Vec3) -> Vec3 {
+  let row = |r: &[f32; 3]| r[0] * c.r() + r[1] * c.g() + r[2] * c.b();
+  Vec3(row(&m[0]), row(&m[1]), row(&m[2]))
+}
+
+fn aces(c: Vec3) -> Vec3 {
+  // sRGB to the ACES reference transform's working space, with its exposure
+  const INPUT: [[f32; 3]; 3] = [
+    [0.597_19, 0.354_58, 0.048_23],
+    [0.076_00, 0.908_34, 0.015_66],
+    [0.028_40, 0.133_83, 0.837_77],
+  ];
+  const OUTPUT: [[f32; 3]; 3] = [
+    [1.604_75, -0.531_08, -0.073_67],
+    [-0.102_08, 1.108_13, -0.006_05],
+    [-0.003_27, -0.072_76, 1.076_02],
+  ];
+  let fit = |v: f32| {
+    (v * (v + 0.024_578_6) - 0.000_090_537) / (v * (0.983_729 * v + 0.432_951) + 0.238_081)
+  };
+  let c = mul(&INPUT, c);
+  mul(&OUTPUT, Vec3(fit(c.r()), fit(c.g()), fit(c.b())))
+}
+
+fn agx(c: Vec3) -> Vec3 {
+  // inset of the primaries, and its inverse
+  const INSET: [[f32; 3]; 3] = [
+    [0.842_479_06, 0.078_433_6, 0.079_223_75],
+    [0.042_328_24, 0.878_468_6, 0.079_166_13],
+    [0.042_375_65, 0.078_433_6, 0.879_143],
+  ];
+  const OUTSET: [[f32; 3]; 3] = [
+    [1.196_879, -0.098_020_88, -0.099_029_74],
+    [-0.052_896_85, 1.151_903_1, -0.098_961_18],
+    [-0.052_971_64, -0.098_043_45, 1.151_073_7],
+  ];
+  const MIN_EV: f32 = -12.473_93;
+  const MAX_EV: f32 = 4.026_069;
+  // sigmoid fitted to AgX's default contrast, on log encoded values
+  let contrast = |x: f32| {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+      - 0.002_32
+  };
+  let log = |v: f32| (v.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+  let c = mul(&INSET, c);
+  let c = mul(
+    &OUTSET,
+    Vec3(
+      contrast(log(c.r())),
+      contrast(log(c.g())),
+      contrast(log(c.b())),
+    ),
+  );
+  // the curve's output is meant for a 2.2 gamma display
+  let linear = |v: f32| v.max(0.0).powf(2.2);
+  Vec3(linear(c.r()), linear(c.g()), linear(c.b()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const TONE_MAPS: [ToneMap; 4] = [
+    ToneMap::Clamp,
+    ToneMap::Reinhard,
+    ToneMap::Aces,
+    ToneMap::Agx,
+  ];
+
+  #[test]
+  fn tone_maps_parse_their_names() {
+    for tone_map in &TONE_MAPS {
+      assert_eq!(Ok(*tone_map), tone_map.to_string().parse());
+    }
+    assert!("filmic".parse::<ToneMap>().is_err());
+  }
+
+  #[test]
+  fn srgb_transfer_function() {
+    assert_eq!(0.0, srgb_oetf(0.0));
+    assert!((srgb_oetf(1.0) - 1.0).abs() < 1e-6);
+    // the linear and power pieces meet
+    assert!((srgb_oetf(0.003_130_8) - 0.040_45).abs() < 1e-5);
+    assert!((srgb_oetf(0.003_130_9) - 0.040_45).abs() < 1e-5);
+    assert!((srgb_oetf(0.18) - 0.461_356).abs() < 1e-5);
+  }
+
+  #[test]
+  fn curves_are_monotonic_and_bounded() {
+    for tone_map in &TONE_MAPS {
+      let mapping = ToneMapping {
+        tone_map: *tone_map,
+        ..ToneMapping::default()
+      };
+      let mut previous = -1.0;
+      for i in 0..200 {
+        let x = 1e-3 * 1.08f32.powi(i);
+        let encoded = mapping.encode(Vec3(x, x, x));
+        assert!(encoded.r() >= previous, "{} at {}", tone_map, x);
+        assert!((0.0..=1.0).contains(&encoded.r()), "{} at {}", tone_map, x);
+        previous = encoded.r();
+      }
+      assert!(
+        mapping.encode(Vec3(0.0, 0.0, 0.0)).r() < 0.01,
+        "{}",
+        tone_map
+      );
+      let invalid = mapping.encode(Vec3(-1.0, f32::NAN, 0.0));
+      assert!(invalid.r() < 0.01 && invalid.g() < 0.01, "{}", tone_map);
+    }
+    // all but clamping keep highlights apart
+    for tone_map in &TONE_MAPS[1..] {
+      let bright = tone_map.apply(Vec3(4.0, 4.0, 4.0)).r();
+      assert!(
+        tone_map.apply(Vec3(2.0, 2.0, 2.0)).r() < bright,
+        "{}",
+        tone_map
+      );
+      assert!(bright < 1.0, "{}", tone_map);
+    }
+  }
+
+  #[test]
+  fn exposure_is_in_stops() {
+    let brighter = ToneMapping {
+      exposure: 1.0,
+      ..ToneMapping::default()
+    };
+    let encoded = brighter.encode(Vec3(0.25, 0.1, 0.0));
+    let expected = ToneMapping::default().encode(Vec3(0.5, 0.2, 0.0));
+    assert!((encoded - expected).length() < 1e-6);
+  }
+}
@@ -0,0 +1,276 @@
+use super::aabb::Aabb;
+use super::cylinder::{around_y, around_y_tangent};
+use super::hittable::{spans_between, HitRecord, Hittable, Span};
+use super::material::Material;
+use super::ray::Ray;
+use super::vec3::Vec3;
+use std::f32;
+use std::f64;
+
+/// Ring around `center` in the xz plane: the tube of radius `minor_radius`
+/// around the circle of radius `major_radius`. u goes around the y axis and
+/// v around the tube, from its outer equator over the top.
+pub struct Torus {
+  pub center: Vec3,
+  pub major_radius: f32,
+  pub minor_radius: f32,
+  pub material: Box<dyn Material>,
+}
+
+impl Torus {
+  pub fn new(
+    center: Vec3,
+    major_radius: f32,
+    minor_radius: f32,
+    material: Box<dyn Material>,
+  ) -> Self {
+    Torus {
+      center,
+      major_radius,
+      minor_radius,
+      material,
+    }
+  }
+}
+
+impl Torus {
+  /// The t of every crossing of the line through `r` with the surface.
+  fn crossings(&self, r: &Ray) -> Vec<f32> {
+    // in double precision, from where the line enters the bounding sphere,
+    // so that the quartic's coefficients stay of similar sizes
+    let v = |a: Vec3| [f64::from(a.x()), f64::from(a.y()), f64::from(a.z())];
+    let dot = |a: [f64; 3], b: [f64; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+    let (big, small) = (f64::from(self.major_radius), f64::from(self.minor_radius));
+    let length = f64::from(r.direction.length());
+    let d = v(r.direction).map(|c| c / length);
+    let o = v(r.origin - self.center);
+    let bound = big + small;
+    let b = dot(o, d);
+    let discriminant = b * b - (dot(o, o) - bound * bound);
+    if discriminant <= 0.0 {
+      return Vec::new();
+    }
+    let start = -b - discriminant.sqrt();
+    let o = [
+      o[0] + start * d[0],
+      o[1] + start * d[1],
+      o[2] + start * d[2],
+    ];
+
+    // (|p|² + R² - r²)² = 4R² (x² + z²) along p = o + s d, with |d| = 1
+    let b = 2.0 * dot(o, d);
+    let c = dot(o, o) + big * big - small * small;
+    let r2 = 4.0 * big * big;
+    let coefficients = [
+      2.0 * b,
+      b * b + 2.0 * c - r2 * (d[0] * d[0] + d[2] * d[2]),
+      2.0 * b * c - 2.0 * r2 * (o[0] * d[0] + o[2] * d[2]),
+      c * c - r2 * (o[0] * o[0] + o[2] * o[2]),
+    ];
+    solve_quartic(coefficients)
+      .into_iter()
+      .map(|s| ((s + start) / length) as f32)
+      .collect()
+  }
+
+  fn record(&self, r: &Ray, t: f32) -> HitRecord<'_> {
+    let local = r.point_at_parameter(t) - self.center;
+    let radial = Vec3(local.x(), 0.0, local.z()).to_unit_vector();
+    let normal = (local - radial * self.major_radius).to_unit_vector();
+    let outwards = Vec3(local.x(), 0.0, local.z()).length() - self.major_radius;
+    let tube = (local.y() as f64).atan2(outwards as f64) / (2.0 * f64::consts::PI);
+    // around the tube, the point turns from the normal towards +y
+    let along_tube = Vec3(0.0, normal.dot(radial), 0.0) - normal.y() * radial;
+    HitRecord::new(r, t, normal, &*self.material).with_uv(
+      around_y(local),
+      tube.rem_euclid(1.0) as f32,
+      around_y_tangent(local),
+      2.0 * f32::consts::PI * self.minor_radius * along_tube,
+    )
+  }
+}
+
+impl Hittable for Torus {
+  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+    let t = self
+      .crossings(r)
+      .into_iter()
+      .filter(|t| t_min < *t && *t < t_max)
+      .min_by(f32::total_cmp)?;
+    Some(self.record(r, t))
+  }
+
+  fn spans(&self, r: &Ray) -> Option<Vec<Span<'_>>> {
+    let hits = self.crossings(r).into_iter();
+    Some(spans_between(hits.map(|t| self.record(r, t)).collect()))
+  }
+
+  fn bounding_box(&self) -> Option<Aabb> {
+    let outer = self.major_radius + self.minor_radius;
+    let half = Vec3(outer, self.minor_radius, outer);
+    Some(Aabb::new(self.center - half, self.center + half))
+  }
+}
+
+/// Real roots of x⁴ + a x³ + b x² + c x + d, by Ferrari's method and a
+/// couple of Newton steps to polish them.
+fn solve_quartic([a, b, c, d]: [f64; 4]) -> Vec<f64> {
+  // depressed to y⁴ + p y² + q y + r with x = y - a / 4
+  let a2 = a * a;
+  let p = b - 3.0 * a2 / 8.0;
+  let q = c - a * b / 2.0 + a2 * a / 8.0;
+  let r = d - a * c / 4.0 + a2 * b / 16.0 - 3.0 * a2 * a2 / 256.0;
+  let mut roots = Vec::with_capacity(4);
+  let mut quadratic = |s: f64, t: f64| {
+    let discriminant = s * s - 4.0 * t;
+    if discriminant >= 0.0 {
+      let root = discriminant.sqrt();
+      roots.push((-s - root) / 2.0);
+      roots.push((-s + root) / 2.0);
+    }
+  };
+  if q.abs() < 1e-12 {
+    // biquadratic: y² is a root of z² + p z + r
+    let discriminant = p * p - 4.0 * r;
+    if discriminant >= 0.0 {
+      for z in [
+        (-p - discriminant.sqrt()) / 2.0,
+        (-p + discriminant.sqrt()) / 2.0,
+      ] {
+        if z >= 0.0 {
+          quadratic(0.0, -z);
+        }
+      }
+    }
+  } else {
+    // a positive root m of the resolvent cubic splits the quartic into
+    // y² ± √(2m) y + p / 2 + m ∓ q / (2 √(2m))
+    let m = solve_cubic(p, p * p / 4.0 - r, -q * q / 8.0)
+      .into_iter()
+      .fold(f64::NEG_INFINITY, f64::max);
+    if m <= 0.0 {
+      return Vec::new();
+    }
+    let s = (2.0 * m).sqrt();
+    quadratic(s, p / 2.0 + m - q / (2.0 * s));
+    quadratic(-s, p / 2.0 + m + q / (2.0 * s));
+  }
+  let f = |x: f64| (((x + a) * x + b) * x + c) * x + d;
+  let df = |x: f64| ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+  roots
+    .into_iter()
+    .map(|y| {
+      let mut x = y - a / 4.0;
+      for _ in 0..2 {
+        let slope = df(x);
+        if slope != 0.0 {
+          x -= f(x) / slope;
+        }
+      }
+      x
+    })
+    .collect()
+}
+
+/// Real roots of x³ + a x² + b x + c.
+fn solve_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+  let q = (a * a - 3.0 * b) / 9.0;
+  let r = (2.0 * a * a * a - 9.0 * a * b + 27.0 * c) / 54.0;
+  if r * r < q * q * q {
+    let theta = (r / (q * q * q).sqrt()).clamp(-1.0, 1.0).acos();
+    let scale = -2.0 * q.sqrt();
+    (0..3)
+      .map(|k| scale * ((theta + 2.0 * f64::consts::PI * k as f64) / 3.0).cos() - a / 3.0)
+      .collect()
+  } else {
+    let big = -r.signum() * (r.abs() + (r * r - q * q * q).sqrt()).cbrt();
+    let small = if big != 0.0 { q / big } else { 0.0 };
+    vec![big + small - a / 3.0]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::material::Lambertian;
+  use super::*;
+
+  fn torus() -> Torus {
+    Torus::new(
+      Vec3(0.0, 0.0, -5.0),
+      2.0,
+      0.5,
+      Box::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+    )
+  }
+
+  #[test]
+  fn quartic_roots() {
+    // (x - 1)(x + 2)(x - 3)(x + 0.5)
+    let mut roots = solve_quartic([-1.5, -6.0, 3.5, 3.0]);
+    roots.sort_by(f64::total_cmp);
+    let expected = [-2.0, -0.5, 1.0, 3.0];
+    assert_eq!(4, roots.len());
+    for (root, expected) in roots.iter().zip(&expected) {
+      assert!((root - expected).abs() < 1e-9, "{:?}", roots);
+    }
+    // x⁴ + 1 has none
+    assert!(solve_quartic([0.0, 0.0, 0.0, 1.0]).is_empty());
+  }
+
+  #[test]
+  fn torus_hit() {
+    let torus = torus();
+    // along the x axis through the center: the near side of the tube
+    let r = Ray::new(Vec3(-10.0, 0.0, -5.0), Vec3(2.0, 0.0, 0.0));
+    let rec = torus.hit(&r, 0.001, f32::MAX).unwrap();
+    assert!((rec.t - 3.75).abs() < 1e-5);
+    assert!((rec.normal - Vec3(-1.0, 0.0, 0.0)).length() < 1e-5);
+    assert!((rec.u - 0.5).abs() < 1e-5 && rec.v.abs() < 1e-5);
+    // from the hole, the inner equator
+    let r = Ray::new(Vec3(0.0, 0.0, -5.0), Vec3(1.0, 0.0, 0.0));
+    let rec = torus.hit(&r, 0.001, f32::MAX).unwrap();
+    assert!((rec.t - 1.5).abs() < 1e-5);
+    assert!((rec.normal - Vec3(-1.0, 0.0, 0.0)).length() < 1e-5);
+    assert!((rec.v - 0.5).abs() < 1e-5);
+    // down onto the top of the tube
+    let r = Ray::new(Vec3(0.0, 3.0, -3.0), Vec3(0.0, -1.0, 0.0));
+    let rec = torus.hit(&r, 0.001, f32::MAX).unwrap();
+    assert!((rec.t - 2.5).abs() < 1e-5);
+    assert!((rec.normal - Vec3(0.0, 1.0, 0.0)).length() < 1e-5);
+    assert!((rec.v - 0.25).abs() < 1e-5);
+    // down through the hole
+    let r = Ray::new(Vec3(0.0, 3.0, -5.0), Vec3(0.0, -1.0, 0.0));
+    assert!(torus.hit(&r, 0.001, f32::MAX).is_none());
+    assert_eq!(
+      Aabb::new(Vec3(-2.5, -0.5, -7.5), Vec3(2.5, 0.5, -2.5)),
+      torus.bounding_box().unwrap()
+    );
+  }
+
+  #[test]
+  fn torus_tangent() {
+    // just over the top of the tube
+    let torus = torus();
+    let r = Ray::new(Vec3(-10.0, 0.501, -5.0), Vec3(1.0, 0.0, 0.0));
+    assert!(torus.hit(&r, 0.001, f32::MAX).is_none());
+    // and from the surface, on to the other side of the ring
+    let r = Ray::new(Vec3(-2.5, 0.0, -5.0), Vec3(1.0, 0.0, 0.0));
+    let rec = torus.hit(&r, 0.001, f32::MAX).unwrap();
+    assert!((rec.t - 1.0).abs() < 1e-4);
+  }
+
+  #[test]
+  fn torus_spans() {
+    // both sides of the tube, from behind the ray's origin too
+    let r = Ray::new(Vec3(0.0, 0.0, -5.0), Vec3(1.0, 0.0, 0.0));
+    let torus = torus();
+    let spans = torus.spans(&r).unwrap();
+    let ts: Vec<_> = spans.iter().map(|s| (s.enter.t, s.exit.t)).collect();
+    let expected = [(-2.5, -1.5), (1.5, 2.5)];
+    assert_eq!(2, ts.len());
+    for ((enter, exit), (e_enter, e_exit)) in ts.iter().zip(expected.iter()) {
+      assert!((enter - e_enter).abs() < 1e-4 && (exit - e_exit).abs() < 1e-4);
+    }
+    assert_eq!(-1.0, spans[1].enter.normal.x().round());
+  }
+}
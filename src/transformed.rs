@@ -0,0 +1,257 @@
+use super::aabb::Aabb;
+use super::hittable::{HitRecord, Hittable, Span};
+use super::mat4::Mat4;
+use super::ray::Ray;
+use super::sampler::Sampler;
+use super::vec3::Vec3;
+use std::sync::Arc;
+
+/// Instance of `object` placed in the world by an affine transform. The
+/// object is shared, so one mesh can be instanced many times without copying
+/// its triangles.
+pub struct Transformed<H: Hittable + ?Sized = dyn Hittable> {
+  pub object: Arc<H>,
+  placement: Placement,
+}
+
+impl<H: Hittable + ?Sized> Transformed<H> {
+  /// Panics if `transform` is singular, e.g. scales by 0.
+  pub fn new(object: Arc<H>, transform: Mat4) -> Self {
+    let placement = Placement::new(transform).expect("instance transform is not invertible");
+    Transformed { object, placement }
+  }
+
+  pub fn transform(&self) -> Mat4 {
+    self.placement.to_world
+  }
+}
+
+impl<H: Hittable + ?Sized> Hittable for Transformed<H> {
+  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+    self
+      .placement
+      .hit_with(r, |local| self.object.hit(local, t_min, t_max))
+  }
+
+  fn hit_sampled(
+    &self,
+    r: &Ray,
+    t_min: f32,
+    t_max: f32,
+    sampler: &mut dyn Sampler,
+  ) -> Option<HitRecord<'_>> {
+    self.placement.hit_with(r, |local| {
+      self.object.hit_sampled(local, t_min, t_max, sampler)
+    })
+  }
+
+  fn spans(&self, r: &Ray) -> Option<Vec<Span<'_>>> {
+    self.placement.spans(&*self.object, r)
+  }
+
+  fn bounding_box(&self) -> Option<Aabb> {
+    Some(transformed_box(
+      &self.placement.to_world,
+      &self.object.bounding_box()?,
+    ))
+  }
+
+  fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+    self
+      .placement
+      .pdf_value(&*self.object, origin, direction, time)
+  }
+
+  fn random(&self, origin: Vec3, time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+    self.placement.random(&*self.object, origin, time, sampler)
+  }
+}
+
+/// An invertible transform with its inverse, answering queries about an
+/// object by moving them into its space.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Placement {
+  pub to_world: Mat4,
+  pub to_object: Mat4,
+}
+
+impl Placement {
+  /// None if `transform` is singular.
+  pub fn new(transform: Mat4) -> Option<Self> {
+    Some(Placement {
+      to_world: transform,
+      to_object: transform.inverse()?,
+    })
+  }
+
+  /// `r` in the object's space. The direction is not normalized, so t is
+  /// the same in both spaces.
+  fn local(&self, r: &Ray) -> Ray {
+    Ray::new_at_time(
+      self.to_object.transform_point(r.origin),
+      self.to_object.transform_vector(r.direction),
+      r.time,
+    )
+  }
+
+  fn world_record<'a>(&self, rec: HitRecord<'a>) -> HitRecord<'a> {
+    // normals transform with the inverse transpose, which keeps them on the
+    // side of the surface the ray comes from
+    let normal = |n: Vec3| {
+      self
+        .to_object
+        .transpose()
+        .transform_vector(n)
+        .to_unit_vector()
+    };
+    HitRecord {
+      p: self.to_world.transform_point(rec.p),
+      normal: normal(rec.normal),
+      geometric_normal: normal(rec.geometric_normal),
+      dpdu: self.to_world.transform_vector(rec.dpdu),
+      dpdv: self.to_world.transform_vector(rec.dpdv),
+      ..rec
+    }
+  }
+
+  /// The hit `query` finds for `r` in the object's space, in the world's.
+  pub fn hit_with<'a>(
+    &self,
+    r: &Ray,
+    query: impl FnOnce(&Ray) -> Option<HitRecord<'a>>,
+  ) -> Option<HitRecord<'a>> {
+    let rec = query(&self.local(r))?;
+    Some(self.world_record(rec))
+  }
+
+  pub fn spans<'a, H: Hittable + ?Sized>(&self, object: &'a H, r: &Ray) -> Option<Vec<Span<'a>>> {
+    let spans = object.spans(&self.local(r))?;
+    Some(
+      spans
+        .into_iter()
+        .map(|span| Span {
+          enter: self.world_record(span.enter),
+          exit: self.world_record(span.exit),
+        })
+        .collect(),
+    )
+  }
+
+  pub fn pdf_value<H: Hittable + ?Sized>(
+    &self,
+    object: &H,
+    origin: Vec3,
+    direction: Vec3,
+    time: f32,
+  ) -> f32 {
+    let local = self.to_object.transform_vector(direction).to_unit_vector();
+    let pdf = object.pdf_value(self.to_object.transform_point(origin), local, time);
+    // directions map by w = A u / |A u|, which stretches solid angles by
+    // |det A| / |A u|^3
+    let stretched = self.to_world.transform_vector(local).length();
+    pdf * stretched.powi(3) / self.to_world.determinant3().abs()
+  }
+
+  pub fn random<H: Hittable + ?Sized>(
+    &self,
+    object: &H,
+    origin: Vec3,
+    time: f32,
+    sampler: &mut dyn Sampler,
+  ) -> Vec3 {
+    let local = object.random(self.to_object.transform_point(origin), time, sampler);
+    self.to_world.transform_vector(local)
+  }
+}
+
+/// Box around the corners of `b` transformed by `m`.
+pub(crate) fn transformed_box(m: &Mat4, b: &Aabb) -> Aabb {
+  let corner = |i: usize| {
+    m.transform_point(Vec3(
+      if i & 1 == 0 { b.min.x() } else { b.max.x() },
+      if i & 2 == 0 { b.min.y() } else { b.max.y() },
+      if i & 4 == 0 { b.min.z() } else { b.max.z() },
+    ))
+  };
+  let first = Aabb::new(corner(0), corner(0));
+  (1..8).fold(first, |acc, i| {
+    Aabb::surrounding(&acc, &Aabb::new(corner(i), corner(i)))
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::material::Lambertian;
+  use super::super::sampler::IndependentSampler;
+  use super::super::sphere::Sphere;
+  use super::*;
+  use std::f32;
+
+  fn unit_sphere() -> Arc<Sphere> {
+    Arc::new(Sphere::new(
+      Vec3(0.0, 0.0, 0.0),
+      1.0,
+      Box::new(Lambertian::new(Vec3(0.5, 0.5, 0.5))),
+    ))
+  }
+
+  #[test]
+  fn ellipsoid_hit_and_box() {
+    let sphere = unit_sphere();
+    let ellipsoid = Transformed::new(
+      sphere.clone(),
+      Mat4::translation(Vec3(0.0, 0.0, -5.0)) * Mat4::scaling(Vec3(1.0, 1.0, 2.0)),
+    );
+    let r = Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, -1.0));
+    let rec = ellipsoid.hit(&r, 0.001, f32::MAX).unwrap();
+    assert!((3.0 - rec.t).abs() < 1e-5);
+    assert!((Vec3(0.0, 0.0, -3.0) - rec.p).length() < 1e-5);
+    assert!((Vec3(0.0, 0.0, 1.0) - rec.normal).length() < 1e-5);
+
+    // the normal of a stretched sphere tilts towards the short axis
+    let r = Ray::new(Vec3(0.5, 0.0, 0.0), Vec3(0.0, 0.0, -1.0));
+    let rec = ellipsoid.hit(&r, 0.001, f32::MAX).unwrap();
+    let expected = Vec3(0.5, 0.0, (0.75f32).sqrt() / 2.0).to_unit_vector();
+    assert!((expected - rec.normal).length() < 1e-5);
+
+    let bbox = ellipsoid.bounding_box().unwrap();
+    assert!((Vec3(-1.0, -1.0, -7.0) - bbox.min).length() < 1e-5);
+    assert!((Vec3(1.0, 1.0, -3.0) - bbox.max).length() < 1e-5);
+    assert_eq!(2, Arc::strong_count(&sphere));
+  }
+
+  #[test]
+  fn sampling_density_integrates_to_one() {
+    let ellipsoid = Transformed::new(
+      unit_sphere(),
+      Mat4::translation(Vec3(0.0, 0.0, -4.0))
+        * Mat4::rotation(Vec3(1.0, 0.0, 0.0), 30.0)
+        * Mat4::scaling(Vec3(0.5, 1.0, 2.0)),
+    );
+    let origin = Vec3(0.0, 0.0, 0.0);
+    // importance sampling the constant 1 over the instance's solid angle
+    // estimates that solid angle; so does uniform sphere sampling
+    let n = 20000;
+    let mut by_instance = 0.0;
+    let mut uniform = 0.0;
+    let mut sampler = IndependentSampler::new(1);
+    for _ in 0..n {
+      let direction = ellipsoid.random(origin, 0.0, &mut sampler);
+      by_instance += 1.0 / ellipsoid.pdf_value(origin, direction, 0.0);
+      let direction = super::super::vec3::random_unit_vector(&mut sampler);
+      if ellipsoid
+        .hit(&Ray::new(origin, direction), 0.001, f32::MAX)
+        .is_some()
+      {
+        uniform += 4.0 * f32::consts::PI;
+      }
+    }
+    let (by_instance, uniform) = (by_instance / n as f32, uniform / n as f32);
+    assert!(
+      (by_instance - uniform).abs() < 0.05 * uniform,
+      "{} vs {}",
+      by_instance,
+      uniform
+    );
+  }
+}
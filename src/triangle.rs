@@ -0,0 +1,490 @@
+use super::aabb::{axis_of, Aabb};
+use super::hittable::{HitRecord, Hittable};
+use super::material::Material;
+use super::progress::luminance;
+use super::ray::Ray;
+use super::sampler::Sampler;
+use super::texture::Texture;
+use super::vec3::Vec3;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Indexed triangles sharing vertex buffers. `normals` and `uvs` are either
+/// empty or hold one entry per position.
+pub struct TriangleMesh {
+  pub positions: Vec<Vec3>,
+  pub normals: Vec<Vec3>,
+  pub uvs: Vec<(f32, f32)>,
+  pub indices: Vec<[usize; 3]>,
+  pub material: Box<dyn Material>,
+}
+
+impl TriangleMesh {
+  pub fn new(positions: Vec<Vec3>, indices: Vec<[usize; 3]>, material: Box<dyn Material>) -> Self {
+    TriangleMesh {
+      positions,
+      normals: Vec::new(),
+      uvs: Vec::new(),
+      indices,
+      material,
+    }
+  }
+
+  /// One `Triangle` per face, all referencing `mesh`, ready to go in a `BvhNode`.
+  pub fn triangles(mesh: Arc<TriangleMesh>) -> Vec<Triangle> {
+    (0..mesh.indices.len())
+      .map(|index| Triangle::new(mesh.clone(), index))
+      .collect()
+  }
+
+  /// Replaces `normals` with the area weighted average of the faces around
+  /// each vertex, vertices at the same position counting as one so the mesh
+  /// stays smooth across uv seams.
+  pub fn smooth_normals(&mut self) {
+    let key = |p: Vec3| (p.x().to_bits(), p.y().to_bits(), p.z().to_bits());
+    let mut sums = HashMap::new();
+    for &[a, b, c] in &self.indices {
+      let p = &self.positions;
+      let n = (p[b] - p[a]).cross(p[c] - p[a]);
+      for &i in &[a, b, c] {
+        *sums.entry(key(p[i])).or_insert_with(Vec3::default) += n;
+      }
+    }
+    self.normals = self
+      .positions
+      .iter()
+      .map(|&p| match sums.get(&key(p)) {
+        Some(n) if n.squared_length() > 0.0 => n.to_unit_vector(),
+        _ => Vec3::default(),
+      })
+      .collect();
+  }
+
+  /// Splits every face into four at the midpoints of its edges, interpolating
+  /// normals and uvs there. Faces sharing an edge share its midpoint.
+  pub fn subdivide(&mut self) {
+    let mut midpoints = HashMap::new();
+    let faces = std::mem::take(&mut self.indices);
+    for [a, b, c] in faces {
+      let ab = self.midpoint(&mut midpoints, a, b);
+      let bc = self.midpoint(&mut midpoints, b, c);
+      let ca = self.midpoint(&mut midpoints, c, a);
+      self
+        .indices
+        .extend_from_slice(&[[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]);
+    }
+  }
+
+  fn midpoint(
+    &mut self,
+    midpoints: &mut HashMap<(usize, usize), usize>,
+    i: usize,
+    j: usize,
+  ) -> usize {
+    let edge = (i.min(j), i.max(j));
+    if let Some(&m) = midpoints.get(&edge) {
+      return m;
+    }
+    let m = self.positions.len();
+    self
+      .positions
+      .push(0.5 * (self.positions[i] + self.positions[j]));
+    if !self.normals.is_empty() {
+      let n = self.normals[i] + self.normals[j];
+      let n = if n.squared_length() > 0.0 {
+        n.to_unit_vector()
+      } else {
+        self.normals[i]
+      };
+      self.normals.push(n);
+    }
+    if !self.uvs.is_empty() {
+      let ((ui, vi), (uj, vj)) = (self.uvs[i], self.uvs[j]);
+      self.uvs.push((0.5 * (ui + uj), 0.5 * (vi + vj)));
+    }
+    midpoints.insert(edge, m);
+    m
+  }
+
+  /// Displacement mapping: `subdivisions` rounds of `subdivide`, then every
+  /// vertex moves along its normal by `scale` times the luminance of
+  /// `height` at its uv, and the normals are recomputed from the new faces.
+  /// Vertices split along hard edges move apart, so those open up cracks.
+  pub fn displace(&mut self, height: &dyn Texture, scale: f32, subdivisions: u32) {
+    if self.normals.is_empty() {
+      self.smooth_normals();
+    }
+    for _ in 0..subdivisions {
+      self.subdivide();
+    }
+    for (i, p) in self.positions.iter_mut().enumerate() {
+      let (u, v) = self.uvs.get(i).copied().unwrap_or_default();
+      *p += scale * luminance(height.value(u, v, *p)) * self.normals[i];
+    }
+    self.smooth_normals();
+  }
+}
+
+/// Face `index` of a `TriangleMesh`.
+pub struct Triangle {
+  mesh: Arc<TriangleMesh>,
+  index: usize,
+}
+
+impl Triangle {
+  pub fn new(mesh: Arc<TriangleMesh>, index: usize) -> Self {
+    Triangle { mesh, index }
+  }
+
+  fn vertices(&self) -> (Vec3, Vec3, Vec3) {
+    let [a, b, c] = self.mesh.indices[self.index];
+    let p = &self.mesh.positions;
+    (p[a], p[b], p[c])
+  }
+}
+
+fn largest_axis(v: Vec3) -> usize {
+  let (x, y, z) = (v.x().abs(), v.y().abs(), v.z().abs());
+  if x > y && x > z {
+    0
+  } else if y > z {
+    1
+  } else {
+    2
+  }
+}
+
+impl Hittable for Triangle {
+  /// Watertight ray/triangle intersection (Woop, Benthin and Wald 2013): rays
+  /// through a shared edge or vertex always hit one of the adjacent faces.
+  fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+    let (p0, p1, p2) = self.vertices();
+
+    // permute axes so z is the largest ray direction component, keeping winding
+    let kz = largest_axis(r.direction);
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    if axis_of(r.direction, kz) < 0.0 {
+      std::mem::swap(&mut kx, &mut ky);
+    }
+    let dz = axis_of(r.direction, kz);
+    let sx = axis_of(r.direction, kx) / dz;
+    let sy = axis_of(r.direction, ky) / dz;
+    let sz = 1.0 / dz;
+
+    // shear the vertices into a space where the ray runs along +z
+    let a = p0 - r.origin;
+    let b = p1 - r.origin;
+    let c = p2 - r.origin;
+    let shear = |v: Vec3| {
+      (
+        axis_of(v, kx) - sx * axis_of(v, kz),
+        axis_of(v, ky) - sy * axis_of(v, kz),
+      )
+    };
+    let (ax, ay) = shear(a);
+    let (bx, by) = shear(b);
+    let (cx, cy) = shear(c);
+
+    let mut u = cx * by - cy * bx;
+    let mut v = ax * cy - ay * cx;
+    let mut w = bx * ay - by * ax;
+    if u == 0.0 || v == 0.0 || w == 0.0 {
+      // edge case: redo the edge functions in double precision
+      let (ax, ay, bx, by, cx, cy) = (
+        f64::from(ax),
+        f64::from(ay),
+        f64::from(bx),
+        f64::from(by),
+        f64::from(cx),
+        f64::from(cy),
+      );
+      u = (cx * by - cy * bx) as f32;
+      v = (ax * cy - ay * cx) as f32;
+      w = (bx * ay - by * ax) as f32;
+    }
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+      return None;
+    }
+    let det = u + v + w;
+    if det == 0.0 {
+      return None;
+    }
+    let t = (u * sz * axis_of(a, kz) + v * sz * axis_of(b, kz) + w * sz * axis_of(c, kz)) / det;
+    if !(t_min < t && t < t_max) {
+      return None;
+    }
+
+    let (b0, b1, b2) = (u / det, v / det, w / det);
+    let [i0, i1, i2] = self.mesh.indices[self.index];
+    let mut geometric = (p1 - p0).cross(p2 - p0).to_unit_vector();
+    let shading = if self.mesh.normals.is_empty() {
+      geometric
+    } else {
+      let n = &self.mesh.normals;
+      let shading = (b0 * n[i0] + b1 * n[i1] + b2 * n[i2]).to_unit_vector();
+      // vertex normals say which side is out, whatever the winding
+      if geometric.dot(shading) < 0.0 {
+        geometric = -geometric;
+      }
+      shading
+    };
+    let ((u, v), (dpdu, dpdv)) = if self.mesh.uvs.is_empty() {
+      ((b1, b2), (p1 - p0, p2 - p0))
+    } else {
+      let uv = &self.mesh.uvs;
+      let uv_at = (
+        b0 * uv[i0].0 + b1 * uv[i1].0 + b2 * uv[i2].0,
+        b0 * uv[i0].1 + b1 * uv[i1].1 + b2 * uv[i2].1,
+      );
+      // solve the edges p0 - p2 and p1 - p2 for the tangents along u and v;
+      // degenerate uvs leave tangents for `with_uv` to make up
+      let (du02, dv02) = (uv[i0].0 - uv[i2].0, uv[i0].1 - uv[i2].1);
+      let (du12, dv12) = (uv[i1].0 - uv[i2].0, uv[i1].1 - uv[i2].1);
+      let det = du02 * dv12 - dv02 * du12;
+      let (dp02, dp12) = (p0 - p2, p1 - p2);
+      let tangents = if det.abs() > 1e-12 {
+        (
+          (dv12 * dp02 - dv02 * dp12) / det,
+          (du02 * dp12 - du12 * dp02) / det,
+        )
+      } else {
+        (Vec3::default(), Vec3::default())
+      };
+      (uv_at, tangents)
+    };
+    let rec = HitRecord::new(r, t, geometric, &*self.mesh.material)
+      .with_uv(u, v, dpdu, dpdv)
+      .with_shading_normal(shading)
+      .with_primitive_id(self.index as u32);
+    Some(HitRecord {
+      p: b0 * p0 + b1 * p1 + b2 * p2,
+      ..rec
+    })
+  }
+
+  fn pdf_value(&self, origin: Vec3, direction: Vec3, time: f32) -> f32 {
+    match self.hit(&Ray::new_at_time(origin, direction, time), 0.001, f32::MAX) {
+      Some(rec) => {
+        let (p0, p1, p2) = self.vertices();
+        let cross = (p1 - p0).cross(p2 - p0);
+        let area = 0.5 * cross.length();
+        let distance_squared = rec.t * rec.t * direction.squared_length();
+        let cosine = (direction.dot(cross) / (direction.length() * cross.length())).abs();
+        distance_squared / (cosine * area)
+      }
+      None => 0.0,
+    }
+  }
+
+  /// Towards a point picked uniformly on the triangle's area.
+  fn random(&self, origin: Vec3, _time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+    let (p0, p1, p2) = self.vertices();
+    let (u1, u2) = sampler.next_2d();
+    let su0 = u1.sqrt();
+    let b0 = 1.0 - su0;
+    let b1 = u2 * su0;
+    b0 * p0 + b1 * p1 + (1.0 - b0 - b1) * p2 - origin
+  }
+
+  fn bounding_box(&self) -> Option<Aabb> {
+    let (p0, p1, p2) = self.vertices();
+    let min = Vec3(
+      p0.x().min(p1.x()).min(p2.x()),
+      p0.y().min(p1.y()).min(p2.y()),
+      p0.z().min(p1.z()).min(p2.z()),
+    );
+    let max = Vec3(
+      p0.x().max(p1.x()).max(p2.x()),
+      p0.y().max(p1.y()).max(p2.y()),
+      p0.z().max(p1.z()).max(p2.z()),
+    );
+    // axis aligned triangles would give a box with no thickness
+    let pad = Vec3(1e-4, 1e-4, 1e-4);
+    Some(Aabb::new(min - pad, max + pad))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::material::Lambertian;
+  use super::super::sampler::IndependentSampler;
+  use super::super::texture::SolidColor;
+  use super::*;
+
+  fn quad() -> Arc<TriangleMesh> {
+    Arc::new(TriangleMesh::new(
+      vec![
+        Vec3(0.0, 0.0, 0.0),
+        Vec3(1.0, 0.0, 0.0),
+        Vec3(1.0, 1.0, 0.0),
+        Vec3(0.0, 1.0, 0.0),
+      ],
+      vec![[0, 1, 2], [0, 2, 3]],
+      Box::new(Lambertian::new(Vec3(0.3, 0.3, 0.3))),
+    ))
+  }
+
+  #[test]
+  fn triangle_hit() {
+    let triangles = TriangleMesh::triangles(quad());
+    let r = Ray::new(Vec3(0.75, 0.25, 2.0), Vec3(0.0, 0.0, -1.0));
+    let rec = triangles[0].hit(&r, 0.0001, f32::MAX).unwrap();
+    assert_eq!(2.0, rec.t);
+    assert_eq!(Vec3(0.75, 0.25, 0.0), rec.p);
+    assert_eq!(Vec3(0.0, 0.0, 1.0), rec.normal);
+    assert!(triangles[1].hit(&r, 0.0001, f32::MAX).is_none());
+    assert!(triangles[0].hit(&r, 0.0001, 1.0).is_none());
+  }
+
+  #[test]
+  fn triangle_not_hit() {
+    let triangles = TriangleMesh::triangles(quad());
+    let r = Ray::new(Vec3(1.5, 0.5, 2.0), Vec3(0.0, 0.0, -1.0));
+    assert!(triangles
+      .iter()
+      .all(|t| t.hit(&r, 0.0001, f32::MAX).is_none()));
+    let r = Ray::new(Vec3(0.5, 0.5, 2.0), Vec3(1.0, 0.0, 0.0));
+    assert!(triangles
+      .iter()
+      .all(|t| t.hit(&r, 0.0001, f32::MAX).is_none()));
+  }
+
+  #[test]
+  fn shared_edge_is_watertight() {
+    let triangles = TriangleMesh::triangles(quad());
+    // rays through the diagonal both triangles share, and through the shared vertex
+    for &(x, y) in &[(0.5, 0.5), (0.3, 0.3), (0.0, 0.0), (1.0, 1.0)] {
+      let r = Ray::new(Vec3(x, y, 1.0), Vec3(0.0, 0.0, -1.0));
+      assert!(
+        triangles
+          .iter()
+          .any(|t| t.hit(&r, 0.0001, f32::MAX).is_some()),
+        "ray through ({}, {}) slipped between triangles",
+        x,
+        y
+      );
+    }
+  }
+
+  #[test]
+  fn triangle_sampling() {
+    let triangles = TriangleMesh::triangles(quad());
+    let origin = Vec3(0.2, 0.7, 2.0);
+    let mut sampler = IndependentSampler::new(1);
+    for _ in 0..100 {
+      let direction = triangles[1].random(origin, 0.0, &mut sampler);
+      assert!(triangles[1]
+        .hit(&Ray::new(origin, direction), 0.001, f32::MAX)
+        .is_some());
+    }
+    // straight down onto the plane from distance 2: 4 / (1 * 0.5)
+    let pdf = triangles[1].pdf_value(Vec3(0.2, 0.7, 2.0), Vec3(0.0, 0.0, -1.0), 0.0);
+    assert!((8.0 - pdf).abs() < 1e-4);
+    assert_eq!(
+      0.0,
+      triangles[0].pdf_value(origin, Vec3(0.0, 0.0, -1.0), 0.0)
+    );
+  }
+
+  #[test]
+  fn interpolated_normals() {
+    let mut mesh = TriangleMesh::new(
+      vec![
+        Vec3(0.0, 0.0, 0.0),
+        Vec3(2.0, 0.0, 0.0),
+        Vec3(0.0, 2.0, 0.0),
+      ],
+      vec![[0, 1, 2]],
+      Box::new(Lambertian::new(Vec3(0.3, 0.3, 0.3))),
+    );
+    mesh.normals = vec![
+      Vec3(0.0, 0.0, 1.0),
+      Vec3(1.0, 0.0, 0.0),
+      Vec3(0.0, 0.0, 1.0),
+    ];
+    let triangle = Triangle::new(Arc::new(mesh), 0);
+    let r = Ray::new(Vec3(1.0, 0.0, 1.0), Vec3(0.0, 0.0, -1.0));
+    let rec = triangle.hit(&r, 0.0001, f32::MAX).unwrap();
+    assert_eq!(Vec3(0.5, 0.0, 0.5).to_unit_vector(), rec.normal);
+    assert_eq!(Vec3(0.0, 0.0, 1.0), rec.geometric_normal);
+    assert!(rec.front_face);
+    // from behind, both normals turn around
+    let r = Ray::new(Vec3(1.0, 0.0, -1.0), Vec3(0.0, 0.0, 1.0));
+    let rec = triangle.hit(&r, 0.0001, f32::MAX).unwrap();
+    assert_eq!(-Vec3(0.5, 0.0, 0.5).to_unit_vector(), rec.normal);
+    assert_eq!(Vec3(0.0, 0.0, -1.0), rec.geometric_normal);
+    assert!(!rec.front_face);
+  }
+
+  #[test]
+  fn tangents_follow_uvs() {
+    let mut mesh = Arc::try_unwrap(quad()).ok().unwrap();
+    mesh.uvs = vec![(0.0, 0.0), (0.0, 0.5), (0.5, 0.5), (0.5, 0.0)];
+    let triangles = TriangleMesh::triangles(Arc::new(mesh));
+    let r = Ray::new(Vec3(0.25, 0.75, 2.0), Vec3(0.0, 0.0, -1.0));
+    let rec = triangles[1].hit(&r, 0.0001, f32::MAX).unwrap();
+    assert_eq!((0.375, 0.125), (rec.u, rec.v));
+    assert_eq!(Vec3(0.0, 2.0, 0.0), rec.dpdu);
+    assert_eq!(Vec3(2.0, 0.0, 0.0), rec.dpdv);
+    assert_eq!(1, rec.primitive_id);
+  }
+
+  #[test]
+  fn displacement_subdivides_and_lifts() {
+    let mut mesh = TriangleMesh::new(
+      quad().positions.clone(),
+      vec![[0, 1, 2], [0, 2, 3]],
+      Box::new(Lambertian::new(Vec3(0.3, 0.3, 0.3))),
+    );
+    mesh.subdivide();
+    // the shared diagonal gets one midpoint
+    assert_eq!((9, 8), (mesh.positions.len(), mesh.indices.len()));
+    assert_eq!(Vec3(0.5, 0.5, 0.0), mesh.positions[mesh.indices[0][2]]);
+
+    let height = SolidColor::new(Vec3(1.0, 1.0, 1.0));
+    mesh.displace(&height, 0.5, 1);
+    assert_eq!((25, 32), (mesh.positions.len(), mesh.indices.len()));
+    assert!(mesh.positions.iter().all(|p| (p.z() - 0.5).abs() < 1e-6));
+    assert!(mesh.normals.iter().all(|n| *n == Vec3(0.0, 0.0, 1.0)));
+    let triangles = TriangleMesh::triangles(Arc::new(mesh));
+    let r = Ray::new(Vec3(0.3, 0.6, 2.0), Vec3(0.0, 0.0, -1.0));
+    let hit = triangles
+      .iter()
+      .find_map(|t| t.hit(&r, 0.0, f32::MAX))
+      .unwrap();
+    assert!((hit.t - 1.5).abs() < 1e-6);
+  }
+
+  /// Height rising with u, so displacement lifts vertices by different amounts.
+  struct Ramp;
+
+  impl Texture for Ramp {
+    fn value(&self, u: f32, _v: f32, _p: Vec3) -> Vec3 {
+      Vec3(u, u, u)
+    }
+  }
+
+  #[test]
+  fn displacement_follows_height_over_uvs() {
+    let mut mesh = TriangleMesh::new(
+      quad().positions.clone(),
+      vec![[0, 1, 2], [0, 2, 3]],
+      Box::new(Lambertian::new(Vec3(0.3, 0.3, 0.3))),
+    );
+    mesh.uvs = mesh.positions.iter().map(|p| (p.x(), p.y())).collect();
+    mesh.displace(&Ramp, 0.5, 2);
+    assert_eq!(25, mesh.positions.len());
+    // every vertex rises by half its u, which is its x
+    for p in &mesh.positions {
+      assert!((p.z() - 0.5 * p.x()).abs() < 1e-5, "{:?}", p);
+    }
+    let mut lifts: Vec<f32> = mesh.positions.iter().map(|p| p.z()).collect();
+    lifts.sort_by(f32::total_cmp);
+    lifts.dedup_by(|a, b| (*a - *b).abs() < 1e-5);
+    assert_eq!(5, lifts.len());
+    // the faces now slope up along x
+    let expected = Vec3(-0.5, 0.0, 1.0).to_unit_vector();
+    assert!(mesh.normals.iter().all(|n| (*n - expected).length() < 1e-5));
+  }
+}
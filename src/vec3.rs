@@ -1,9 +1,7 @@
-extern crate rand;
-use rand::Rng;
-
+use super::sampler::Sampler;
 use std::ops::*;
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Vec3(pub f32, pub f32, pub f32);
 
 impl Vec3 {
@@ -49,8 +47,8 @@ impl Vec3 {
     self.squared_length().sqrt()
   }
 
-  pub fn to_unit_vector(&self) -> Vec3 {
-    *self / self.length()
+  pub fn to_unit_vector(self) -> Vec3 {
+    self / self.length()
   }
 }
 
@@ -159,29 +157,36 @@ impl DivAssign<f32> for Vec3 {
   }
 }
 
-pub fn random_in_unit_sphere() -> Vec3 {
-  let mut rng = rand::thread_rng();
-  let unit = Vec3(1.0, 1.0, 1.0);
-  loop {
-    let p = 2.0 * Vec3(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>()) - unit;
-    if p.squared_length() < 1.0 {
-      return p;
-    }
-  }
+/// Uniformly distributed point inside the unit sphere.
+pub fn random_in_unit_sphere(sampler: &mut dyn Sampler) -> Vec3 {
+  let radius = sampler.next_1d().cbrt();
+  radius * random_unit_vector(sampler)
 }
 
-pub fn random_in_unit_disk() -> Vec3 {
-  let mut rng = rand::thread_rng();
-  loop {
-    let p = Vec3(
-      2.0 * rng.gen::<f32>() - 1.0,
-      2.0 * rng.gen::<f32>() - 1.0,
-      0.0,
-    );
-    if p.squared_length() < 1.0 {
-      return p;
-    }
-  }
+/// Uniformly distributed direction.
+pub fn random_unit_vector(sampler: &mut dyn Sampler) -> Vec3 {
+  let (u1, u2) = sampler.next_2d();
+  let z = 2.0 * u1 - 1.0;
+  let phi = 2.0 * std::f32::consts::PI * u2;
+  let r = (1.0 - z * z).max(0.0).sqrt();
+  Vec3(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Uniformly distributed point inside the unit disk in the xy plane, by
+/// Shirley and Chiu's concentric mapping of the square onto the disk.
+pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Vec3 {
+  let (u1, u2) = sampler.next_2d();
+  let (a, b) = (2.0 * u1 - 1.0, 2.0 * u2 - 1.0);
+  if a == 0.0 && b == 0.0 {
+    return Vec3(0.0, 0.0, 0.0);
+  }
+  let quarter_pi = std::f32::consts::FRAC_PI_4;
+  let (r, theta) = if a.abs() > b.abs() {
+    (a, quarter_pi * (b / a))
+  } else {
+    (b, 2.0 * quarter_pi - quarter_pi * (a / b))
+  };
+  Vec3(r * theta.cos(), r * theta.sin(), 0.0)
 }
 
 pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
@@ -229,7 +234,7 @@ mod tests {
   #[test]
   fn vec_length() {
     let v1 = Vec3(2.0, 3.0, 4.0);
-    assert_eq!((29.0 as f32).sqrt(), v1.length())
+    assert_eq!(29.0_f32.sqrt(), v1.length())
   }
 
   #[test]